@@ -0,0 +1,244 @@
+//! This module contains synchronous counterparts of the services, and is enabled with the `blocking` feature.
+//!
+//! Each service is wrapped in a `Blocking` struct, which drives the async service to completion on an internal
+//! runtime. This mirrors `reqwest::blocking`, and is intended for scripts and bindings which do not want to
+//! depend on an async runtime themselves.
+//!
+//! The blocking services must not be used from within an async runtime, as blocking on a future from inside
+//! another runtime will panic. Use the async services directly in that case.
+//!
+//! ```rust,no_run
+//! use ebiotic::blocking;
+//! use ebiotic::data::AccessionIds;
+//!
+//! let dbfetch = blocking::Dbfetch::default();
+//! let ids = AccessionIds::new(vec!["M10051".to_string()]);
+//! let records = dbfetch.run(ids).unwrap().into_records();
+//! ```
+
+use bio::io::fasta::Record;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::core::{
+    CancellationToken, EbioticClient, EbioticHttpClient, EbioticResult, JobHandle, JobService,
+    PollStatus, Service,
+};
+use crate::data::ebisearch::{
+    ebisearchdomains::EbiSearchDomains, ebisearchquery::EbiSearchFilters,
+};
+use crate::data::{self, AccessionIds};
+use crate::tools;
+
+/// A synchronous `Blast` service.
+pub type Blast<C = EbioticClient> = Blocking<tools::Blast<C>>;
+/// A synchronous `Clustalo` service.
+pub type Clustalo<C = EbioticClient> = Blocking<tools::Clustalo<C>>;
+/// A synchronous `Dbfetch` service.
+pub type Dbfetch<C = EbioticClient> = Blocking<data::Dbfetch<C>>;
+/// A synchronous `EbiSearch` service.
+pub type EbiSearch<C = EbioticClient> = Blocking<data::EbiSearch<C>>;
+
+/// The `Blocking` struct is used to run an async service synchronously.
+///
+/// All blocking services share a single runtime, so the connection pool of a shared `EbioticClient`
+/// stays valid between services.
+#[derive(Debug, Clone, Default)]
+pub struct Blocking<S> {
+    service: S,
+}
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("ebiotic-blocking")
+            .enable_all()
+            .build()
+            .expect("failed to build the ebiotic blocking runtime")
+    })
+}
+
+impl<S> Blocking<S> {
+    /// Wrap an async service.
+    pub fn new(service: S) -> Blocking<S> {
+        Blocking { service }
+    }
+
+    /// Get the wrapped service.
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+
+    /// Get the wrapped service mutably, e.g. to use its setters.
+    pub fn service_mut(&mut self) -> &mut S {
+        &mut self.service
+    }
+
+    /// Take the wrapped service. You own it!
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+impl<S> From<S> for Blocking<S> {
+    fn from(service: S) -> Self {
+        Blocking::new(service)
+    }
+}
+
+impl<S: Service> Blocking<S> {
+    /// Run the service, blocking the current thread until it completes.
+    pub fn run(&self, input: S::InputType) -> EbioticResult<S::ResultType> {
+        runtime().block_on(self.service.run(input))
+    }
+}
+
+impl<S: JobService + Sync> Blocking<S> {
+    /// Submit a job, returning its handle without waiting for it to finish.
+    pub fn submit(&self, input: S::InputType) -> EbioticResult<JobHandle> {
+        runtime().block_on(self.service.submit(input))
+    }
+
+    /// Request the current status of a job once.
+    pub fn status(&self, handle: &JobHandle) -> EbioticResult<PollStatus> {
+        runtime().block_on(handle.status(&self.service))
+    }
+
+    /// Block until a job has finished or failed.
+    pub fn wait(&self, handle: &JobHandle) -> EbioticResult<()> {
+        runtime().block_on(handle.wait(&self.service))
+    }
+
+    /// Block until a job has finished or failed, or `token` is cancelled from another thread.
+    pub fn wait_with_cancel(
+        &self,
+        handle: &JobHandle,
+        token: &CancellationToken,
+    ) -> EbioticResult<()> {
+        runtime().block_on(handle.wait_with_cancel(&self.service, token))
+    }
+
+    /// Run the service like `run`, stopping early if `token` is cancelled from another thread.
+    pub fn run_with_cancel(
+        &self,
+        input: S::InputType,
+        token: &CancellationToken,
+    ) -> EbioticResult<S::ResultType>
+    where
+        S::InputType: Send,
+    {
+        runtime().block_on(self.service.run_with_cancel(input, token))
+    }
+
+    /// Fetch the result of a finished job.
+    pub fn fetch_result(&self, handle: &JobHandle) -> EbioticResult<S::ResultType> {
+        runtime().block_on(handle.fetch_result(&self.service))
+    }
+
+    /// Ask the service to discard a job.
+    pub fn cancel(&self, handle: &JobHandle) -> EbioticResult<()> {
+        runtime().block_on(handle.cancel(&self.service))
+    }
+}
+
+impl<C: EbioticHttpClient> Blocking<tools::Blast<C>> {
+    /// Search several query sequences under a single RID, returning one result per record.
+    pub fn run_records(&self, records: Vec<Record>) -> EbioticResult<Vec<tools::BlastResult>> {
+        runtime().block_on(self.service.run_records(records))
+    }
+}
+
+impl<C: EbioticHttpClient> Blocking<data::EbiSearch<C>> {
+    /// Send a query to the EBI Search service under the self domain.
+    pub fn query(
+        &self,
+        query: String,
+        filters: Option<EbiSearchFilters>,
+    ) -> EbioticResult<data::ebisearch::EbiSearchResult> {
+        runtime().block_on(self.service.query(query, filters))
+    }
+
+    /// Send a cross-reference query to the EBI Search service under the self domain.
+    pub fn xref(
+        &self,
+        target_domain: Option<EbiSearchDomains>,
+        filters: Option<EbiSearchFilters>,
+    ) -> EbioticResult<data::ebisearch::EbiSearchResult> {
+        runtime().block_on(self.service.xref(target_domain, filters))
+    }
+
+    /// Send an auto-complete query to the EBI Search service under the self domain.
+    pub fn autocomplete(
+        &self,
+        term: String,
+        filters: Option<EbiSearchFilters>,
+    ) -> EbioticResult<data::ebisearch::EbiSearchResult> {
+        runtime().block_on(self.service.autocomplete(term, filters))
+    }
+
+    /// Return the specified entries from the EBI Search service under the self domain.
+    pub fn entries(
+        &self,
+        ids: AccessionIds,
+        filters: Option<EbiSearchFilters>,
+    ) -> EbioticResult<data::ebisearch::EbiSearchResult> {
+        runtime().block_on(self.service.entries(ids, filters))
+    }
+
+    /// Find more like this under the entries from the EBI Search service under the self domain.
+    pub fn more_like_this(
+        &self,
+        ids: AccessionIds,
+        target_domain: Option<EbiSearchDomains>,
+        filters: Option<EbiSearchFilters>,
+    ) -> EbioticResult<data::ebisearch::EbiSearchResult> {
+        runtime().block_on(self.service.more_like_this(ids, target_domain, filters))
+    }
+
+    /// Query the sequence analysis results from the EBI Search service under the self domain.
+    pub fn seq_tool_results(
+        &self,
+        tool_id: String,
+        job_id: String,
+        filters: Option<EbiSearchFilters>,
+    ) -> EbioticResult<data::ebisearch::EbiSearchResult> {
+        runtime().block_on(self.service.seq_tool_results(tool_id, job_id, filters))
+    }
+
+    /// Query the top fields from the EBI Search service under the self domain.
+    pub fn top_terms(
+        &self,
+        field_id: String,
+        filters: Option<EbiSearchFilters>,
+    ) -> EbioticResult<data::ebisearch::EbiSearchResult> {
+        runtime().block_on(self.service.top_terms(field_id, filters))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Fixture, Fixtures, HttpRequest, HttpResponse};
+    use crate::data::{DataReturnFormats, DbfetchDbs, DbfetchStyle};
+
+    #[test]
+    fn runs_services_without_an_async_context() {
+        let client = EbioticClient::replay(Fixtures::new(vec![Fixture::new(
+            HttpRequest::get("https://www.ebi.ac.uk/Tools/dbfetch/?db=ena_sequence&format=fasta&style=raw&id=M10051"),
+            HttpResponse::new(200, ">ENA|M10051|M10051.1 test\nACGT\n".to_string()),
+        )]));
+        let dbfetch = Dbfetch::new(data::Dbfetch::new(
+            client,
+            DbfetchDbs::EnaSequence,
+            DataReturnFormats::Fasta,
+            DbfetchStyle::Raw,
+        ));
+
+        let ids = AccessionIds::new(vec!["M10051".to_string()]);
+        let records = dbfetch.run(ids).unwrap().into_records().unwrap();
+
+        assert_eq!(records[0].id(), "ENA|M10051|M10051.1");
+    }
+}
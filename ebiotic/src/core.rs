@@ -3,26 +3,96 @@ use bio::io::fasta::{Reader, Record};
 use std::future::Future;
 use std::io::Cursor;
 
+mod cache;
+mod events;
+mod job;
 mod network;
+mod poll;
+mod ratelimit;
 mod reqwest;
+mod retry;
+mod transport;
 
-pub use self::network::EbioticHttpClient;
+pub use self::cache::{CacheMode, CacheStats, ResponseCache};
+pub use self::events::{JobEvent, JobObserver, JobState};
+pub(crate) use self::job::{report_fetch, run_job};
+pub use self::job::{JobHandle, JobService};
+pub use self::network::{BoxFuture, BoxedHttpClient, DynEbioticHttpClient, EbioticHttpClient};
+pub use self::poll::PollPolicy;
+pub(crate) use self::poll::PollSchedule;
+pub use self::ratelimit::{RateLimit, RateLimiter};
+pub use self::retry::{RetryPolicy, RetryableStatus};
+pub use self::transport::{Fixture, Fixtures, HttpMethod, HttpRequest, HttpResponse};
+pub use tokio_util::sync::CancellationToken;
 // This will allow us in the future to optionally compile with different clients
 // but maintain the same interface internally
 pub use self::reqwest::EbioticReqwestClient as EbioticClient;
 
 pub type EbioticResult<T> = Result<T, EbioticError>;
 
-pub(crate) enum PollStatus {
+// The data endpoints are defined here, rather than in `data`, as the `ResponseCache` caches them by default.
+pub const EBI_DBFETCH_ENDPOINT: &str = "https://www.ebi.ac.uk/Tools/dbfetch/";
+pub const EBI_SEARCH_ENDPOINT: &str = "https://www.ebi.ac.uk/ebisearch/ws/rest/";
+
+/// The `PollStatus` enum is used to report the state of a long running job on a remote service.
+pub enum PollStatus {
+    /// The job has finished and its results can be fetched.
     Finished,
-    Running(u64),
+    /// The job is queued or running, poll again according to the `PollPolicy`.
+    Running,
+    /// The job failed, or its status could not be determined.
     Error(EbioticError),
 }
 
-pub(crate) trait PollableService {
+/// The `PollableService` trait is used to interpret the status responses of a long running job.
+pub trait PollableService {
     fn poll_status(&self, response: &str) -> PollStatus;
 }
 
+/// The `ErrorDecoder` trait is used by services to recognise their own error formats in response bodies,
+/// which are often returned with a `200` status or as the body of a non-2xx response.
+pub(crate) trait ErrorDecoder {
+    fn decode_error(&self, body: &str) -> Option<EbioticError>;
+}
+
+/// Replace a successful response containing a recognised error body, or an `HttpStatus` error whose body
+/// can be decoded, with the service-specific error.
+pub(crate) fn decode_response<D>(
+    decoder: &D,
+    response: EbioticResult<String>,
+) -> EbioticResult<String>
+where
+    D: ErrorDecoder,
+{
+    match response {
+        Ok(body) => match decoder.decode_error(&body) {
+            Some(err) => Err(err),
+            None => Ok(body),
+        },
+        Err(EbioticError::HttpStatus { status, url, body }) => match decoder.decode_error(&body) {
+            Some(err) => {
+                log::debug!("Request to {} failed with status {}", url, status);
+                Err(err)
+            }
+            None => Err(EbioticError::HttpStatus { status, url, body }),
+        },
+        Err(err) => Err(err),
+    }
+}
+
+/// Truncate a response body to a length suitable for an error message.
+pub(crate) fn body_excerpt(body: &str) -> String {
+    const MAX_LEN: usize = 1024;
+    if body.len() <= MAX_LEN {
+        return body.to_string();
+    }
+    let mut end = MAX_LEN;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &body[..end])
+}
+
 pub trait Service {
     type ResultType;
     type InputType;
@@ -0,0 +1,404 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::transport::{HttpRequest, HttpResponse};
+use crate::core::{EbioticResult, EBI_DBFETCH_ENDPOINT, EBI_SEARCH_ENDPOINT};
+use crate::errors::EbioticError;
+
+/// The `CacheMode` enum is used to specify how a `ResponseCache` is used.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CacheMode {
+    /// Serve fresh entries from the cache, and store responses fetched from the network.
+    ReadWrite,
+    /// Never use the network, serving entries even once they have expired. Requests which cannot be served
+    /// from the cache fail with `EbioticError::CacheMiss`.
+    CacheOnly,
+}
+
+/// The `CacheStats` struct is a snapshot of the hits and misses of a `ResponseCache`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    hits: u64,
+    misses: u64,
+    stores: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stores: AtomicU64,
+}
+
+/// The `CacheEntry` struct is the on-disk representation of a cached response.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    stored_at: u64,
+    request: HttpRequest,
+    response: HttpResponse,
+}
+
+/// The `ResponseCache` struct is used to store successful responses on disk, so that repeated requests
+/// for the same data are served without using the network.
+///
+/// Entries are content-addressed on the method, URL and form body of the request. Only requests whose
+/// URL starts with a cacheable prefix are cached, each prefix having its own time to live. By default
+/// these are the Dbfetch (7 days) and EBI Search (1 day) services. Job submissions and status polls
+/// must never be cached, so prefixes should be added with care. Services evict responses they reject,
+/// such as the errors Dbfetch returns with a `200` status, so they are not served again.
+///
+/// The statistics are shared between clones, so cloning an `EbioticClient` keeps a single count.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    mode: CacheMode,
+    prefixes: Vec<(String, Duration)>,
+    counters: Arc<Counters>,
+}
+
+impl CacheStats {
+    pub fn hits(&self) -> &u64 {
+        &self.hits
+    }
+
+    pub fn misses(&self) -> &u64 {
+        &self.misses
+    }
+
+    pub fn stores(&self) -> &u64 {
+        &self.stores
+    }
+
+    /// The fraction of lookups served from the cache, or `0.0` if there have been none.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+impl ResponseCache {
+    /// Create a cache storing its entries in `dir`, which is created if it does not exist.
+    pub fn new<P: AsRef<Path>>(dir: P) -> EbioticResult<ResponseCache> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(ResponseCache {
+            dir: dir.as_ref().to_path_buf(),
+            mode: CacheMode::ReadWrite,
+            prefixes: vec![
+                (
+                    EBI_DBFETCH_ENDPOINT.to_string(),
+                    Duration::from_secs(7 * 24 * 60 * 60),
+                ),
+                (
+                    EBI_SEARCH_ENDPOINT.to_string(),
+                    Duration::from_secs(24 * 60 * 60),
+                ),
+            ],
+            counters: Arc::new(Counters::default()),
+        })
+    }
+
+    pub fn set_mode(&mut self, mode: CacheMode) {
+        self.mode = mode;
+    }
+
+    /// Cache requests whose URL starts with `prefix` for `ttl`, replacing the time to live of an existing prefix.
+    pub fn set_prefix_ttl(&mut self, prefix: &str, ttl: Duration) {
+        match self.prefixes.iter_mut().find(|(p, _)| p == prefix) {
+            Some((_, existing)) => *existing = ttl,
+            None => self.prefixes.push((prefix.to_string(), ttl)),
+        }
+    }
+
+    /// Stop caching requests whose URL starts with `prefix`.
+    pub fn remove_prefix(&mut self, prefix: &str) {
+        self.prefixes.retain(|(p, _)| p != prefix);
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    pub fn mode(&self) -> &CacheMode {
+        &self.mode
+    }
+
+    pub fn prefixes(&self) -> &Vec<(String, Duration)> {
+        &self.prefixes
+    }
+
+    /// The time to live of responses to `url`, or `None` if they are not cached.
+    pub fn ttl(&self, url: &str) -> Option<&Duration> {
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, ttl)| ttl)
+    }
+
+    /// Get a snapshot of the cache statistics.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            stores: self.counters.stores.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Remove every entry from the cache directory.
+    pub fn clear(&self) -> EbioticResult<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ResponseCache {
+    /// Look up a fresh response to `request`, or any stored response in `CacheMode::CacheOnly`.
+    ///
+    /// Returns `Ok(None)` if the request should be sent, or `EbioticError::CacheMiss` in `CacheMode::CacheOnly`.
+    pub(crate) fn lookup(&self, request: &HttpRequest) -> EbioticResult<Option<HttpResponse>> {
+        let ttl = match self.ttl(request.url()) {
+            Some(ttl) => *ttl,
+            None => return self.miss(request, false),
+        };
+
+        let entry = fs::read_to_string(self.path_for(request))
+            .ok()
+            .and_then(|json| serde_json::from_str::<CacheEntry>(&json).ok())
+            .filter(|entry| entry.request.matches(request));
+
+        match entry {
+            Some(entry) => {
+                let fresh = now_secs().saturating_sub(entry.stored_at) < ttl.as_secs();
+                if !fresh && self.mode != CacheMode::CacheOnly {
+                    return self.miss(request, true);
+                }
+                if fresh {
+                    log::debug!("Serving {} from the cache", request.url());
+                } else {
+                    log::debug!("Serving expired entry for {} from the cache", request.url());
+                }
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                Ok(Some(entry.response))
+            }
+            None => self.miss(request, true),
+        }
+    }
+
+    /// Remove the stored response to `request`, if there is one.
+    pub(crate) fn evict(&self, request: &HttpRequest) -> EbioticResult<()> {
+        match fs::remove_file(self.path_for(request)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Store a successful response to a cacheable request.
+    pub(crate) fn store(
+        &self,
+        request: &HttpRequest,
+        response: &HttpResponse,
+    ) -> EbioticResult<()> {
+        if !response.is_success() || self.ttl(request.url()).is_none() {
+            return Ok(());
+        }
+
+        let entry = CacheEntry {
+            stored_at: now_secs(),
+            request: request.clone(),
+            response: response.clone(),
+        };
+        // Write to a temporary file first, so that concurrent readers never see a partial entry.
+        let path = self.path_for(request);
+        let tmp = path.with_extension(format!("{}.tmp", fastrand::u32(..)));
+        fs::write(&tmp, serde_json::to_string(&entry)?)?;
+        fs::rename(tmp, path)?;
+        self.counters.stores.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn miss(&self, request: &HttpRequest, counted: bool) -> EbioticResult<Option<HttpResponse>> {
+        if counted {
+            self.counters.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        match self.mode {
+            CacheMode::ReadWrite => Ok(None),
+            CacheMode::CacheOnly => Err(EbioticError::CacheMiss(request.url().to_string())),
+        }
+    }
+
+    fn path_for(&self, request: &HttpRequest) -> PathBuf {
+        self.dir.join(format!("{}.json", cache_key(request)))
+    }
+}
+
+/// Hash the method, URL and form body of a request. Form fields are sorted, matching how fixtures are matched.
+fn cache_key(request: &HttpRequest) -> String {
+    let mut form = request.form().clone();
+    form.sort();
+
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{:?}\n{}\n", request.method(), request.url()));
+    for (key, value) in &form {
+        hasher.update(format!("{}={}\n", key, value));
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .fold(String::with_capacity(64), |mut key, byte| {
+            let _ = write!(key, "{:02x}", byte);
+            key
+        })
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(name: &str) -> ResponseCache {
+        let dir = std::env::temp_dir().join(format!(
+            "ebiotic-cache-{}-{}-{}",
+            name,
+            std::process::id(),
+            fastrand::u32(..)
+        ));
+        ResponseCache::new(dir).unwrap()
+    }
+
+    #[test]
+    fn stores_and_serves_cacheable_responses() {
+        let cache = temp_cache("store");
+        let request = HttpRequest::get("https://www.ebi.ac.uk/Tools/dbfetch/?id=M10051");
+
+        assert!(cache.lookup(&request).unwrap().is_none());
+        cache
+            .store(
+                &request,
+                &HttpResponse::new(200, ">M10051\nACGT".to_string()),
+            )
+            .unwrap();
+        let cached = cache.lookup(&request).unwrap().unwrap();
+
+        assert_eq!(cached.body(), ">M10051\nACGT");
+        assert_eq!(*cache.stats().hits(), 1);
+        assert_eq!(*cache.stats().misses(), 1);
+        assert_eq!(*cache.stats().stores(), 1);
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn ignores_uncacheable_and_failed_responses() {
+        let cache = temp_cache("ignore");
+        let blast = HttpRequest::post(
+            "https://blast.ncbi.nlm.nih.gov/Blast.cgi",
+            &[("CMD", "Get")],
+        );
+        let search = HttpRequest::get("https://www.ebi.ac.uk/ebisearch/ws/rest/uniprot?query=");
+
+        cache
+            .store(
+                &blast,
+                &HttpResponse::new(200, "Status=WAITING".to_string()),
+            )
+            .unwrap();
+        cache
+            .store(&search, &HttpResponse::new(400, "".to_string()))
+            .unwrap();
+
+        assert!(cache.lookup(&blast).unwrap().is_none());
+        assert!(cache.lookup(&search).unwrap().is_none());
+        assert_eq!(*cache.stats().stores(), 0);
+    }
+
+    #[test]
+    fn expired_entries_are_misses() {
+        let mut cache = temp_cache("expire");
+        cache.set_prefix_ttl(EBI_DBFETCH_ENDPOINT, Duration::ZERO);
+        let request = HttpRequest::get("https://www.ebi.ac.uk/Tools/dbfetch/?id=M10051");
+
+        cache
+            .store(&request, &HttpResponse::new(200, "".to_string()))
+            .unwrap();
+
+        assert!(cache.lookup(&request).unwrap().is_none());
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn cache_only_mode_serves_expired_entries() {
+        let mut cache = temp_cache("stale");
+        cache.set_prefix_ttl(EBI_DBFETCH_ENDPOINT, Duration::ZERO);
+        let request = HttpRequest::get("https://www.ebi.ac.uk/Tools/dbfetch/?id=M10051");
+        cache
+            .store(
+                &request,
+                &HttpResponse::new(200, ">M10051\nACGT".to_string()),
+            )
+            .unwrap();
+
+        cache.set_mode(CacheMode::CacheOnly);
+        let cached = cache.lookup(&request).unwrap().unwrap();
+
+        assert_eq!(cached.body(), ">M10051\nACGT");
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn evicted_entries_are_misses() {
+        let cache = temp_cache("evict");
+        let request = HttpRequest::get("https://www.ebi.ac.uk/Tools/dbfetch/?id=M10051");
+        cache
+            .store(&request, &HttpResponse::new(200, "ERROR 12".to_string()))
+            .unwrap();
+
+        cache.evict(&request).unwrap();
+        cache.evict(&request).unwrap();
+
+        assert!(cache.lookup(&request).unwrap().is_none());
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn cache_only_mode_fails_on_miss() {
+        let mut cache = temp_cache("offline");
+        cache.set_mode(CacheMode::CacheOnly);
+        let request = HttpRequest::get("https://www.ebi.ac.uk/Tools/dbfetch/?id=M10051");
+
+        assert!(matches!(
+            cache.lookup(&request),
+            Err(EbioticError::CacheMiss(_))
+        ));
+    }
+
+    #[test]
+    fn form_order_does_not_change_the_key() {
+        let a = HttpRequest::post("http://example.org", &[("a", "1"), ("b", "2")]);
+        let b = HttpRequest::post("http://example.org", &[("b", "2"), ("a", "1")]);
+        let c = HttpRequest::post("http://example.org", &[("a", "2"), ("b", "1")]);
+
+        assert_eq!(cache_key(&a), cache_key(&b));
+        assert_ne!(cache_key(&a), cache_key(&c));
+    }
+}
@@ -0,0 +1,57 @@
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::Duration;
+
+/// The `JobState` enum is used to report the state of a job when it is polled.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum JobState {
+    Running,
+    Finished,
+    Failed,
+}
+
+/// The `JobEvent` enum lists the lifecycle events of a job run by a `JobService`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobEvent {
+    /// The job was accepted by the service.
+    Submitted { job_id: String },
+    /// The status of the job was requested, `elapsed` since waiting began.
+    Polled {
+        job_id: String,
+        status: JobState,
+        elapsed: Duration,
+    },
+    /// A result artifact (e.g. `"aln-clustal_num"` or `"JSON2_S"`) is being downloaded.
+    Fetching { job_id: String, artifact: String },
+    /// The results of the job were fetched and parsed, reported by `fetch_result` however it was called.
+    Completed { job_id: String },
+    /// The job failed while waiting for it or fetching its results. A failed submission is only reported by
+    /// `run` and `run_with_cancel`, with a `job_id` of `None`.
+    Failed {
+        job_id: Option<String>,
+        error: String,
+    },
+}
+
+/// The `JobObserver` trait is used to receive the `JobEvent`s of the jobs run by a service.
+///
+/// It is implemented for closures taking a `&JobEvent`, and for `UnboundedSender<JobEvent>` so the
+/// events can be consumed as a stream.
+pub trait JobObserver: Send + Sync {
+    fn on_event(&self, event: &JobEvent);
+}
+
+impl<F> JobObserver for F
+where
+    F: Fn(&JobEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &JobEvent) {
+        self(event)
+    }
+}
+
+impl JobObserver for UnboundedSender<JobEvent> {
+    fn on_event(&self, event: &JobEvent) {
+        // The receiver may have been dropped, in which case nobody is listening.
+        let _ = self.send(event.clone());
+    }
+}
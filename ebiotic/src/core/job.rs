@@ -0,0 +1,403 @@
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use tokio::time::{self, Duration};
+use tokio_util::sync::CancellationToken;
+
+use crate::core::{
+    EbioticResult, JobEvent, JobState, PollPolicy, PollSchedule, PollStatus, Service,
+};
+use crate::errors::EbioticError;
+
+/// The `JobHandle` struct identifies a job submitted to a long running service.
+///
+/// A handle holds everything needed to find the job again, so it can be serialised to JSON, stored, and used
+/// to resume the job after a restart instead of resubmitting it.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct JobHandle {
+    service: String,
+    job_id: String,
+    endpoint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimated_seconds: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    query_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output_format: Option<String>,
+}
+
+/// The `JobService` trait is used by services whose jobs run remotely, splitting `Service::run` into
+/// submitting the job, checking on it, and fetching its result.
+///
+/// `run` is equivalent to `submit`, `wait` and `fetch_result` in sequence.
+pub trait JobService: Service {
+    /// The name recorded in the handles of this service, used to check a handle belongs to it.
+    fn service_name(&self) -> &'static str;
+
+    /// Submit a job, returning its handle without waiting for it to finish.
+    fn submit(
+        &self,
+        input: Self::InputType,
+    ) -> impl Future<Output = EbioticResult<JobHandle>> + Send;
+
+    /// Request the current status of a job once.
+    ///
+    /// Transport errors are returned as `Err`, while a job that has failed remotely is reported as `PollStatus::Error`.
+    fn status(&self, handle: &JobHandle) -> impl Future<Output = EbioticResult<PollStatus>> + Send;
+
+    /// Fetch the result of a finished job, reporting a `JobEvent::Completed` or `JobEvent::Failed`.
+    fn fetch_result(
+        &self,
+        handle: &JobHandle,
+    ) -> impl Future<Output = EbioticResult<Self::ResultType>> + Send;
+
+    /// Ask the service to discard a job. Services which cannot cancel jobs return `EbioticError::CancelNotSupported`.
+    fn cancel(&self, handle: &JobHandle) -> impl Future<Output = EbioticResult<()>> + Send;
+
+    /// The policy used by `wait` to poll the status of a job.
+    fn poll_policy(&self) -> &PollPolicy;
+
+    /// Poll the status of a job until it has finished or failed, following the `poll_policy`.
+    fn wait(&self, handle: &JobHandle) -> impl Future<Output = EbioticResult<()>> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            self.wait_with_cancel(handle, &CancellationToken::new())
+                .await
+        }
+    }
+
+    /// Poll the status of a job like `wait`, stopping early if `token` is cancelled.
+    ///
+    /// On cancellation the service is asked to discard the job, where it supports it, and
+    /// `EbioticError::Cancelled` is returned. Any failure is also reported as a `JobEvent::Failed`.
+    fn wait_with_cancel(
+        &self,
+        handle: &JobHandle,
+        token: &CancellationToken,
+    ) -> impl Future<Output = EbioticResult<()>> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            let result = wait_until_done(self, handle, token).await;
+            if let Err(err) = &result {
+                self.notify(&JobEvent::Failed {
+                    job_id: Some(handle.job_id.clone()),
+                    error: err.to_string(),
+                });
+            }
+            result
+        }
+    }
+
+    /// Submit, wait for and fetch a job like `run`, stopping early if `token` is cancelled.
+    fn run_with_cancel(
+        &self,
+        input: Self::InputType,
+        token: &CancellationToken,
+    ) -> impl Future<Output = EbioticResult<Self::ResultType>> + Send
+    where
+        Self: Sync,
+        Self::InputType: Send,
+    {
+        async move {
+            run_job(self, self.submit(input), token, |handle| async move {
+                self.fetch_result(&handle).await
+            })
+            .await
+        }
+    }
+
+    /// Report a lifecycle event to the observer of the service. Services without observers ignore events.
+    fn notify(&self, _event: &JobEvent) {}
+}
+
+/// Drive a job through its lifecycle: await its submission, wait for it to finish, then fetch its result with
+/// `fetch`. A failed submission is reported here, while waiting and fetching report their own outcome.
+///
+/// This is shared by `JobService::run_with_cancel` and the services' own multi-step runs, which only differ in how
+/// they submit the job and fetch its result.
+pub(crate) async fn run_job<S, T, Sub, F, Fut>(
+    service: &S,
+    submit: Sub,
+    token: &CancellationToken,
+    fetch: F,
+) -> EbioticResult<T>
+where
+    S: JobService + Sync + ?Sized,
+    Sub: Future<Output = EbioticResult<JobHandle>>,
+    F: FnOnce(JobHandle) -> Fut,
+    Fut: Future<Output = EbioticResult<T>>,
+{
+    let handle = match submit.await {
+        Ok(handle) => handle,
+        Err(err) => {
+            service.notify(&JobEvent::Failed {
+                job_id: None,
+                error: err.to_string(),
+            });
+            return Err(err);
+        }
+    };
+    service.wait_with_cancel(&handle, token).await?;
+    fetch(handle).await
+}
+
+/// Report the outcome of fetching the result of a job, the last step of its lifecycle, as a `Completed`
+/// or `Failed` event. Every `JobService::fetch_result` passes its result through this.
+pub(crate) fn report_fetch<S: JobService + ?Sized, T>(
+    service: &S,
+    handle: &JobHandle,
+    result: EbioticResult<T>,
+) -> EbioticResult<T> {
+    match &result {
+        Ok(_) => service.notify(&JobEvent::Completed {
+            job_id: handle.job_id.clone(),
+        }),
+        Err(err) => service.notify(&JobEvent::Failed {
+            job_id: Some(handle.job_id.clone()),
+            error: err.to_string(),
+        }),
+    }
+    result
+}
+
+/// Poll the status of a job until it has finished or failed, or `token` is cancelled, following the `poll_policy`
+/// of the service.
+async fn wait_until_done<S: JobService + ?Sized>(
+    service: &S,
+    handle: &JobHandle,
+    token: &CancellationToken,
+) -> EbioticResult<()> {
+    let estimate = handle.estimated_seconds.map(Duration::from_secs);
+    let mut schedule = PollSchedule::new(service.poll_policy(), estimate);
+    loop {
+        let poll = poll_once(service, handle, schedule.polls() + 1, schedule.elapsed());
+        let status = tokio::select! {
+            biased;
+            _ = token.cancelled() => return discard(service, handle).await,
+            status = poll => status?,
+        };
+        let state = match status {
+            PollStatus::Finished => JobState::Finished,
+            PollStatus::Running => JobState::Running,
+            PollStatus::Error(_) => JobState::Failed,
+        };
+        service.notify(&JobEvent::Polled {
+            job_id: handle.job_id.clone(),
+            status: state,
+            elapsed: schedule.elapsed(),
+        });
+        match status {
+            PollStatus::Finished => return Ok(()),
+            PollStatus::Running => {
+                let wait = schedule.next_wait(&handle.job_id)?;
+                log::info!(
+                    "Job {} is still running, sleeping for {:?}",
+                    handle.job_id,
+                    wait
+                );
+                tokio::select! {
+                    biased;
+                    _ = token.cancelled() => return discard(service, handle).await,
+                    _ = time::sleep(wait) => {}
+                }
+            }
+            PollStatus::Error(err) => return Err(err),
+        }
+    }
+}
+
+/// Request the status of a job, recorded as a `poll` span when the `tracing` feature is enabled.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "poll",
+        skip_all,
+        fields(
+            job_id = %handle.job_id,
+            poll = poll,
+            elapsed_ms = elapsed.as_millis() as u64,
+            status = tracing::field::Empty,
+        )
+    )
+)]
+async fn poll_once<S: JobService + ?Sized>(
+    service: &S,
+    handle: &JobHandle,
+    poll: u32,
+    elapsed: Duration,
+) -> EbioticResult<PollStatus> {
+    log::debug!(
+        "Polling job {} (poll {}, {:?} elapsed)",
+        handle.job_id,
+        poll,
+        elapsed
+    );
+    let status = service.status(handle).await;
+
+    #[cfg(feature = "tracing")]
+    if let Ok(status) = &status {
+        let state = match status {
+            PollStatus::Finished => "finished",
+            PollStatus::Running => "running",
+            PollStatus::Error(_) => "failed",
+        };
+        tracing::Span::current().record("status", state);
+    }
+
+    status
+}
+
+/// Cancel a job whose caller has given up on it, returning `EbioticError::Cancelled` unless the request
+/// to the service itself failed.
+async fn discard<S: JobService + ?Sized>(service: &S, handle: &JobHandle) -> EbioticResult<()> {
+    log::info!("Job {} was cancelled", handle.job_id);
+    match service.cancel(handle).await {
+        Ok(()) | Err(EbioticError::CancelNotSupported(_)) => {
+            Err(EbioticError::Cancelled(handle.job_id.clone()))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+impl JobHandle {
+    pub fn new(
+        service: String,
+        job_id: String,
+        endpoint: String,
+        estimated_seconds: Option<u64>,
+    ) -> JobHandle {
+        JobHandle {
+            service,
+            job_id,
+            endpoint,
+            estimated_seconds,
+            query_ids: Vec::new(),
+            output_format: None,
+        }
+    }
+
+    /// Record the ids of the query sequences submitted with the job, in the order they were submitted.
+    pub fn set_query_ids(&mut self, query_ids: Vec<String>) {
+        self.query_ids = query_ids;
+    }
+
+    /// Record the format the result was requested in, so that it is fetched in the same format when resumed.
+    pub fn set_output_format(&mut self, output_format: Option<String>) {
+        self.output_format = output_format;
+    }
+
+    /// Parse a handle previously serialised with `to_json`.
+    pub fn from_json(json: &str) -> EbioticResult<JobHandle> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serialise the handle to JSON, so the job can be resumed later.
+    pub fn to_json(&self) -> EbioticResult<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn service(&self) -> &String {
+        &self.service
+    }
+
+    pub fn job_id(&self) -> &String {
+        &self.job_id
+    }
+
+    pub fn endpoint(&self) -> &String {
+        &self.endpoint
+    }
+
+    /// The service's own estimate of how long the job will take, if it gave one (e.g. the BLAST RTOE).
+    pub fn estimated_seconds(&self) -> &Option<u64> {
+        &self.estimated_seconds
+    }
+
+    /// The ids of the query sequences submitted with the job, if the service records them (e.g. a multi-query BLAST search).
+    pub fn query_ids(&self) -> &Vec<String> {
+        &self.query_ids
+    }
+
+    /// The format the result was requested in, if the service records it (e.g. the Clustal Omega `outfmt`).
+    pub fn output_format(&self) -> &Option<String> {
+        &self.output_format
+    }
+
+    /// Request the current status of the job from `service`.
+    pub async fn status<S: JobService>(&self, service: &S) -> EbioticResult<PollStatus> {
+        self.check_service(service)?;
+        service.status(self).await
+    }
+
+    /// Poll `service` until the job has finished or failed.
+    pub async fn wait<S: JobService + Sync>(&self, service: &S) -> EbioticResult<()> {
+        self.check_service(service)?;
+        service.wait(self).await
+    }
+
+    /// Poll `service` until the job has finished or failed, or `token` is cancelled.
+    pub async fn wait_with_cancel<S: JobService + Sync>(
+        &self,
+        service: &S,
+        token: &CancellationToken,
+    ) -> EbioticResult<()> {
+        self.check_service(service)?;
+        service.wait_with_cancel(self, token).await
+    }
+
+    /// Fetch the result of the finished job from `service`.
+    pub async fn fetch_result<S: JobService>(&self, service: &S) -> EbioticResult<S::ResultType> {
+        self.check_service(service)?;
+        service.fetch_result(self).await
+    }
+
+    /// Ask `service` to discard the job.
+    pub async fn cancel<S: JobService>(&self, service: &S) -> EbioticResult<()> {
+        self.check_service(service)?;
+        service.cancel(self).await
+    }
+
+    fn check_service<S: JobService>(&self, service: &S) -> EbioticResult<()> {
+        if self.service != service.service_name() {
+            return Err(EbioticError::InvalidJobHandle(format!(
+                "Job {} belongs to {}, not {}",
+                self.job_id,
+                self.service,
+                service.service_name()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_handle_round_trips_through_json() {
+        let handle = JobHandle::new(
+            "blast".to_string(),
+            "9T2SHSH1016".to_string(),
+            "https://blast.ncbi.nlm.nih.gov/Blast.cgi".to_string(),
+            Some(20),
+        );
+
+        let json = handle.to_json().unwrap();
+
+        assert_eq!(JobHandle::from_json(&json).unwrap(), handle);
+    }
+
+    #[test]
+    fn estimated_seconds_is_optional() {
+        let handle = JobHandle::from_json(
+            r#"{"service":"clustalo","job_id":"clustalo-R1","endpoint":"https://www.ebi.ac.uk/Tools/services/rest/clustalo/"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(handle.estimated_seconds(), &None);
+    }
+}
@@ -1,22 +1,106 @@
-use crate::core::PollableService;
-use crate::errors::EbioticError;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::core::{EbioticClient, EbioticResult, HttpRequest};
+
+/// A boxed, `Send` future as returned by `DynEbioticHttpClient`.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
 
 /// The `EbioticHttpClient` trait is used to specify the HTTP functionality for a given client.
-pub trait EbioticHttpClient: Default + Send + Clone {
-    async fn post_form(
+///
+/// Every service is generic over this trait, so an instrumented client, a caching wrapper or a test double
+/// can be used in place of the default `EbioticClient`. Polling is driven by `JobService::wait`, on top of these requests.
+pub trait EbioticHttpClient: Send + Sync + Clone {
+    fn post_form(
         &self,
         endpoint: &str,
         body: &[(&str, &str)],
-    ) -> Result<String, EbioticError>;
+    ) -> impl Future<Output = EbioticResult<String>> + Send;
 
-    async fn get(&self, endpoint: &str) -> Result<String, EbioticError>;
+    fn get(&self, endpoint: &str) -> impl Future<Output = EbioticResult<String>> + Send;
 
-    async fn poll<F>(
-        &self,
-        endpoint: &str,
-        post_body: Option<&[(&str, &str)]>,
-        method_caller: &F,
-    ) -> Result<String, EbioticError>
+    /// Forget the response to `request`, which a service has rejected (e.g. an error body returned with a `200`
+    /// status), so that a caching client does not serve it again. Clients without a cache ignore this.
+    fn evict(&self, _request: &HttpRequest) {}
+}
+
+/// The `DynEbioticHttpClient` trait is an object safe counterpart of `EbioticHttpClient`, implemented for every
+/// `EbioticHttpClient`. It is used by `BoxedHttpClient` to erase the type of the underlying client.
+pub trait DynEbioticHttpClient: Send + Sync {
+    fn post_form_boxed<'a>(
+        &'a self,
+        endpoint: &'a str,
+        body: &'a [(&'a str, &'a str)],
+    ) -> BoxFuture<'a, EbioticResult<String>>;
+
+    fn get_boxed<'a>(&'a self, endpoint: &'a str) -> BoxFuture<'a, EbioticResult<String>>;
+
+    fn evict_boxed(&self, request: &HttpRequest);
+}
+
+impl<C> DynEbioticHttpClient for C
+where
+    C: EbioticHttpClient,
+{
+    fn post_form_boxed<'a>(
+        &'a self,
+        endpoint: &'a str,
+        body: &'a [(&'a str, &'a str)],
+    ) -> BoxFuture<'a, EbioticResult<String>> {
+        Box::pin(self.post_form(endpoint, body))
+    }
+
+    fn get_boxed<'a>(&'a self, endpoint: &'a str) -> BoxFuture<'a, EbioticResult<String>> {
+        Box::pin(self.get(endpoint))
+    }
+
+    fn evict_boxed(&self, request: &HttpRequest) {
+        self.evict(request)
+    }
+}
+
+/// The `BoxedHttpClient` struct wraps any `EbioticHttpClient` behind a trait object, so that services
+/// of different types can share one client without being generic over the same concrete type.
+#[derive(Clone)]
+pub struct BoxedHttpClient {
+    inner: Arc<dyn DynEbioticHttpClient>,
+}
+
+impl BoxedHttpClient {
+    pub fn new<C>(client: C) -> BoxedHttpClient
     where
-        F: PollableService;
+        C: EbioticHttpClient + 'static,
+    {
+        BoxedHttpClient {
+            inner: Arc::new(client),
+        }
+    }
+}
+
+impl Default for BoxedHttpClient {
+    fn default() -> Self {
+        BoxedHttpClient::new(EbioticClient::default())
+    }
+}
+
+impl Debug for BoxedHttpClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoxedHttpClient").finish_non_exhaustive()
+    }
+}
+
+impl EbioticHttpClient for BoxedHttpClient {
+    async fn post_form(&self, endpoint: &str, body: &[(&str, &str)]) -> EbioticResult<String> {
+        self.inner.post_form_boxed(endpoint, body).await
+    }
+
+    async fn get(&self, endpoint: &str) -> EbioticResult<String> {
+        self.inner.get_boxed(endpoint).await
+    }
+
+    fn evict(&self, request: &HttpRequest) {
+        self.inner.evict_boxed(request)
+    }
 }
@@ -0,0 +1,245 @@
+use tokio::time::{Duration, Instant};
+
+use crate::core::EbioticResult;
+use crate::errors::EbioticError;
+
+/// The `PollPolicy` struct is used to specify how often the status of a long running job is requested, and when to give up.
+///
+/// The first wait is the service's own estimate of the job duration when it gives one (e.g. the BLAST RTOE), and
+/// `initial_interval` otherwise. Each following wait grows by `growth_factor`, up to `max_interval`. Polling stops
+/// with `EbioticError::PollTimeout` once the `deadline` has passed or `max_polls` status requests have reported the
+/// job as still running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollPolicy {
+    initial_interval: Duration,
+    growth_factor: f64,
+    max_interval: Duration,
+    deadline: Option<Duration>,
+    max_polls: Option<u32>,
+}
+
+impl Default for PollPolicy {
+    fn default() -> Self {
+        PollPolicy::job_dispatcher()
+    }
+}
+
+impl PollPolicy {
+    pub fn new(
+        initial_interval: Duration,
+        growth_factor: f64,
+        max_interval: Duration,
+        deadline: Option<Duration>,
+        max_polls: Option<u32>,
+    ) -> PollPolicy {
+        PollPolicy {
+            initial_interval,
+            growth_factor: growth_factor.max(1.0),
+            max_interval: max_interval.max(initial_interval),
+            deadline,
+            max_polls,
+        }
+    }
+
+    /// NCBI asks that a search is polled no more than once a minute. Results are kept for 36 hours,
+    /// but a search still running after 6 hours is unlikely to finish.
+    pub fn blast() -> PollPolicy {
+        PollPolicy::new(
+            Duration::from_secs(60),
+            1.5,
+            Duration::from_secs(300),
+            Some(Duration::from_secs(6 * 60 * 60)),
+            None,
+        )
+    }
+
+    /// Most EBI Job Dispatcher jobs finish within seconds, so polling starts quickly and backs off.
+    pub fn job_dispatcher() -> PollPolicy {
+        PollPolicy::new(
+            Duration::from_secs(3),
+            1.5,
+            Duration::from_secs(60),
+            Some(Duration::from_secs(60 * 60)),
+            None,
+        )
+    }
+
+    pub fn set_initial_interval(&mut self, initial_interval: Duration) {
+        self.initial_interval = initial_interval;
+        self.max_interval = self.max_interval.max(initial_interval);
+    }
+
+    /// Set the factor each wait grows by, at least `1.0`.
+    pub fn set_growth_factor(&mut self, growth_factor: f64) {
+        self.growth_factor = growth_factor.max(1.0);
+    }
+
+    pub fn set_max_interval(&mut self, max_interval: Duration) {
+        self.max_interval = max_interval.max(self.initial_interval);
+    }
+
+    /// Set the overall time allowed for a job. `None` waits indefinitely.
+    pub fn set_deadline(&mut self, deadline: Option<Duration>) {
+        self.deadline = deadline;
+    }
+
+    /// Set the maximum number of status requests for a job. `None` polls indefinitely.
+    pub fn set_max_polls(&mut self, max_polls: Option<u32>) {
+        self.max_polls = max_polls;
+    }
+
+    pub fn initial_interval(&self) -> &Duration {
+        &self.initial_interval
+    }
+
+    pub fn growth_factor(&self) -> &f64 {
+        &self.growth_factor
+    }
+
+    pub fn max_interval(&self) -> &Duration {
+        &self.max_interval
+    }
+
+    pub fn deadline(&self) -> &Option<Duration> {
+        &self.deadline
+    }
+
+    pub fn max_polls(&self) -> &Option<u32> {
+        &self.max_polls
+    }
+}
+
+/// The `PollSchedule` struct tracks the progress of a single job against a `PollPolicy`.
+pub(crate) struct PollSchedule<'a> {
+    policy: &'a PollPolicy,
+    started: Instant,
+    polls: u32,
+    estimate: Option<Duration>,
+    interval: Duration,
+}
+
+impl<'a> PollSchedule<'a> {
+    pub(crate) fn new(policy: &'a PollPolicy, estimate: Option<Duration>) -> PollSchedule<'a> {
+        PollSchedule {
+            policy,
+            started: Instant::now(),
+            polls: 0,
+            estimate,
+            interval: policy.initial_interval,
+        }
+    }
+
+    /// The number of polls which found the job still running.
+    pub(crate) fn polls(&self) -> u32 {
+        self.polls
+    }
+
+    /// The time since the schedule started.
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Record a poll which found `job` still running, and return how long to wait before the next one.
+    pub(crate) fn next_wait(&mut self, job: &str) -> EbioticResult<Duration> {
+        self.polls += 1;
+        let elapsed = self.started.elapsed();
+
+        let exhausted = self.policy.max_polls.is_some_and(|max| self.polls >= max)
+            || self
+                .policy
+                .deadline
+                .is_some_and(|deadline| elapsed >= deadline);
+        if exhausted {
+            return Err(EbioticError::PollTimeout {
+                job: job.to_string(),
+                polls: self.polls,
+                elapsed,
+            });
+        }
+
+        let mut wait = match self.estimate.take() {
+            Some(estimate) => estimate.max(self.policy.initial_interval),
+            None => {
+                let wait = self.interval;
+                self.interval = self
+                    .interval
+                    .mul_f64(self.policy.growth_factor)
+                    .min(self.policy.max_interval);
+                wait
+            }
+        };
+        if let Some(deadline) = self.policy.deadline {
+            wait = wait.min(deadline - elapsed);
+        }
+        Ok(wait)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn intervals_grow_and_cap() {
+        let policy = PollPolicy::new(
+            Duration::from_secs(2),
+            2.0,
+            Duration::from_secs(5),
+            None,
+            None,
+        );
+        let mut schedule = PollSchedule::new(&policy, None);
+
+        assert_eq!(schedule.next_wait("job").unwrap(), Duration::from_secs(2));
+        assert_eq!(schedule.next_wait("job").unwrap(), Duration::from_secs(4));
+        assert_eq!(schedule.next_wait("job").unwrap(), Duration::from_secs(5));
+        assert_eq!(schedule.next_wait("job").unwrap(), Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn estimate_is_used_for_the_first_wait() {
+        let policy = PollPolicy::blast();
+        let mut schedule = PollSchedule::new(&policy, Some(Duration::from_secs(120)));
+
+        assert_eq!(schedule.next_wait("job").unwrap(), Duration::from_secs(120));
+        assert_eq!(schedule.next_wait("job").unwrap(), Duration::from_secs(60));
+
+        // An estimate shorter than the initial interval does not poll any sooner.
+        let mut schedule = PollSchedule::new(&policy, Some(Duration::from_secs(10)));
+        assert_eq!(schedule.next_wait("job").unwrap(), Duration::from_secs(60));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn deadline_shortens_the_last_wait_then_times_out() {
+        let policy = PollPolicy::new(
+            Duration::from_secs(10),
+            1.0,
+            Duration::from_secs(10),
+            Some(Duration::from_secs(15)),
+            None,
+        );
+        let mut schedule = PollSchedule::new(&policy, None);
+
+        assert_eq!(schedule.next_wait("job").unwrap(), Duration::from_secs(10));
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(schedule.next_wait("job").unwrap(), Duration::from_secs(5));
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(matches!(
+            schedule.next_wait("job"),
+            Err(EbioticError::PollTimeout { polls: 3, .. })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn max_polls_times_out() {
+        let mut policy = PollPolicy::default();
+        policy.set_max_polls(Some(2));
+        let mut schedule = PollSchedule::new(&policy, None);
+
+        assert!(schedule.next_wait("job").is_ok());
+        assert!(matches!(
+            schedule.next_wait("job"),
+            Err(EbioticError::PollTimeout { polls: 2, .. })
+        ));
+    }
+}
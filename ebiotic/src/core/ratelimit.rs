@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::time::{self, Duration, Instant};
+
+use crate::core::HttpRequest;
+
+/// Host used by the NCBI BLAST URL API.
+pub const NCBI_BLAST_HOST: &str = "blast.ncbi.nlm.nih.gov";
+/// Host used by the NCBI E-utilities, the only NCBI service which accepts an API key.
+pub const NCBI_EUTILS_HOST: &str = "eutils.ncbi.nlm.nih.gov";
+/// Host used by the EBI Job Dispatcher, Dbfetch and EBI Search services.
+pub const EBI_HOST: &str = "www.ebi.ac.uk";
+
+/// The `RateLimit` struct is used to specify a token bucket: up to `burst` requests may be made at once,
+/// and tokens are refilled at `requests` per `per`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    requests: u32,
+    per: Duration,
+    burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(requests: u32, per: Duration, burst: u32) -> RateLimit {
+        RateLimit {
+            requests: requests.max(1),
+            per,
+            burst: burst.max(1),
+        }
+    }
+
+    /// A limit of `requests` per second, allowing a burst of the same size.
+    pub fn per_second(requests: u32) -> RateLimit {
+        RateLimit::new(requests, Duration::from_secs(1), requests)
+    }
+
+    /// The BLAST URL API asks that the server is contacted no more than once every 10 seconds.
+    pub fn ncbi_blast() -> RateLimit {
+        RateLimit::new(1, Duration::from_secs(10), 1)
+    }
+
+    /// The BLAST URL API asks that a single RID is polled no more than once a minute.
+    pub fn ncbi_blast_poll() -> RateLimit {
+        RateLimit::new(1, Duration::from_secs(60), 1)
+    }
+
+    /// NCBI E-utilities allow 3 requests per second without an API key.
+    pub fn ncbi() -> RateLimit {
+        RateLimit::per_second(3)
+    }
+
+    /// NCBI E-utilities allow 10 requests per second when an API key is supplied.
+    pub fn ncbi_with_api_key() -> RateLimit {
+        RateLimit::per_second(10)
+    }
+
+    /// The EBI does not publish a hard limit for its REST services, but asks users not to
+    /// flood them. This is a conservative default.
+    pub fn ebi() -> RateLimit {
+        RateLimit::per_second(10)
+    }
+
+    pub fn requests(&self) -> &u32 {
+        &self.requests
+    }
+
+    pub fn per(&self) -> &Duration {
+        &self.per
+    }
+
+    pub fn burst(&self) -> &u32 {
+        &self.burst
+    }
+
+    fn tokens_per_second(&self) -> f64 {
+        self.requests as f64 / self.per.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// When the bucket will be full again, after which it is no different from a new bucket.
+    full_at: Instant,
+}
+
+/// The limits and buckets of a `RateLimiter`, kept together so that every clone sees the same configuration.
+#[derive(Debug, Default)]
+struct Limits {
+    limits: HashMap<String, RateLimit>,
+    poll_limits: HashMap<String, RateLimit>,
+    default_limit: Option<RateLimit>,
+    buckets: HashMap<String, Bucket>,
+}
+
+/// The `RateLimiter` struct is used to throttle requests per host using token buckets.
+///
+/// A host may also have a poll limit, which throttles the status polls of each job on that host separately.
+/// The limits and buckets are shared between clones, so cloning an `EbioticClient` keeps a single budget per host,
+/// and changing a limit on one clone changes it for all of them.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    inner: Arc<Mutex<Limits>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        let mut limiter = RateLimiter::unlimited();
+        limiter.set_limit(NCBI_BLAST_HOST, RateLimit::ncbi_blast());
+        limiter.set_poll_limit(NCBI_BLAST_HOST, RateLimit::ncbi_blast_poll());
+        limiter.set_limit(NCBI_EUTILS_HOST, RateLimit::ncbi());
+        limiter.set_limit(EBI_HOST, RateLimit::ebi());
+        limiter
+    }
+}
+
+impl RateLimiter {
+    /// A rate limiter with no limits configured for any host.
+    pub fn unlimited() -> RateLimiter {
+        RateLimiter {
+            inner: Arc::new(Mutex::new(Limits::default())),
+        }
+    }
+
+    /// Set the limit for a given host, replacing any existing limit.
+    pub fn set_limit(&mut self, host: &str, limit: RateLimit) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.limits.insert(host.to_string(), limit);
+        inner.buckets.remove(host);
+    }
+
+    /// Remove the limit for a given host.
+    pub fn remove_limit(&mut self, host: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.limits.remove(host);
+        inner.buckets.remove(host);
+    }
+
+    /// Set the limit applied to the status polls of each job on a given host, replacing any existing limit.
+    pub fn set_poll_limit(&mut self, host: &str, limit: RateLimit) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.poll_limits.insert(host.to_string(), limit);
+        let prefix = poll_key(host, "");
+        inner.buckets.retain(|key, _| !key.starts_with(&prefix));
+    }
+
+    /// Remove the poll limit for a given host.
+    pub fn remove_poll_limit(&mut self, host: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.poll_limits.remove(host);
+        let prefix = poll_key(host, "");
+        inner.buckets.retain(|key, _| !key.starts_with(&prefix));
+    }
+
+    /// Set the limit used for hosts without a specific limit. `None` leaves them unlimited.
+    pub fn set_default_limit(&mut self, limit: Option<RateLimit>) {
+        self.inner.lock().unwrap().default_limit = limit;
+    }
+
+    pub fn limit(&self, host: &str) -> Option<RateLimit> {
+        self.inner.lock().unwrap().limit(host).cloned()
+    }
+
+    pub fn poll_limit(&self, host: &str) -> Option<RateLimit> {
+        self.inner.lock().unwrap().poll_limits.get(host).cloned()
+    }
+
+    /// Wait until a request to `host` is permitted.
+    pub async fn acquire(&self, host: &str) {
+        let wait = {
+            let mut inner = self.inner.lock().unwrap();
+            let limit = inner.limit(host).cloned();
+            limit.and_then(|limit| inner.reserve(host, &limit))
+        };
+        if let Some(wait) = wait {
+            log::debug!("Rate limit reached for {}, waiting {:?}", host, wait);
+            time::sleep(wait).await;
+        }
+    }
+
+    /// Wait until `request` to `host` is permitted. Status polls wait for the poll limit of their job first,
+    /// so the host budget is not held while waiting.
+    pub(crate) async fn acquire_request(&self, host: &str, request: &HttpRequest) {
+        if let Some(job_id) = request.polled_job_id() {
+            let key = poll_key(host, job_id);
+            let wait = {
+                let mut inner = self.inner.lock().unwrap();
+                let limit = inner.poll_limits.get(host).cloned();
+                limit.and_then(|limit| inner.reserve(&key, &limit))
+            };
+            if let Some(wait) = wait {
+                log::debug!("Poll limit reached for job {}, waiting {:?}", job_id, wait);
+                time::sleep(wait).await;
+            }
+        }
+        self.acquire(host).await;
+    }
+}
+
+/// The bucket key used for the polls of `job_id` on `host`.
+fn poll_key(host: &str, job_id: &str) -> String {
+    format!("{} poll {}", host, job_id)
+}
+
+impl Limits {
+    fn limit(&self, host: &str) -> Option<&RateLimit> {
+        self.limits.get(host).or(self.default_limit.as_ref())
+    }
+
+    /// Take a token from the bucket under `key`, returning how long the caller must wait for it.
+    ///
+    /// Tokens are allowed to go negative, so concurrent callers queue up behind each other
+    /// rather than all waking at once. Buckets which have refilled are dropped when a new one is added, so the
+    /// per-job poll buckets do not build up over the life of the client.
+    fn reserve(&mut self, key: &str, limit: &RateLimit) -> Option<Duration> {
+        let rate = limit.tokens_per_second();
+        let now = Instant::now();
+
+        if !self.buckets.contains_key(key) {
+            self.buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+        let bucket = self.buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: limit.burst as f64,
+            last_refill: now,
+            full_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(limit.burst as f64);
+        bucket.last_refill = now;
+        bucket.tokens -= 1.0;
+        bucket.full_at = now + Duration::from_secs_f64((limit.burst as f64 - bucket.tokens) / rate);
+
+        if bucket.tokens >= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-bucket.tokens / rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn allows_burst_then_throttles() {
+        let mut limiter = RateLimiter::unlimited();
+        limiter.set_limit("example.org", RateLimit::per_second(3));
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire("example.org").await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        for _ in 0..3 {
+            limiter.acquire("example.org").await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(990));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hosts_have_independent_buckets() {
+        let mut limiter = RateLimiter::unlimited();
+        limiter.set_limit("a.org", RateLimit::per_second(1));
+        limiter.set_limit("b.org", RateLimit::per_second(1));
+
+        let start = Instant::now();
+        limiter.acquire("a.org").await;
+        limiter.acquire("b.org").await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clones_share_buckets() {
+        let limiter = RateLimiter::default();
+        let clone = limiter.clone();
+
+        let start = Instant::now();
+        limiter.acquire(NCBI_BLAST_HOST).await;
+        clone.acquire(NCBI_BLAST_HOST).await;
+        assert!(start.elapsed() >= Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clones_share_limits() {
+        let limiter = RateLimiter::unlimited();
+        let mut clone = limiter.clone();
+        clone.set_limit("example.org", RateLimit::per_second(1));
+
+        let start = Instant::now();
+        limiter.acquire("example.org").await;
+        clone.acquire("example.org").await;
+        assert_eq!(limiter.limit("example.org"), Some(RateLimit::per_second(1)));
+        assert!(start.elapsed() >= Duration::from_millis(990));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn polls_are_limited_per_job() {
+        let mut limiter = RateLimiter::unlimited();
+        limiter.set_poll_limit("example.org", RateLimit::new(1, Duration::from_secs(60), 1));
+        let poll = |rid| {
+            HttpRequest::post(
+                "https://example.org/",
+                &[
+                    ("CMD", "Get"),
+                    ("FORMAT_OBJECT", "SearchInfo"),
+                    ("RID", rid),
+                ],
+            )
+        };
+        let fetch = HttpRequest::post("https://example.org/", &[("CMD", "Get"), ("RID", "A")]);
+
+        let start = Instant::now();
+        limiter.acquire_request("example.org", &poll("A")).await;
+        limiter.acquire_request("example.org", &poll("B")).await;
+        limiter.acquire_request("example.org", &fetch).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        limiter.acquire_request("example.org", &poll("A")).await;
+        assert!(start.elapsed() >= Duration::from_secs(60));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drops_refilled_poll_buckets() {
+        let mut limiter = RateLimiter::unlimited();
+        limiter.set_poll_limit("example.org", RateLimit::new(1, Duration::from_secs(60), 1));
+        let poll = |rid: &str| {
+            HttpRequest::post(
+                "https://example.org/",
+                &[
+                    ("CMD", "Get"),
+                    ("FORMAT_OBJECT", "SearchInfo"),
+                    ("RID", rid),
+                ],
+            )
+        };
+
+        for rid in 0..100 {
+            limiter
+                .acquire_request("example.org", &poll(&rid.to_string()))
+                .await;
+        }
+        assert_eq!(limiter.inner.lock().unwrap().buckets.len(), 100);
+
+        time::advance(Duration::from_secs(60)).await;
+        limiter.acquire_request("example.org", &poll("next")).await;
+        assert_eq!(limiter.inner.lock().unwrap().buckets.len(), 1);
+
+        // A job still polled keeps its bucket, and so its limit
+        let start = Instant::now();
+        limiter.acquire_request("example.org", &poll("other")).await;
+        limiter.acquire_request("example.org", &poll("next")).await;
+        assert!(start.elapsed() >= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn unknown_hosts_are_unlimited_by_default() {
+        let limiter = RateLimiter::default();
+        assert!(limiter.limit("example.org").is_none());
+        assert_eq!(
+            limiter.limit(NCBI_BLAST_HOST),
+            Some(RateLimit::ncbi_blast())
+        );
+        assert_eq!(
+            limiter.poll_limit(NCBI_BLAST_HOST),
+            Some(RateLimit::ncbi_blast_poll())
+        );
+        assert_eq!(limiter.limit(NCBI_EUTILS_HOST), Some(RateLimit::ncbi()));
+        assert_eq!(limiter.limit(EBI_HOST), Some(RateLimit::ebi()));
+    }
+}
@@ -1,7 +1,14 @@
-use reqwest::Client;
-use tokio::time::{self, Duration};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Url};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::time;
 
-use crate::core::{PollStatus, PollableService};
+use crate::core::cache::ResponseCache;
+use crate::core::ratelimit::{RateLimit, RateLimiter, NCBI_EUTILS_HOST};
+use crate::core::retry::{parse_retry_after, RetryPolicy};
+use crate::core::transport::{FixtureRecorder, Fixtures, HttpMethod, HttpRequest, HttpResponse};
+use crate::core::EbioticResult;
 use crate::errors::EbioticError;
 
 use super::network::EbioticHttpClient;
@@ -10,64 +17,555 @@ use super::network::EbioticHttpClient;
 #[derive(Debug, Clone)]
 pub struct EbioticReqwestClient {
     pub(crate) client: Client,
+    transport: Transport,
+    retry_policy: RetryPolicy,
+    rate_limiter: RateLimiter,
+    ncbi_api_key: Option<String>,
+    cache: Option<ResponseCache>,
+}
+
+/// Where requests are actually sent. Retrying and rate limiting are applied on top of every transport.
+#[derive(Debug, Clone)]
+enum Transport {
+    Live,
+    Replay(Arc<Fixtures>),
+    Record(Arc<FixtureRecorder>),
 }
 
 impl Default for EbioticReqwestClient {
     fn default() -> Self {
+        EbioticReqwestClient::new(Client::new())
+    }
+}
+
+impl EbioticReqwestClient {
+    pub fn new(client: Client) -> EbioticReqwestClient {
         EbioticReqwestClient {
-            client: Client::new(),
+            client,
+            transport: Transport::Live,
+            retry_policy: RetryPolicy::default(),
+            rate_limiter: RateLimiter::default(),
+            ncbi_api_key: None,
+            cache: None,
         }
     }
+
+    /// Create a client that never touches the network, serving every request from the given `Fixtures`.
+    /// Requests without a matching fixture fail with `EbioticError::NoMatchingFixture`.
+    pub fn replay(fixtures: Fixtures) -> EbioticReqwestClient {
+        EbioticReqwestClient {
+            transport: Transport::Replay(Arc::new(fixtures)),
+            rate_limiter: RateLimiter::unlimited(),
+            ..EbioticReqwestClient::default()
+        }
+    }
+
+    /// Create a client that sends requests with the given `reqwest::Client` and records every
+    /// interaction to a JSON fixture file at `path`, which can later be loaded with `Fixtures::from_file`.
+    pub fn record<P: AsRef<Path>>(client: Client, path: P) -> EbioticReqwestClient {
+        EbioticReqwestClient {
+            transport: Transport::Record(Arc::new(FixtureRecorder::new(
+                path.as_ref().to_path_buf(),
+            ))),
+            ..EbioticReqwestClient::new(client)
+        }
+    }
+
+    /// Set the `RetryPolicy` applied to every request made through this client, including polling.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Set the `RateLimiter` used to throttle requests per host, including polling.
+    pub fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Set an NCBI API key. The key is sent with every request to the NCBI E-utilities and raises their
+    /// rate limit to the 10 requests per second permitted for keyed access. The BLAST URL API does not
+    /// accept a key, so its limits are unchanged.
+    pub fn set_ncbi_api_key(&mut self, api_key: String) {
+        self.ncbi_api_key = Some(api_key);
+        self.rate_limiter
+            .set_limit(NCBI_EUTILS_HOST, RateLimit::ncbi_with_api_key());
+    }
+
+    pub fn ncbi_api_key(&self) -> Option<&String> {
+        self.ncbi_api_key.as_ref()
+    }
+
+    /// Set a `ResponseCache`, consulted before any request is sent.
+    pub fn set_cache(&mut self, cache: ResponseCache) {
+        self.cache = Some(cache);
+    }
+
+    pub fn cache(&self) -> Option<&ResponseCache> {
+        self.cache.as_ref()
+    }
 }
 
 impl EbioticReqwestClient {
-    pub fn new(client: Client) -> EbioticReqwestClient {
-        EbioticReqwestClient { client }
+    /// Send a request, serving it from the `ResponseCache` when possible.
+    async fn send(&self, request: HttpRequest) -> EbioticResult<HttpResponse> {
+        let Some(cache) = &self.cache else {
+            return self.send_uncached(&request).await;
+        };
+
+        if let Some(response) = cache.lookup(&request)? {
+            return Ok(response);
+        }
+        let response = self.send_uncached(&request).await?;
+        if let Err(err) = cache.store(&request, &response) {
+            log::warn!("Unable to cache response to {}: {}", request.url(), err);
+        }
+        Ok(response)
+    }
+
+    /// Send a request, throttled by the `RateLimiter` and retrying transient failures according to the `RetryPolicy`.
+    /// Requests that are not idempotent are attempted once unless the policy allows retrying them.
+    async fn send_uncached(&self, request: &HttpRequest) -> EbioticResult<HttpResponse> {
+        let host = host_of(request).unwrap_or_default();
+
+        let max_attempts = if self.retry_policy.may_retry(request) {
+            *self.retry_policy.max_attempts()
+        } else {
+            1
+        };
+        let mut attempt = 1;
+
+        loop {
+            self.rate_limiter.acquire_request(&host, request).await;
+
+            match self.attempt(request, attempt).await {
+                Ok(response) => {
+                    let status = *response.status();
+                    if attempt >= max_attempts || !self.retry_policy.should_retry_status(status) {
+                        return Ok(response);
+                    }
+
+                    let retry_after = response
+                        .header(RETRY_AFTER.as_str())
+                        .and_then(parse_retry_after);
+                    let delay = self.retry_policy.delay_for(attempt, retry_after);
+
+                    log::warn!(
+                        "Request to {} returned status {} (attempt {}/{}), retrying in {:?}",
+                        request.url(),
+                        status,
+                        attempt,
+                        max_attempts,
+                        delay
+                    );
+                    time::sleep(delay).await;
+                }
+                Err(err) => {
+                    if attempt >= max_attempts || !self.retry_policy.should_retry_error(&err) {
+                        return Err(err);
+                    }
+
+                    let delay = self.retry_policy.delay_for(attempt, None);
+
+                    log::warn!(
+                        "Request failed: {} (attempt {}/{}), retrying in {:?}",
+                        err,
+                        attempt,
+                        max_attempts,
+                        delay
+                    );
+                    time::sleep(delay).await;
+                }
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Make a single attempt at a request, recorded as an `http_request` span when the `tracing` feature is enabled.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "http_request",
+            skip_all,
+            fields(
+                method = ?request.method(),
+                url = %request.url(),
+                attempt = attempt,
+                status = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+                bytes = tracing::field::Empty,
+            )
+        )
+    )]
+    async fn attempt(&self, request: &HttpRequest, attempt: u32) -> EbioticResult<HttpResponse> {
+        log::debug!("Sending {} (attempt {})", request.url(), attempt);
+
+        #[cfg(feature = "tracing")]
+        let started = tokio::time::Instant::now();
+
+        let response = self.execute(request).await;
+
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("latency_ms", started.elapsed().as_millis() as u64);
+            if let Ok(response) = &response {
+                span.record("status", response.status());
+                span.record("bytes", response.body().len());
+            }
+        }
+
+        response
+    }
+
+    /// Make a single attempt at a request on the configured transport.
+    async fn execute(&self, request: &HttpRequest) -> EbioticResult<HttpResponse> {
+        match &self.transport {
+            Transport::Live => self.execute_live(request).await,
+            Transport::Replay(fixtures) => fixtures.replay(request),
+            Transport::Record(recorder) => {
+                let response = self.execute_live(request).await?;
+                recorder.record(request, &response)?;
+                Ok(response)
+            }
+        }
+    }
+
+    async fn execute_live(&self, request: &HttpRequest) -> EbioticResult<HttpResponse> {
+        let mut builder = match request.method() {
+            HttpMethod::Get => self.client.get(request.url()),
+            HttpMethod::Post => self.client.post(request.url()).form(request.form()),
+        };
+
+        // The key is added here rather than to the `HttpRequest` so that it is never recorded.
+        if let Some(key) = &self.ncbi_api_key {
+            if host_of(request).as_deref() == Some(NCBI_EUTILS_HOST) {
+                builder = builder.query(&[("api_key", key)]);
+            }
+        }
+
+        let response = builder.send().await?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| *name == RETRY_AFTER)
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect::<Vec<_>>();
+        let body = response.text().await?;
+
+        let mut response = HttpResponse::new(status, body);
+        for (name, value) in headers {
+            response.add_header(&name, &value);
+        }
+        Ok(response)
     }
 }
 
+/// The host a request is sent to, used to key rate limits.
+fn host_of(request: &HttpRequest) -> Option<String> {
+    Url::parse(request.url())
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+}
+
 impl EbioticHttpClient for EbioticReqwestClient {
     async fn post_form(
         &self,
         endpoint: &str,
         body: &[(&str, &str)],
     ) -> Result<String, EbioticError> {
-        let response = self.client.post(endpoint).form(body).send().await?;
-        Ok(response.text().await?)
+        self.send(HttpRequest::post(endpoint, body))
+            .await?
+            .into_body(endpoint)
     }
 
     async fn get(&self, endpoint: &str) -> Result<String, EbioticError> {
-        let response = self.client.get(endpoint).send().await?;
-        Ok(response.text().await?)
+        self.send(HttpRequest::get(endpoint))
+            .await?
+            .into_body(endpoint)
     }
 
-    async fn poll<F>(
-        &self,
-        endpoint: &str,
-        post_body: Option<&[(&str, &str)]>,
-        method_caller: &F,
-    ) -> Result<String, EbioticError>
-    where
-        F: PollableService,
-    {
-        loop {
-            let response;
-            if let Some(body) = post_body {
-                response = self.post_form(endpoint, body).await?;
-            } else {
-                response = self.get(endpoint).await?;
+    fn evict(&self, request: &HttpRequest) {
+        if let Some(cache) = &self.cache {
+            if let Err(err) = cache.evict(request) {
+                log::warn!("Unable to evict {} from the cache: {}", request.url(), err);
             }
+        }
+    }
+}
 
-            let status = method_caller.poll_status(&response);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ratelimit::NCBI_BLAST_HOST;
+    use crate::core::retry::RetryableStatus;
+    use crate::core::transport::Fixture;
+    use tokio::time::Duration;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
-            match status {
-                PollStatus::Finished => return Ok(response),
-                PollStatus::Running(sleep_time) => {
-                    log::info!("Job is still running, sleeping for {} seconds", sleep_time);
-                    time::sleep(Duration::from_secs(sleep_time)).await;
-                }
-                PollStatus::Error(err) => return Err(err),
+    fn fast_client(max_attempts: u32) -> EbioticReqwestClient {
+        let mut client = EbioticReqwestClient::default();
+        client.set_retry_policy(RetryPolicy::new(
+            max_attempts,
+            Duration::ZERO,
+            Duration::ZERO,
+            0.0,
+            vec![
+                RetryableStatus::ServerError,
+                RetryableStatus::TooManyRequests,
+            ],
+        ));
+        client
+    }
+
+    #[tokio::test]
+    async fn get_retries_transient_failures() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let client = fast_client(3);
+        let body = client
+            .get(&format!("{}/flaky", server.uri()))
+            .await
+            .unwrap();
+
+        assert_eq!(body, "ok");
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn post_form_honours_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("done"))
+            .mount(&server)
+            .await;
+
+        let mut client = fast_client(2);
+        let mut policy = client.retry_policy().clone();
+        policy.set_base_delay(Duration::from_secs(3600));
+        policy.set_max_delay(Duration::from_secs(3600));
+        client.set_retry_policy(policy);
+
+        let body = time::timeout(
+            Duration::from_secs(10),
+            client.post_form(&server.uri(), &[("CMD", "Get"), ("RID", "ABC")]),
+        )
+        .await
+        .expect("Retry-After should override the base delay")
+        .unwrap();
+
+        assert_eq!(body, "done");
+    }
+
+    #[tokio::test]
+    async fn submissions_are_not_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let client = fast_client(4);
+        let _ = client.post_form(&server.uri(), &[("CMD", "Put")]).await;
+
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn requests_are_rate_limited_per_host() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let mut client = fast_client(1);
+        let mut limiter = RateLimiter::unlimited();
+        limiter.set_limit(
+            "127.0.0.1",
+            RateLimit::new(1, Duration::from_millis(200), 1),
+        );
+        client.set_rate_limiter(limiter);
+
+        let start = time::Instant::now();
+        for _ in 0..3 {
+            client.get(&server.uri()).await.unwrap();
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(390));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replay_serves_fixtures_through_retries() {
+        let mut retry = HttpResponse::new(503, "".to_string());
+        retry.add_header("Retry-After", "30");
+        let fixtures = Fixtures::new(vec![
+            Fixture::new(HttpRequest::get("http://example.org/"), retry),
+            Fixture::new(
+                HttpRequest::get("http://example.org/"),
+                HttpResponse::new(200, "ok".to_string()),
+            ),
+        ]);
+
+        let client = EbioticReqwestClient::replay(fixtures);
+        let body = client.get("http://example.org/").await.unwrap();
+
+        assert_eq!(body, "ok");
+    }
+
+    #[tokio::test]
+    async fn record_writes_fixtures_that_can_be_replayed() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("RID = ABC"))
+            .mount(&server)
+            .await;
+
+        let path = std::env::temp_dir().join(format!(
+            "ebiotic-record-{}-{}.json",
+            std::process::id(),
+            server.address().port()
+        ));
+        let client = EbioticReqwestClient::record(Client::new(), &path);
+        let live = client
+            .post_form(&server.uri(), &[("CMD", "Put")])
+            .await
+            .unwrap();
+
+        let replay = EbioticReqwestClient::replay(Fixtures::from_file(&path).unwrap());
+        let replayed = replay
+            .post_form(&server.uri(), &[("CMD", "Put")])
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(live, replayed);
+    }
+
+    #[test]
+    fn ncbi_api_key_raises_limit() {
+        let mut client = EbioticReqwestClient::default();
+        client.set_ncbi_api_key("key".to_string());
+
+        assert_eq!(client.ncbi_api_key(), Some(&"key".to_string()));
+        assert_eq!(
+            client.rate_limiter().limit(NCBI_EUTILS_HOST),
+            Some(RateLimit::ncbi_with_api_key())
+        );
+        assert_eq!(
+            client.rate_limiter().limit(NCBI_BLAST_HOST),
+            Some(RateLimit::ncbi_blast())
+        );
+    }
+
+    #[tokio::test]
+    async fn non_success_status_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("<html>Not Found</html>"))
+            .mount(&server)
+            .await;
+
+        let client = fast_client(1);
+        let result = client.get(&format!("{}/missing", server.uri())).await;
+
+        match result {
+            Err(EbioticError::HttpStatus { status, url, body }) => {
+                assert_eq!(status, 404);
+                assert!(url.ends_with("/missing"));
+                assert_eq!(body, "<html>Not Found</html>");
             }
+            other => panic!("Expected HttpStatus error, got {:?}", other),
         }
     }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(502))
+            .mount(&server)
+            .await;
+
+        let client = fast_client(4);
+        let _ = client.get(&server.uri()).await;
+
+        assert_eq!(server.received_requests().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let client = fast_client(4);
+        let _ = client.get(&server.uri()).await;
+
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn requests_are_recorded_as_spans() {
+        use std::sync::Mutex;
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Collects the names of the spans created while it is the default subscriber.
+        struct SpanNames(Arc<Mutex<Vec<String>>>);
+
+        impl Subscriber for SpanNames {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut names = self.0.lock().unwrap();
+                names.push(span.metadata().name().to_string());
+                Id::from_u64(names.len() as u64)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let _guard = tracing::subscriber::set_default(SpanNames(names.clone()));
+        let client = EbioticReqwestClient::replay(Fixtures::new(vec![Fixture::new(
+            HttpRequest::get("http://example.org/"),
+            HttpResponse::new(200, "ok".to_string()),
+        )]));
+
+        client.get("http://example.org/").await.unwrap();
+
+        assert_eq!(*names.lock().unwrap(), vec!["http_request".to_string()]);
+    }
 }
@@ -0,0 +1,299 @@
+use std::time::{Duration, SystemTime};
+
+use crate::core::HttpRequest;
+use crate::errors::EbioticError;
+
+/// The `RetryableStatus` enum is used to specify which HTTP responses are considered transient by a `RetryPolicy`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RetryableStatus {
+    /// Any `5xx` response.
+    ServerError,
+    /// `429 Too Many Requests`.
+    TooManyRequests,
+    /// `408 Request Timeout`.
+    RequestTimeout,
+    /// A specific status code.
+    Code(u16),
+}
+
+impl RetryableStatus {
+    fn matches(&self, status: u16) -> bool {
+        match self {
+            RetryableStatus::ServerError => (500..600).contains(&status),
+            RetryableStatus::TooManyRequests => status == 429,
+            RetryableStatus::RequestTimeout => status == 408,
+            RetryableStatus::Code(code) => *code == status,
+        }
+    }
+}
+
+/// The `RetryPolicy` struct is used to specify how the `EbioticClient` retries transient failures.
+///
+/// Delays grow exponentially from `base_delay` (doubling per attempt) up to `max_delay`, with a random
+/// `jitter` fraction applied either side. If the server sends a `Retry-After` header it takes precedence
+/// over the computed delay.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    retry_on: Vec<RetryableStatus>,
+    retry_connection_errors: bool,
+    respect_retry_after: bool,
+    retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: 0.25,
+            retry_on: vec![
+                RetryableStatus::ServerError,
+                RetryableStatus::TooManyRequests,
+                RetryableStatus::RequestTimeout,
+            ],
+            retry_connection_errors: true,
+            respect_retry_after: true,
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(
+        max_attempts: u32,
+        base_delay: Duration,
+        max_delay: Duration,
+        jitter: f64,
+        retry_on: Vec<RetryableStatus>,
+    ) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            base_delay,
+            max_delay,
+            jitter: jitter.clamp(0.0, 1.0),
+            retry_on,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// A policy that never retries, every request is attempted exactly once.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    pub fn set_max_attempts(&mut self, max_attempts: u32) {
+        self.max_attempts = max_attempts.max(1);
+    }
+
+    pub fn set_base_delay(&mut self, base_delay: Duration) {
+        self.base_delay = base_delay;
+    }
+
+    pub fn set_max_delay(&mut self, max_delay: Duration) {
+        self.max_delay = max_delay;
+    }
+
+    /// Set the jitter as a fraction of the computed delay, clamped to `0.0..=1.0`.
+    pub fn set_jitter(&mut self, jitter: f64) {
+        self.jitter = jitter.clamp(0.0, 1.0);
+    }
+
+    pub fn set_retry_on(&mut self, retry_on: Vec<RetryableStatus>) {
+        self.retry_on = retry_on;
+    }
+
+    pub fn set_retry_connection_errors(&mut self, retry_connection_errors: bool) {
+        self.retry_connection_errors = retry_connection_errors;
+    }
+
+    pub fn set_respect_retry_after(&mut self, respect_retry_after: bool) {
+        self.respect_retry_after = respect_retry_after;
+    }
+
+    /// Also retry requests that are not idempotent, such as job submissions, which may then be submitted twice.
+    pub fn set_retry_non_idempotent(&mut self, retry_non_idempotent: bool) {
+        self.retry_non_idempotent = retry_non_idempotent;
+    }
+
+    pub fn max_attempts(&self) -> &u32 {
+        &self.max_attempts
+    }
+
+    pub fn base_delay(&self) -> &Duration {
+        &self.base_delay
+    }
+
+    pub fn max_delay(&self) -> &Duration {
+        &self.max_delay
+    }
+
+    pub fn jitter(&self) -> &f64 {
+        &self.jitter
+    }
+
+    pub fn retry_on(&self) -> &Vec<RetryableStatus> {
+        &self.retry_on
+    }
+
+    pub fn retry_connection_errors(&self) -> &bool {
+        &self.retry_connection_errors
+    }
+
+    pub fn respect_retry_after(&self) -> &bool {
+        &self.respect_retry_after
+    }
+
+    pub fn retry_non_idempotent(&self) -> &bool {
+        &self.retry_non_idempotent
+    }
+}
+
+impl RetryPolicy {
+    /// Whether `request` may be retried at all under this policy.
+    pub(crate) fn may_retry(&self, request: &HttpRequest) -> bool {
+        self.retry_non_idempotent || request.is_idempotent()
+    }
+
+    pub(crate) fn should_retry_status(&self, status: u16) -> bool {
+        self.retry_on.iter().any(|s| s.matches(status))
+    }
+
+    pub(crate) fn should_retry_error(&self, err: &EbioticError) -> bool {
+        match err {
+            EbioticError::NetworkError(err) => {
+                self.retry_connection_errors
+                    && (err.is_connect() || err.is_timeout() || err.is_request())
+            }
+            _ => false,
+        }
+    }
+
+    /// Compute the delay before the next attempt, `attempt` being the (1-based) attempt that just failed.
+    pub(crate) fn delay_for(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if self.respect_retry_after {
+            if let Some(retry_after) = retry_after {
+                return retry_after.min(self.max_delay);
+            }
+        }
+
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(1u32 << exponent)
+            .min(self.max_delay);
+
+        if self.jitter > 0.0 {
+            let factor = 1.0 + self.jitter * (fastrand::f64() * 2.0 - 1.0);
+            delay.mul_f64(factor).min(self.max_delay)
+        } else {
+            delay
+        }
+    }
+}
+
+/// Parse a `Retry-After` header value, which is either a number of seconds or an HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_exponentially_and_caps() {
+        let policy = RetryPolicy::new(
+            5,
+            Duration::from_secs(1),
+            Duration::from_secs(5),
+            0.0,
+            vec![RetryableStatus::ServerError],
+        );
+
+        assert_eq!(policy.delay_for(1, None), Duration::from_secs(1));
+        assert_eq!(policy.delay_for(2, None), Duration::from_secs(2));
+        assert_eq!(policy.delay_for(3, None), Duration::from_secs(4));
+        assert_eq!(policy.delay_for(4, None), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let mut policy = RetryPolicy::default();
+        policy.set_base_delay(Duration::from_secs(10));
+        policy.set_jitter(0.5);
+
+        for _ in 0..100 {
+            let delay = policy.delay_for(1, None);
+            assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(15));
+        }
+    }
+
+    #[test]
+    fn retry_after_takes_precedence() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(42))),
+            Duration::from_secs(42)
+        );
+    }
+
+    #[test]
+    fn retry_after_is_capped_at_max_delay() {
+        let mut policy = RetryPolicy::default();
+        policy.set_max_delay(Duration::from_secs(10));
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(3600))),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn only_idempotent_requests_are_retried_by_default() {
+        let mut policy = RetryPolicy::default();
+        let submit = HttpRequest::post("https://example.org/", &[("CMD", "Put")]);
+        let poll = HttpRequest::post("https://example.org/", &[("CMD", "Get"), ("RID", "X")]);
+
+        assert!(policy.may_retry(&HttpRequest::get("https://example.org/")));
+        assert!(policy.may_retry(&poll));
+        assert!(!policy.may_retry(&submit));
+
+        policy.set_retry_non_idempotent(true);
+        assert!(policy.may_retry(&submit));
+    }
+
+    #[test]
+    fn status_classes_match() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry_status(502));
+        assert!(policy.should_retry_status(503));
+        assert!(policy.should_retry_status(429));
+        assert!(!policy.should_retry_status(404));
+        assert!(!policy.should_retry_status(200));
+    }
+
+    #[test]
+    fn parse_retry_after_handles_seconds_and_dates() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
@@ -0,0 +1,317 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::core::{body_excerpt, EbioticResult};
+use crate::errors::EbioticError;
+
+/// The `HttpMethod` enum lists the HTTP methods used by the services.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    Get,
+    Post,
+}
+
+/// The `HttpRequest` struct is a transport independent description of a request made by the `EbioticClient`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HttpRequest {
+    method: HttpMethod,
+    url: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    form: Vec<(String, String)>,
+}
+
+/// The `HttpResponse` struct is a transport independent description of a response received by the `EbioticClient`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HttpResponse {
+    status: u16,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+/// The `Fixture` struct pairs a request with the response that should be replayed for it.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    request: HttpRequest,
+    response: HttpResponse,
+}
+
+/// The `Fixtures` struct is an ordered set of recorded interactions used to replay requests offline.
+///
+/// Each fixture is served once, in the order it was recorded, so a polled endpoint can be replayed
+/// as e.g. `RUNNING`, `RUNNING`, `FINISHED`.
+#[derive(Debug, Default)]
+pub struct Fixtures {
+    fixtures: Mutex<Vec<(Fixture, bool)>>,
+}
+
+impl HttpRequest {
+    pub fn get(url: &str) -> HttpRequest {
+        HttpRequest {
+            method: HttpMethod::Get,
+            url: url.to_string(),
+            form: Vec::new(),
+        }
+    }
+
+    pub fn post(url: &str, form: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest {
+            method: HttpMethod::Post,
+            url: url.to_string(),
+            form: form
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn method(&self) -> &HttpMethod {
+        &self.method
+    }
+
+    pub fn url(&self) -> &String {
+        &self.url
+    }
+
+    pub fn form(&self) -> &Vec<(String, String)> {
+        &self.form
+    }
+
+    /// Whether the request can safely be sent more than once. `GET` requests are, as are form posts that
+    /// only read the state of a job (`CMD=Get`), while submissions such as a BLAST `CMD=Put` are not.
+    pub fn is_idempotent(&self) -> bool {
+        match self.method {
+            HttpMethod::Get => true,
+            HttpMethod::Post => self.form.iter().any(|(k, v)| k == "CMD" && v == "Get"),
+        }
+    }
+
+    /// The job whose status this request polls, if it is a BLAST `SearchInfo` request.
+    pub(crate) fn polled_job_id(&self) -> Option<&str> {
+        let field = |name: &str| {
+            self.form
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+        };
+        if field("CMD") == Some("Get") && field("FORMAT_OBJECT") == Some("SearchInfo") {
+            field("RID")
+        } else {
+            None
+        }
+    }
+
+    /// Requests match if the method and URL are equal and the form holds the same fields, in any order.
+    pub(crate) fn matches(&self, other: &HttpRequest) -> bool {
+        if self.method != other.method || self.url != other.url {
+            return false;
+        }
+        let mut lhs = self.form.clone();
+        let mut rhs = other.form.clone();
+        lhs.sort();
+        rhs.sort();
+        lhs == rhs
+    }
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: String) -> HttpResponse {
+        HttpResponse {
+            status,
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    /// Add a header to the response.
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn status(&self) -> &u16 {
+        &self.status
+    }
+
+    pub fn headers(&self) -> &Vec<(String, String)> {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &String {
+        &self.body
+    }
+
+    /// Get the first value of a header, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Take the body of a `2xx` response, or turn any other status into an `EbioticError::HttpStatus`.
+    pub(crate) fn into_body(self, url: &str) -> EbioticResult<String> {
+        if self.is_success() {
+            Ok(self.body)
+        } else {
+            Err(EbioticError::HttpStatus {
+                status: self.status,
+                url: url.to_string(),
+                body: body_excerpt(&self.body),
+            })
+        }
+    }
+}
+
+impl Fixture {
+    pub fn new(request: HttpRequest, response: HttpResponse) -> Fixture {
+        Fixture { request, response }
+    }
+
+    pub fn request(&self) -> &HttpRequest {
+        &self.request
+    }
+
+    pub fn response(&self) -> &HttpResponse {
+        &self.response
+    }
+}
+
+impl Fixtures {
+    pub fn new(fixtures: Vec<Fixture>) -> Fixtures {
+        Fixtures {
+            fixtures: Mutex::new(fixtures.into_iter().map(|f| (f, false)).collect()),
+        }
+    }
+
+    /// Parse fixtures from a JSON array, as written by a recording `EbioticClient`.
+    pub fn from_json(json: &str) -> EbioticResult<Fixtures> {
+        let fixtures: Vec<Fixture> = serde_json::from_str(json)?;
+        Ok(Fixtures::new(fixtures))
+    }
+
+    /// Load fixtures from a JSON file, as written by a recording `EbioticClient`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> EbioticResult<Fixtures> {
+        Fixtures::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Add a fixture to the end of the set.
+    pub fn push(&self, fixture: Fixture) {
+        self.fixtures.lock().unwrap().push((fixture, false));
+    }
+
+    /// The number of fixtures that have not yet been replayed.
+    pub fn remaining(&self) -> usize {
+        self.fixtures
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, used)| !used)
+            .count()
+    }
+
+    /// Serve the first unused fixture matching the request.
+    pub(crate) fn replay(&self, request: &HttpRequest) -> EbioticResult<HttpResponse> {
+        let mut fixtures = self.fixtures.lock().unwrap();
+        let (fixture, used) = fixtures
+            .iter_mut()
+            .find(|(fixture, used)| !used && fixture.request.matches(request))
+            .ok_or_else(|| {
+                EbioticError::NoMatchingFixture(format!("{:?} {}", request.method, request.url))
+            })?;
+        *used = true;
+        Ok(fixture.response.clone())
+    }
+}
+
+/// The `FixtureRecorder` struct captures live interactions and writes them to disk as they happen.
+#[derive(Debug)]
+pub(crate) struct FixtureRecorder {
+    path: PathBuf,
+    fixtures: Mutex<Vec<Fixture>>,
+}
+
+impl FixtureRecorder {
+    pub(crate) fn new(path: PathBuf) -> FixtureRecorder {
+        FixtureRecorder {
+            path,
+            fixtures: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn record(
+        &self,
+        request: &HttpRequest,
+        response: &HttpResponse,
+    ) -> EbioticResult<()> {
+        let mut fixtures = self.fixtures.lock().unwrap();
+        fixtures.push(Fixture::new(request.clone(), response.clone()));
+        fs::write(&self.path, serde_json::to_string_pretty(&*fixtures)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_replay_in_order_and_once() {
+        let fixtures = Fixtures::new(vec![
+            Fixture::new(
+                HttpRequest::get("http://example.org/status"),
+                HttpResponse::new(200, "RUNNING".to_string()),
+            ),
+            Fixture::new(
+                HttpRequest::get("http://example.org/status"),
+                HttpResponse::new(200, "FINISHED".to_string()),
+            ),
+        ]);
+        let request = HttpRequest::get("http://example.org/status");
+
+        assert_eq!(fixtures.replay(&request).unwrap().body(), "RUNNING");
+        assert_eq!(fixtures.replay(&request).unwrap().body(), "FINISHED");
+        assert!(matches!(
+            fixtures.replay(&request),
+            Err(EbioticError::NoMatchingFixture(_))
+        ));
+    }
+
+    #[test]
+    fn form_fields_match_in_any_order() {
+        let fixtures = Fixtures::new(vec![Fixture::new(
+            HttpRequest::post("http://example.org", &[("a", "1"), ("b", "2")]),
+            HttpResponse::new(200, "ok".to_string()),
+        )]);
+
+        assert!(fixtures
+            .replay(&HttpRequest::post(
+                "http://example.org",
+                &[("b", "2"), ("a", "1")]
+            ))
+            .is_ok());
+    }
+
+    #[test]
+    fn fixtures_round_trip_through_json() {
+        let mut response = HttpResponse::new(429, "".to_string());
+        response.add_header("Retry-After", "5");
+        let fixture = Fixture::new(
+            HttpRequest::post("http://example.org", &[("a", "1")]),
+            response,
+        );
+
+        let json = serde_json::to_string(&vec![fixture.clone()]).unwrap();
+        let fixtures = Fixtures::from_json(&json).unwrap();
+
+        assert_eq!(fixtures.remaining(), 1);
+        let replayed = fixtures.replay(fixture.request()).unwrap();
+        assert_eq!(replayed.header("retry-after"), Some("5"));
+    }
+}
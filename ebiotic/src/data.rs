@@ -14,16 +14,21 @@ use std::fmt::{Display, Formatter};
 pub mod dbfetch;
 pub mod ebisearch;
 
-pub use dbfetch::{dbfetchdbs::DbfetchDbs, Dbfetch, DbfetchStyle};
+pub use dbfetch::{dbfetchdbs::DbfetchDbs, Dbfetch, DbfetchStyle, DBFETCH_BATCH_SIZE};
 pub use ebisearch::{
     ebisearchdomains::EbiSearchDomains, ebisearchquery::EbiSearchQuery, EbiSearch,
 };
 
 pub use crate::core::EbioticClient;
 pub use crate::core::Service;
-
-pub const EBI_DBFETCH_ENDPOINT: &str = "https://www.ebi.ac.uk/Tools/dbfetch/";
-pub const EBI_SEARCH_ENDPOINT: &str = "https://www.ebi.ac.uk/ebisearch/ws/rest/";
+pub use crate::core::{BoxFuture, BoxedHttpClient, DynEbioticHttpClient, EbioticHttpClient};
+pub use crate::core::{CacheMode, CacheStats, ResponseCache};
+pub use crate::core::{
+    Fixture, Fixtures, HttpMethod, HttpRequest, HttpResponse, RateLimit, RateLimiter, RetryPolicy,
+    RetryableStatus,
+};
+pub use crate::core::{EBI_DBFETCH_ENDPOINT, EBI_SEARCH_ENDPOINT};
+pub use crate::errors::EbioticError;
 
 /// The `DataReturnFormats` enum is used to specify the return format of the various data retrieval services. This is dependent on the type of data available from the database.
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -41,7 +46,7 @@ pub enum DataReturnFormats {
     PatentEquivalents,
 }
 
-/// The `DbfetchIds` struct is used to specify the IDs to be fetched from the `Dbfetch` service.
+/// The `AccessionIds` struct is used to specify the IDs to be fetched from the `Dbfetch` and `EbiSearch` services.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct AccessionIds {
     ids: Vec<String>,
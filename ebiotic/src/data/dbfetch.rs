@@ -1,15 +1,21 @@
 use super::{AccessionIds, AvailableReturnFormats, DataReturnFormats, EBI_DBFETCH_ENDPOINT};
-use crate::core::{self, EbioticClient, EbioticHttpClient, Service};
+use crate::core::{
+    self, decode_response, EbioticClient, EbioticHttpClient, EbioticResult, ErrorDecoder,
+    HttpRequest, Service,
+};
 use crate::errors::EbioticError;
 use bio::io::fasta::Record;
 use std::fmt::{Display, Formatter};
 
 pub mod dbfetchdbs;
 
+/// Dbfetch accepts at most 200 entries per request.
+pub const DBFETCH_BATCH_SIZE: usize = 200;
+
 /// The `Dbfetch` struct is used to specify the parameters for the `Dbfetch` service.
 #[derive(Debug, Clone)]
-pub struct Dbfetch {
-    pub(crate) client: EbioticClient,
+pub struct Dbfetch<C = EbioticClient> {
+    pub(crate) client: C,
     db: dbfetchdbs::DbfetchDbs,
     return_format: DataReturnFormats,
     style: DbfetchStyle,
@@ -63,13 +69,13 @@ impl DbfetchResult {
     }
 }
 
-impl Dbfetch {
+impl<C> Dbfetch<C> {
     pub fn new(
-        client: EbioticClient,
+        client: C,
         db: dbfetchdbs::DbfetchDbs,
         return_format: DataReturnFormats,
         style: DbfetchStyle,
-    ) -> Dbfetch {
+    ) -> Dbfetch<C> {
         Dbfetch {
             client,
             db,
@@ -101,13 +107,33 @@ impl Dbfetch {
     pub fn style(&self) -> &DbfetchStyle {
         &self.style
     }
+
+    pub fn set_client(&mut self, client: C) {
+        self.client = client;
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
 }
 
-impl Service for Dbfetch {
+impl<C: EbioticHttpClient> Service for Dbfetch<C> {
     type ResultType = DbfetchResult;
     type InputType = AccessionIds;
 
     /// Run the `Dbfetch` service with a list of IDs.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "service_run",
+            skip_all,
+            fields(
+                service = "dbfetch",
+                endpoint = EBI_DBFETCH_ENDPOINT,
+                db = %self.db,
+            )
+        )
+    )]
     async fn run(&self, input: Self::InputType) -> Result<Self::ResultType, EbioticError> {
         if !self
             .db
@@ -123,14 +149,110 @@ impl Service for Dbfetch {
 
         log::info!("Submitting DBfetch request");
 
-        let res = self
-            .client
-            .get(&format!(
-                "{}?db={}&format={}&style={}&id={}",
-                EBI_DBFETCH_ENDPOINT, self.db, self.return_format, self.style, input
-            ))
-            .await?;
+        let url = format!(
+            "{}?db={}&format={}&style={}&id={}",
+            EBI_DBFETCH_ENDPOINT, self.db, self.return_format, self.style, input
+        );
+        let res = self.client.get(&url).await;
+        let res = match decode_response(self, res) {
+            Ok(res) => res,
+            Err(err) => {
+                // Dbfetch reports errors with a `200` status, so the error body may have been cached.
+                self.client.evict(&HttpRequest::get(&url));
+                return Err(err);
+            }
+        };
 
         Ok(DbfetchResult::new(res))
     }
 }
+
+impl<C: EbioticHttpClient> Dbfetch<C> {
+    /// Run the `Dbfetch` service with any number of IDs, split into requests of at most `DBFETCH_BATCH_SIZE` IDs.
+    /// A batch with none of its entries found is left out of the results rather than failing the others.
+    pub async fn run_batched(&self, input: AccessionIds) -> EbioticResult<Vec<DbfetchResult>> {
+        let mut results = Vec::new();
+        for batch in input.ids().chunks(DBFETCH_BATCH_SIZE) {
+            match self.run(AccessionIds::new(batch.to_vec())).await {
+                Ok(result) => results.push(result),
+                Err(EbioticError::DbfetchError { code: 12, .. }) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(results)
+    }
+}
+
+impl<C> ErrorDecoder for Dbfetch<C> {
+    /// Recognise the plain text errors returned by Dbfetch, e.g. `ERROR 12 No entries found.`
+    fn decode_error(&self, body: &str) -> Option<EbioticError> {
+        let line = body.trim_start().lines().next()?;
+        let rest = line.strip_prefix("ERROR ")?;
+        let (code, message) = rest.split_once(' ').unwrap_or((rest, ""));
+        let code = code.parse::<u32>().ok()?;
+        Some(EbioticError::DbfetchError {
+            code,
+            message: message.trim().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Fixture, Fixtures, HttpResponse};
+
+    #[test]
+    fn decode_error_recognises_dbfetch_errors() {
+        let dbfetch = Dbfetch::default();
+
+        match dbfetch.decode_error("ERROR 12 No entries found.\n") {
+            Some(EbioticError::DbfetchError { code, message }) => {
+                assert_eq!(code, 12);
+                assert_eq!(message, "No entries found.");
+            }
+            other => panic!("Expected DbfetchError, got {:?}", other),
+        }
+        assert!(dbfetch
+            .decode_error(">ENA|M10051|M10051.1 Human insulin receptor mRNA\nGGGGGGCTGCGCGGCCGGGTCGGTGCGCACACGAGAAGGACGCGCGGCCCCCAGCG")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn run_batched_skips_batches_without_entries() {
+        let ids: Vec<String> = (0..=DBFETCH_BATCH_SIZE)
+            .map(|i| format!("X{}", i))
+            .collect();
+        let url = |ids: &[String]| {
+            format!(
+                "{}?db=ena_sequence&format=fasta&style=raw&id={}",
+                EBI_DBFETCH_ENDPOINT,
+                ids.join(",")
+            )
+        };
+        let client = EbioticClient::replay(Fixtures::new(vec![
+            Fixture::new(
+                HttpRequest::get(&url(&ids[..DBFETCH_BATCH_SIZE])),
+                HttpResponse::new(200, "ERROR 12 No entries found.\n".to_string()),
+            ),
+            Fixture::new(
+                HttpRequest::get(&url(&ids[DBFETCH_BATCH_SIZE..])),
+                HttpResponse::new(200, ">ENA|X200|X200.1 test\nACGT\n".to_string()),
+            ),
+        ]));
+        let dbfetch = Dbfetch::new(
+            client,
+            dbfetchdbs::DbfetchDbs::EnaSequence,
+            DataReturnFormats::Fasta,
+            DbfetchStyle::Raw,
+        );
+
+        let results = dbfetch.run_batched(AccessionIds::new(ids)).await.unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].clone().into_records().unwrap()[0].id(),
+            "ENA|X200|X200.1"
+        );
+    }
+}
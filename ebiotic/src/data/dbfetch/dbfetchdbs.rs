@@ -4,7 +4,7 @@ use std::fmt::{Display, Formatter};
 // WILL HOPEFULLY REPLACE ALL OF THIS ONCE THE MACROS ARE WORKING
 
 /// The `DbfetchDbs` enum is used to specify the databases to be fetched from the `Dbfetch` service.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum DbfetchDbs {
     AlphaFoldDb,
     Cdp,
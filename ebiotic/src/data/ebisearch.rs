@@ -8,8 +8,8 @@ pub mod ebisearchquery;
 
 /// The `EbiSearch` struct is used to query the EBI Search service.
 #[derive(Debug, Clone)]
-pub struct EbiSearch {
-    pub(crate) client: EbioticClient,
+pub struct EbiSearch<C = EbioticClient> {
+    pub(crate) client: C,
     domain: ebisearchdomains::EbiSearchDomains,
     return_format: DataReturnFormats,
 }
@@ -42,9 +42,9 @@ impl EbiSearchResult {
     }
 }
 
-impl EbiSearch {
+impl<C: EbioticHttpClient> EbiSearch<C> {
     pub fn new(
-        client: EbioticClient,
+        client: C,
         domain: ebisearchdomains::EbiSearchDomains,
         return_format: DataReturnFormats,
     ) -> Self {
@@ -168,7 +168,7 @@ impl EbiSearch {
     }
 
     /// Set the client for the EBI Search service.
-    pub fn set_client(&mut self, client: EbioticClient) {
+    pub fn set_client(&mut self, client: C) {
         self.client = client;
     }
 
@@ -183,16 +183,28 @@ impl EbiSearch {
     }
 
     /// Get the client!
-    pub fn client(self) -> EbioticClient {
+    pub fn client(self) -> C {
         self.client
     }
 }
 
-impl Service for EbiSearch {
+impl<C: EbioticHttpClient> Service for EbiSearch<C> {
     type ResultType = EbiSearchResult;
     type InputType = ebisearchquery::EbiSearchQuery;
 
-    async fn run(&self, mut query: Self::InputType) -> EbioticResult<Self::ResultType> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "service_run",
+            skip_all,
+            fields(
+                service = "ebisearch",
+                endpoint = EBI_SEARCH_ENDPOINT,
+                domain = %self.domain,
+            )
+        )
+    )]
+    async fn run(&self, query: Self::InputType) -> EbioticResult<Self::ResultType> {
         let query_url = query.build(&self.return_format.to_string())?;
         let url = self.concat_url(&query_url);
 
@@ -203,9 +215,9 @@ impl Service for EbiSearch {
     }
 }
 
-impl EbiSearch {
-    fn concat_url(&self, query: &String) -> String {
-        let mut url = format!("{}", EBI_SEARCH_ENDPOINT);
+impl<C> EbiSearch<C> {
+    fn concat_url(&self, query: &str) -> String {
+        let mut url = EBI_SEARCH_ENDPOINT.to_string();
 
         match self.domain {
             ebisearchdomains::EbiSearchDomains::All => {}
@@ -214,7 +226,7 @@ impl EbiSearch {
             }
         }
 
-        url.push_str(&format!("{}", query));
-        return url;
+        url.push_str(query);
+        url
     }
 }
@@ -173,9 +173,9 @@ impl EbiSearchQuery {
 
         if let Some(filters) = &self.filters {
             if !query_included {
-                url.push_str("?")
+                url.push('?')
             } else {
-                url.push_str("&")
+                url.push('&')
             }
             let filter_str = filters
                 .iter()
@@ -203,8 +203,7 @@ mod tests {
 
     #[test]
     fn build_query_with_single_command() {
-        let mut query = Vec::new();
-        query.push(QueryCommand::QueryStr("test".to_string()));
+        let query = vec![QueryCommand::QueryStr("test".to_string())];
         let search_query = EbiSearchQuery::new(query, None).unwrap();
         let result = search_query.build(&"test".to_string()).unwrap();
         assert_eq!(result, "?query=test&format=test");
@@ -213,9 +212,10 @@ mod tests {
     #[test]
     #[should_panic]
     fn build_query_with_multiple_commands() {
-        let mut query = Vec::new();
-        query.push(QueryCommand::QueryStr("test".to_string()));
-        query.push(QueryCommand::AutoComplete("lol".to_string()));
+        let query = vec![
+            QueryCommand::QueryStr("test".to_string()),
+            QueryCommand::AutoComplete("lol".to_string()),
+        ];
         let search_query = EbiSearchQuery::new(query, None).unwrap();
         let result = search_query.build(&"test".to_string()).unwrap();
         assert_eq!(result, "?query=testautocomplete?term=lol");
@@ -223,8 +223,7 @@ mod tests {
 
     #[test]
     fn build_query_with_filters() {
-        let mut query = Vec::new();
-        query.push(QueryCommand::QueryStr("test".to_string()));
+        let query = vec![QueryCommand::QueryStr("test".to_string())];
         let mut search_query = EbiSearchQuery::new(query, None).unwrap();
         search_query.add_filter(EbiSearchFilter::Size(10));
         let result = search_query.build(&"test".to_string()).unwrap();
@@ -233,8 +232,7 @@ mod tests {
 
     #[test]
     fn build_query_with_sort_order() {
-        let mut query = Vec::new();
-        query.push(QueryCommand::QueryStr("test".to_string()));
+        let query = vec![QueryCommand::QueryStr("test".to_string())];
         let mut search_query = EbiSearchQuery::new(query, None).unwrap();
         let mut sort = HashMap::new();
         sort.insert("field".to_string(), SortOrder::Ascending);
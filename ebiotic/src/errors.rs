@@ -13,9 +13,43 @@ pub enum EbioticError {
     #[error("Unable to handle IO: {0}")]
     IoError(#[from] std::io::Error),
 
+    // HTTP and service responses
+    #[error("Request to {url} failed with status {status}: {body}")]
+    HttpStatus {
+        status: u16,
+        url: String,
+        body: String,
+    },
+    #[error("EBI Job Dispatcher error: {0}")]
+    JobDispatcherError(String),
+    #[error("Dbfetch error {code}: {message}")]
+    DbfetchError { code: u32, message: String },
+    #[error("NCBI BLAST error: {0}")]
+    BlastError(String),
+    #[error("Job failed: {0}")]
+    JobFailed(String),
+    #[error("No results were found.")]
+    NoResults,
+    #[error("Unable to parse service response: {0}")]
+    MalformedResponse(String),
+    #[error("Gave up waiting for job {job} after {polls} polls ({elapsed:?})")]
+    PollTimeout {
+        job: String,
+        polls: u32,
+        elapsed: std::time::Duration,
+    },
+    #[error("Job {0} was cancelled")]
+    Cancelled(String),
+    #[error("Invalid job handle: {0}")]
+    InvalidJobHandle(String),
+    #[error("{0} does not support cancelling jobs")]
+    CancelNotSupported(String),
+    #[error("No cached response for {0}")]
+    CacheMiss(String),
+    #[error("No recorded fixture matches request: {0}")]
+    NoMatchingFixture(String),
+
     // Custom
-    #[error("Error: {0}")] // This is a catch-all for now
-    ServiceError(String),
     #[error("Return format {0} not available for database {1}")]
     ReturnFormatNotAvailable(String, String),
     #[error("Empty queries are not permitted. Add something!")]
@@ -24,4 +58,16 @@ pub enum EbioticError {
     TooManyQueryCommands,
     #[error("Query string/search term must be the last or only command.")]
     QueryStrOrTermNotFirst,
+    #[error("Program {0} not available for database {1}")]
+    ProgramNotAvailable(String, String),
+    #[error("Invalid BLAST parameters: {0}")]
+    InvalidBlastParameters(String),
+    #[error("Unknown BLAST tabular field: {0}")]
+    UnknownTabularField(String),
+    #[error("Invalid Clustal Omega parameters: {0}")]
+    InvalidClustaloParameters(String),
+    #[error("Invalid multiple alignment: {0}")]
+    InvalidAlignment(String),
+    #[error("Alignment format {0} is not supported")]
+    UnsupportedAlignmentFormat(String),
 }
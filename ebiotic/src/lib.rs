@@ -23,12 +23,13 @@
 //! ## Examples
 //!
 //! The idea of this crate is that all the services, regardless of the type, use a similar interface. This is achieved (currently) by using the `core::Service` trait and accompanying utilities.
-//! Therefore, this trait must be in scope to use any of the services. This can be done by importing it directly or by importing an entire module that contains it. Each utility currently initialises
-//! its own `reqwest::Client` which is used to make the requests. This was done for fine-grained control, but I hope to provide a way to use a custom client in the future.
+//! Therefore, this trait must be in scope to use any of the services. This can be done by importing it directly or by importing an entire module that contains it. Each service is generic over
+//! the `EbioticHttpClient` trait and defaults to `EbioticClient`, so an instrumented client, a caching wrapper or a test double can be used instead. `BoxedHttpClient`
+//! erases the client type, so services of different types can share one client.
 //!
 //! This is an example of how to use the `Dbfetch` service to query the European Nucleotide Archive (ENA) for a sequence in FASTA format:
 //!
-//! ```rust
+//! ```rust,no_run
 //! use ebiotic::data::*;
 //!
 //! #[tokio::main]
@@ -38,7 +39,7 @@
 //!
 //!    let client = EbioticClient::default(); // <- Uses an ARC under the hood, so cloning is cheap and ensures a single client pool. It also means that it is thread safe!
 //!    let dbfetch = Dbfetch::new(client.clone(), DbfetchDbs::EnaSequence, DataReturnFormats::Fasta, DbfetchStyle::Raw);
-//!    let ids = DbfetchIds::new(vec!["M10051".to_string(), "M10052".to_string()]);
+//!    let ids = AccessionIds::new(vec!["M10051".to_string(), "M10052".to_string()]);
 //!
 //!    // The `Dbfetch` service returns a `DbfetchResult` which can be converted into a `Vec<Record>` using the `into_records` method.
 //!    // I hope to provide a more ergonomic way of doing this in the future.
@@ -53,14 +54,16 @@
 //!    // For this reason, it is recommended to manually initialize the client and clone it if you use multiple services to avoid creating multiple client pools.
 //!    let dbfetch = Dbfetch::default();
 //!
-//!    let ids = DbfetchIds::new(vec!["M10051".to_string(), "M10052".to_string()]);
+//!    let ids = AccessionIds::new(vec!["M10051".to_string(), "M10052".to_string()]);
 //!    let result = dbfetch.run(ids).await.unwrap().into_records();
 //! }
 //!```
 //!
+//! Dbfetch accepts at most `DBFETCH_BATCH_SIZE` ids per request; `run_batched` splits longer lists into several requests.
+//!
 //! And here is an example of how to use the `Blast` service to query the NCBI's sequence database:
 //!
-//! ```rust
+//! ```rust,no_run
 //! use ebiotic::tools::*;
 //!
 //! #[tokio::main]
@@ -72,19 +75,68 @@
 //! }
 //! ```
 //!
+//! The search program, database and optional URL API parameters are set with `set_program`, `set_database` and `set_params`,
+//! and invalid combinations are rejected before the search is submitted. Several queries can be searched under a single RID
+//! with `run_records`, which returns one `BlastResult` per `Record`. Results can be exported for other tools as BLAST tabular output
+//! (`write_tabular` and `write_tabular_with_comments`, with the columns chosen as `TabularField`s) or BLAST XML2 (`write_xml2`).
+//! The output of a local BLAST+ search is read into the same model with `BlastResult::from_json_file` (`-outfmt 15`),
+//! `from_xml2_file` (`-outfmt 16`) and `from_tabular_file` (`-outfmt 6` and `7`).
+//! Hits can be narrowed down with helpers such as `filter_by_evalue`, `filter_by_identity`, `filter_by_query_coverage`,
+//! `filter_by_taxid`, `best_hsps` and `dedup_by_accession`, and the full-length sequences of the hits fetched from Dbfetch
+//! with `fetch_subject_sequences`, which also reports the accessions it has no database for. `annotate_lineages` attaches the taxonomic `Lineage` of each hit, resolved through the
+//! Dbfetch `Taxonomy` database and kept in a `TaxonomyCache`, for use with `filter_by_lineage` and `lowest_common_ancestor`.
+//!
+//! The Clustal Omega options, e.g. the sequence type, the number of iterations or the output format of the alignment, are
+//! set on `Clustalo` with `set_params` and a `ClustaloParams`. `ClustaloResult::multiple_alignment` parses the Clustal, FASTA
+//! or Stockholm alignment into a `MultipleAlignment`, which can be sliced, filtered by gap fraction, summarised as a consensus
+//! and written back out in any of those formats. `ClustaloResult::parsed_tree` parses the Newick tree into a `PhyloTree`, with
+//! patristic distances, midpoint rooting and subtree extraction, and `ClustaloResult::pim` is a `PercentIdentityMatrix`
+//! which keeps the order of the sequences, for nearest-neighbour queries, threshold clustering and TSV/CSV export.
+//!
 //! ### Synchronous example
 //!
-//! Despite being designed with asynchronicity in mind, the services can also be run synchronously by blocking on the thread. In the future I hope to provide an API for this.
+//! Despite being designed with asynchronicity in mind, the services can also be run synchronously with the `blocking` feature.
+//! The `ebiotic::blocking` module wraps every service so that it is driven on an internal runtime, in the same way as `reqwest::blocking`.
+//!
+//! ```toml
+//! [dependencies]
+//! ebiotic = { version = "0.0.26", features = ["blocking"] }
+//! ```
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "blocking")]
+//! # {
+//! use ebiotic::blocking;
 //!
-//! ```rust
+//! let blast = blocking::Blast::default();
+//! let query = "MAKQVQKARKLAEQAERYDDMAAAMKAVTEQGHELSNEERNLLSVAYKNVVGARRSSWRVISSIEQKTERNEKKQQMGKEYREKIEAELQDICNDVLELLDKYLIPNATQPESKVFYLKMKGDYFRYLSEVASGDNKQTTVSNSQQAYQEAFEISKKEMQPTHPIRLGLALNFSVFYYEILNSPDRACRLAKAAFDDASLAKDAESEKNPEEIAWYQSITQ";
+//! let result = blast.run(query.to_string());
+//! # }
+//! ```
+//!
+//! ### Long running jobs
+//!
+//! `Blast` and `Clustalo` implement the `JobService` trait, which splits `run` into `submit`, `wait` and `fetch_result`.
+//! The `JobHandle` returned by `submit` can be serialised, so a job can be resumed after a restart rather than resubmitted.
+//! How often `wait` polls, and when it gives up with `EbioticError::PollTimeout`, is set per service with a `PollPolicy`.
+//! `wait_with_cancel` and `run_with_cancel` take a `CancellationToken`, which stops polling and asks the service to discard the job
+//! (NCBI BLAST deletes the search, while Job Dispatcher jobs are left to expire).
+//! Progress can be followed by giving the service a `JobObserver` with `set_observer`, either a closure taking a `&JobEvent`
+//! or the sending half of a `tokio::sync::mpsc::unbounded_channel`.
+//!
+//! ```rust,no_run
 //! use ebiotic::tools::*;
-//! use tokio;
 //!
 //! #[tokio::main]
 //! async fn main() {
 //!    let blast = Blast::default();
-//!    let query = "MAKQVQKARKLAEQAERYDDMAAAMKAVTEQGHELSNEERNLLSVAYKNVVGARRSSWRVISSIEQKTERNEKKQQMGKEYREKIEAELQDICNDVLELLDKYLIPNATQPESKVFYLKMKGDYFRYLSEVASGDNKQTTVSNSQQAYQEAFEISKKEMQPTHPIRLGLALNFSVFYYEILNSPDRACRLAKAAFDDASLAKDAESEKNPEEIAWYQSITQ";
-//!    let result = tokio::task::block_in_place(|| blast.run(query.to_string()));
+//!    let handle = blast.submit("MAKQVQKARKLAEQAERYDDMAAAMKAVTEQGHELSNEERNLLSVAYKNVVGARRSSWRVISSIEQKTERNEKKQQMGKEYREKIEAELQDICNDVLELLDKYLIPNATQPESKVFYLKMKGDYFRYLSEVASGDNKQTTVSNSQQAYQEAFEISKKEMQPTHPIRLGLALNFSVFYYEILNSPDRACRLAKAAFDDASLAKDAESEKNPEEIAWYQSITQ".to_string()).await.unwrap();
+//!    let saved = handle.to_json().unwrap(); // <- Store this somewhere safe
+//!
+//!    // ...later
+//!    let handle = JobHandle::from_json(&saved).unwrap();
+//!    handle.wait(&blast).await.unwrap();
+//!    let result = handle.fetch_result(&blast).await.unwrap();
 //! }
 //! ```
 //!
@@ -109,11 +161,112 @@
 //!    );
 //!
 //!    let dbfetch = Dbfetch::new(client, DbfetchDbs::EnaSequence, DataReturnFormats::Fasta, DbfetchStyle::Raw);
-//!    let ids = DbfetchIds::new(vec!["M10051".to_string(), "M10052".to_string()]);
+//!    let ids = AccessionIds::new(vec!["M10051".to_string(), "M10052".to_string()]);
+//!    let result = dbfetch.run(ids).await.unwrap().into_records();
+//! }
+//!```
+//!
+//! ### Retrying transient failures
+//!
+//! Every idempotent request made through an `EbioticClient` (including the polling of long running jobs) is retried according to its `RetryPolicy`.
+//! By default, connection errors, `5xx`, `408` and `429` responses are retried up to four times with exponential backoff, honouring any `Retry-After` header sent by the server up to the maximum delay.
+//! Job submissions are sent once, so a search is never started twice.
+//!
+//!```rust
+//! use ebiotic::tools::*;
+//! use std::time::Duration;
+//!
+//! let mut policy = RetryPolicy::default();
+//! policy.set_max_attempts(6);
+//! policy.set_base_delay(Duration::from_secs(5));
+//!
+//! let mut client = EbioticClient::default();
+//! client.set_retry_policy(policy);
+//!
+//! let blast = Blast::new(
+//!     client,
+//!     BLAST_ENDPOINT.to_string(),
+//!     BlastProgram::Blastp,
+//!     BlastDatabase::Nr,
+//!     Some(BlastMatrix::Blosum62),
+//!     10,
+//!     "".to_string(),
+//!     "".to_string(),
+//! );
+//!```
+//!
+//! ### Rate limiting
+//!
+//! Requests are also throttled per host by a token bucket `RateLimiter`. By default NCBI BLAST is limited to one request every 10 seconds
+//! and one status poll per search a minute, the NCBI E-utilities to 3 requests per second (10 once an API key is set) and the EBI to
+//! 10 requests per second. Clones of a client share the same limits and buckets.
+//!
+//!```rust
+//! use ebiotic::tools::*;
+//!
+//! let mut client = EbioticClient::default();
+//! client.set_ncbi_api_key("my-ncbi-api-key".to_string());
+//!
+//! let mut limiter = client.rate_limiter().clone();
+//! limiter.set_limit("www.ebi.ac.uk", RateLimit::per_second(2));
+//! client.set_rate_limiter(limiter);
+//!```
+//!
+//! ### Tracing
+//!
+//! With the `tracing` feature, every `Service::run`, job submission, status poll and HTTP request is wrapped in a [tracing](https://docs.rs/tracing) span.
+//! The spans carry structured fields (`service`, `endpoint`, `job_id`, `attempt`, `status`, `latency_ms`, `bytes`), so timings can be exported
+//! with any `tracing` subscriber, e.g. to an OpenTelemetry collector. The `log` output is unchanged.
+//!
+//! ### Response caching
+//!
+//! A `ResponseCache` stores successful responses on disk, keyed on the method, URL and form body of each request. By default
+//! only Dbfetch and EBI Search responses are cached, so repeated lookups of the same accessions are served without using the network.
+//! In `CacheMode::CacheOnly` the network is never used, expired entries are still served, and uncached requests fail with `EbioticError::CacheMiss`.
+//!
+//! ```rust,no_run
+//! use ebiotic::data::*;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!    let cache = ResponseCache::new("ebiotic-cache").unwrap();
+//!    let mut client = EbioticClient::default();
+//!    client.set_cache(cache.clone());
+//!
+//!    let dbfetch = Dbfetch::new(client, DbfetchDbs::EnaSequence, DataReturnFormats::Fasta, DbfetchStyle::Raw);
+//!    let ids = AccessionIds::new(vec!["M10051".to_string()]);
+//!    dbfetch.run(ids.clone()).await.unwrap();
+//!    dbfetch.run(ids).await.unwrap(); // <- Served from the cache
+//!
+//!    println!("Cache hit rate: {}", cache.stats().hit_rate());
+//! }
+//!```
+//!
+//! ### Offline testing
+//!
+//! An `EbioticClient` can record every interaction to a JSON fixture file, and a replaying client can later serve those
+//! fixtures without touching the network. Retrying and rate limiting still apply, so scripted failures can be replayed too.
+//!
+//!```rust,no_run
+//! use ebiotic::data::*;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!    // Record a live session...
+//!    let client = EbioticClient::record(reqwest::Client::new(), "tests/fixtures/dbfetch.json");
+//!    let dbfetch = Dbfetch::new(client, DbfetchDbs::EnaSequence, DataReturnFormats::Fasta, DbfetchStyle::Raw);
+//!    let ids = AccessionIds::new(vec!["M10051".to_string()]);
+//!    dbfetch.run(ids.clone()).await.unwrap();
+//!
+//!    // ...and replay it offline.
+//!    let client = EbioticClient::replay(Fixtures::from_file("tests/fixtures/dbfetch.json").unwrap());
+//!    let dbfetch = Dbfetch::new(client, DbfetchDbs::EnaSequence, DataReturnFormats::Fasta, DbfetchStyle::Raw);
 //!    let result = dbfetch.run(ids).await.unwrap().into_records();
 //! }
 //!```
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod core;
 pub mod data;
 mod errors;
@@ -10,11 +10,35 @@
 pub mod blast;
 pub mod msa;
 
-pub use blast::{Blast, BlastResult, Description, Hit, Hsp};
-pub use msa::{Clustalo, ClustaloResult};
+pub use blast::{
+    blastdatabases::BlastDatabase,
+    blastfetch::SubjectSequences,
+    blastformat::TabularField,
+    blastparams::{BlastMatrix, BlastParams, BlastProgram, BlastSequenceType},
+    blasttaxonomy::{Lineage, Taxon, TaxonomyCache},
+    Blast, BlastResult, Description, Hit, Hsp, SearchStatistics,
+};
+pub use msa::{
+    clustaloparams::{ClustaloOrder, ClustaloOutputFormat, ClustaloParams, ClustaloSequenceType},
+    multiplealignment::MultipleAlignment,
+    percentidentitymatrix::PercentIdentityMatrix,
+    phylotree::{PhyloNode, PhyloTree},
+    Clustalo, ClustaloResult,
+};
 
 pub use crate::core::EbioticClient;
 pub use crate::core::Service;
+pub use crate::core::{BoxFuture, BoxedHttpClient, DynEbioticHttpClient, EbioticHttpClient};
+pub use crate::core::{CacheMode, CacheStats, ResponseCache};
+pub use crate::core::{
+    CancellationToken, JobEvent, JobHandle, JobObserver, JobService, JobState, PollPolicy,
+    PollStatus,
+};
+pub use crate::core::{
+    Fixture, Fixtures, HttpMethod, HttpRequest, HttpResponse, RateLimit, RateLimiter, RetryPolicy,
+    RetryableStatus,
+};
+pub use crate::errors::EbioticError;
 pub use bio::io::fasta::{Reader, Record};
 
 pub const EBI_TOOLS_ENDPOINT: &str = "https://www.ebi.ac.uk/Tools/services/rest/";
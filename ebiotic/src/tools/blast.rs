@@ -3,22 +3,54 @@ use bio::io::fasta::Record;
 use serde::de::Deserializer;
 use serde::Deserialize;
 use serde_json::Value;
+use std::fmt::Write;
+use std::sync::Arc;
 
 use super::BLAST_ENDPOINT;
-use crate::core::{EbioticClient, EbioticHttpClient, PollStatus, PollableService, Service};
+use crate::core::{
+    decode_response, report_fetch, run_job, CancellationToken, EbioticClient, EbioticHttpClient,
+    EbioticResult, ErrorDecoder, JobEvent, JobHandle, JobObserver, JobService, PollPolicy,
+    PollStatus, PollableService, Service,
+};
 use crate::errors::EbioticError;
 
+pub mod blastdatabases;
+pub mod blastfetch;
+pub mod blastfilters;
+pub mod blastformat;
+pub mod blastparams;
+pub mod blastparsers;
+pub mod blasttaxonomy;
+
+use blastdatabases::BlastDatabase;
+use blastparams::{BlastMatrix, BlastParams, BlastProgram};
+use blasttaxonomy::Lineage;
+
+trait AvailablePrograms {
+    fn available_programs(&self) -> Vec<BlastProgram>;
+}
+
 /// The `Description` struct is used to specify the description of the hit.
+///
+/// Databases without taxonomy information report a `taxid` of `0` and an empty `sciname`.
+/// The `lineage` is only set once the result has been annotated with `BlastResult::annotate_lineages`.
 #[derive(Deserialize, Debug, Clone)]
 pub struct Description {
     id: String,
     accession: String,
     title: String,
+    #[serde(default)]
     taxid: u32,
+    #[serde(default)]
     sciname: String,
+    #[serde(skip)]
+    lineage: Option<Lineage>,
 }
 
 /// The `Hsp` struct is used to specify the High-scoring Segment Pair (HSP) of the hit.
+///
+/// Coordinates are 1-based and inclusive. On the minus strand `hit_from` is greater than `hit_to`.
+/// Strands are only reported by nucleotide comparisons, and frames by translated searches.
 #[derive(Deserialize, Debug, Clone)]
 pub struct Hsp {
     num: u32,
@@ -26,8 +58,28 @@ pub struct Hsp {
     score: u32,
     evalue: f64,
     identity: u32,
+    #[serde(default)]
+    positive: u32,
+    query_from: u32,
+    query_to: u32,
+    query_strand: Option<String>,
+    query_frame: Option<i32>,
+    hit_from: u32,
+    hit_to: u32,
+    hit_strand: Option<String>,
+    hit_frame: Option<i32>,
+    align_len: u32,
+    #[serde(default)]
+    gaps: u32,
+    qseq: String,
     #[serde(deserialize_with = "deserialize_hseq")]
     hseq: Record,
+    midline: String,
+    /// The mismatch and gap open counts read from tabular output, which has no aligned sequences to count them from.
+    #[serde(skip)]
+    mismatch_count: Option<u32>,
+    #[serde(skip)]
+    gap_open_count: Option<u32>,
 }
 
 /// The `Hit` struct is used to specify the hit from the BLAST ebisearch.
@@ -39,25 +91,50 @@ pub struct Hit {
     hsps: Vec<Hsp>,
 }
 
+/// The `SearchStatistics` struct is used to specify the statistics of the database search.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SearchStatistics {
+    db_num: u64,
+    db_len: u64,
+    hsp_len: u64,
+    eff_space: u64,
+    kappa: f64,
+    lambda: f64,
+    entropy: f64,
+}
+
 /// The `BlastResult` struct is used to specify the result of the BLAST ebisearch.
 #[derive(Deserialize, Debug, Clone)]
 pub struct BlastResult {
     query_id: String,
+    #[serde(default)]
     query_title: String,
     query_len: u32,
     hits: Vec<Hit>,
+    stat: Option<SearchStatistics>,
+    message: Option<String>,
+    // Taken from the enclosing report rather than the search
+    #[serde(skip)]
+    program: Option<String>,
+    #[serde(skip)]
+    version: Option<String>,
+    #[serde(skip)]
+    database: Option<String>,
 }
 
 /// The `Blast` struct is used to specify the parameters for the `Blast` service.
-pub struct Blast {
-    pub(crate) client: EbioticClient,
+pub struct Blast<C = EbioticClient> {
+    pub(crate) client: C,
     endpoint: String,
-    program: String,
-    database: String,
-    matrix: String,
+    program: BlastProgram,
+    database: BlastDatabase,
+    matrix: Option<BlastMatrix>,
     hitlist_size: u32,
     email: String,
     tool: String,
+    params: BlastParams,
+    poll_policy: PollPolicy,
+    observer: Option<Arc<dyn JobObserver>>,
 }
 
 fn deserialize_hseq<'de, D>(deserializer: D) -> Result<Record, D::Error>
@@ -80,27 +157,31 @@ impl Default for Blast {
         Blast {
             client: EbioticClient::default(),
             endpoint: BLAST_ENDPOINT.to_string(),
-            program: "blastp".to_string(),
-            database: "nr".to_string(),
-            matrix: "BLOSUM62".to_string(),
+            program: BlastProgram::Blastp,
+            database: BlastDatabase::Nr,
+            matrix: Some(BlastMatrix::Blosum62),
             hitlist_size: 10,
             email: "".to_string(),
             tool: "".to_string(),
+            params: BlastParams::default(),
+            poll_policy: PollPolicy::blast(),
+            observer: None,
         }
     }
 }
 
-impl Blast {
+impl<C> Blast<C> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        client: EbioticClient,
+        client: C,
         endpoint: String,
-        program: String,
-        database: String,
-        matrix: String,
+        program: BlastProgram,
+        database: BlastDatabase,
+        matrix: Option<BlastMatrix>,
         hitlist_size: u32,
         email: String,
         tool: String,
-    ) -> Blast {
+    ) -> Blast<C> {
         Blast {
             client,
             endpoint,
@@ -110,6 +191,9 @@ impl Blast {
             hitlist_size,
             email,
             tool,
+            params: BlastParams::default(),
+            poll_policy: PollPolicy::blast(),
+            observer: None,
         }
     }
 
@@ -117,15 +201,17 @@ impl Blast {
         self.endpoint = endpoint;
     }
 
-    pub fn set_program(&mut self, program: String) {
+    /// Set the search program. Nucleotide comparisons do not use a matrix, so clear it with `set_matrix(None)`.
+    pub fn set_program(&mut self, program: BlastProgram) {
         self.program = program;
     }
 
-    pub fn set_database(&mut self, database: String) {
+    pub fn set_database(&mut self, database: BlastDatabase) {
         self.database = database;
     }
 
-    pub fn set_matrix(&mut self, matrix: String) {
+    /// Set the scoring matrix of protein comparisons. `None` uses the server default.
+    pub fn set_matrix(&mut self, matrix: Option<BlastMatrix>) {
         self.matrix = matrix;
     }
 
@@ -140,6 +226,56 @@ impl Blast {
     pub fn set_tool(&mut self, tool: String) {
         self.tool = tool;
     }
+
+    /// Set the optional search parameters.
+    pub fn set_params(&mut self, params: BlastParams) {
+        self.params = params;
+    }
+
+    /// Set the policy used to poll the status of submitted searches.
+    pub fn set_poll_policy(&mut self, poll_policy: PollPolicy) {
+        self.poll_policy = poll_policy;
+    }
+
+    /// Set an observer to receive the lifecycle events of submitted searches.
+    pub fn set_observer<O: JobObserver + 'static>(&mut self, observer: O) {
+        self.observer = Some(Arc::new(observer));
+    }
+
+    pub fn set_client(&mut self, client: C) {
+        self.client = client;
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    pub fn program(&self) -> &BlastProgram {
+        &self.program
+    }
+
+    pub fn database(&self) -> &BlastDatabase {
+        &self.database
+    }
+
+    pub fn matrix(&self) -> &Option<BlastMatrix> {
+        &self.matrix
+    }
+
+    pub fn params(&self) -> &BlastParams {
+        &self.params
+    }
+
+    /// Check the program, database, matrix and parameters can be used together, without submitting a search.
+    pub fn validate(&self) -> EbioticResult<()> {
+        if !self.database.available_programs().contains(&self.program) {
+            return Err(EbioticError::ProgramNotAvailable(
+                format!("{:?}", self.program),
+                self.database.to_string(),
+            ));
+        }
+        self.params.validate(&self.program, &self.matrix)
+    }
 }
 
 impl BlastResult {
@@ -158,6 +294,65 @@ impl BlastResult {
     pub fn hits(&self) -> &Vec<Hit> {
         &self.hits
     }
+
+    /// The statistics of the search, if the server reported them.
+    pub fn stat(&self) -> &Option<SearchStatistics> {
+        &self.stat
+    }
+
+    /// A message from the server about the search, e.g. `"No hits found"`.
+    pub fn message(&self) -> &Option<String> {
+        &self.message
+    }
+
+    /// The program the search was run with, e.g. `"blastp"`.
+    pub fn program(&self) -> &Option<String> {
+        &self.program
+    }
+
+    /// The version of BLAST the search was run with, e.g. `"BLASTP 2.14.1+"`.
+    pub fn version(&self) -> &Option<String> {
+        &self.version
+    }
+
+    /// The database searched, e.g. `"nr"`.
+    pub fn database(&self) -> &Option<String> {
+        &self.database
+    }
+}
+
+impl SearchStatistics {
+    /// The number of sequences in the database.
+    pub fn db_num(&self) -> &u64 {
+        &self.db_num
+    }
+
+    /// The total length of the database sequences.
+    pub fn db_len(&self) -> &u64 {
+        &self.db_len
+    }
+
+    /// The length adjustment applied to the query and database.
+    pub fn hsp_len(&self) -> &u64 {
+        &self.hsp_len
+    }
+
+    /// The effective search space used to calculate e-values.
+    pub fn eff_space(&self) -> &u64 {
+        &self.eff_space
+    }
+
+    pub fn kappa(&self) -> &f64 {
+        &self.kappa
+    }
+
+    pub fn lambda(&self) -> &f64 {
+        &self.lambda
+    }
+
+    pub fn entropy(&self) -> &f64 {
+        &self.entropy
+    }
 }
 
 impl Hit {
@@ -199,9 +394,101 @@ impl Hsp {
         &self.identity
     }
 
+    pub fn positive(&self) -> &u32 {
+        &self.positive
+    }
+
+    pub fn query_from(&self) -> &u32 {
+        &self.query_from
+    }
+
+    pub fn query_to(&self) -> &u32 {
+        &self.query_to
+    }
+
+    /// The strand of the query, `"Plus"` or `"Minus"`, for nucleotide comparisons.
+    pub fn query_strand(&self) -> &Option<String> {
+        &self.query_strand
+    }
+
+    /// The reading frame of a translated query.
+    pub fn query_frame(&self) -> &Option<i32> {
+        &self.query_frame
+    }
+
+    pub fn hit_from(&self) -> &u32 {
+        &self.hit_from
+    }
+
+    pub fn hit_to(&self) -> &u32 {
+        &self.hit_to
+    }
+
+    /// The strand of the hit, `"Plus"` or `"Minus"`, for nucleotide comparisons.
+    pub fn hit_strand(&self) -> &Option<String> {
+        &self.hit_strand
+    }
+
+    /// The reading frame of a translated hit.
+    pub fn hit_frame(&self) -> &Option<i32> {
+        &self.hit_frame
+    }
+
+    pub fn align_len(&self) -> &u32 {
+        &self.align_len
+    }
+
+    pub fn gaps(&self) -> &u32 {
+        &self.gaps
+    }
+
+    /// The aligned query sequence, including gaps.
+    pub fn qseq(&self) -> &String {
+        &self.qseq
+    }
+
+    /// The aligned hit sequence, including gaps.
     pub fn hseq(&self) -> &Record {
         &self.hseq
     }
+
+    /// The line between the aligned sequences, marking identities and positive substitutions.
+    pub fn midline(&self) -> &String {
+        &self.midline
+    }
+
+    /// The percentage of aligned positions which are identical.
+    pub fn percent_identity(&self) -> f64 {
+        if self.align_len == 0 {
+            0.0
+        } else {
+            100.0 * self.identity as f64 / self.align_len as f64
+        }
+    }
+
+    /// The number of aligned positions which are neither identical nor gaps.
+    pub fn mismatches(&self) -> u32 {
+        self.mismatch_count
+            .unwrap_or_else(|| self.align_len.saturating_sub(self.identity + self.gaps))
+    }
+
+    /// The number of gaps opened in either sequence, counting each run of `-` once.
+    pub fn gap_opens(&self) -> u32 {
+        self.gap_open_count
+            .unwrap_or_else(|| gap_runs(self.qseq.as_bytes()) + gap_runs(self.hseq.seq()))
+    }
+}
+
+fn gap_runs(seq: &[u8]) -> u32 {
+    let mut runs = 0;
+    let mut in_gap = false;
+    for &c in seq {
+        if c == b'-' && !in_gap {
+            runs += 1;
+        }
+        in_gap = c == b'-';
+    }
+    runs
 }
 
 impl Description {
@@ -224,98 +511,398 @@ impl Description {
     pub fn sciname(&self) -> &String {
         &self.sciname
     }
+
+    pub fn lineage(&self) -> &Option<Lineage> {
+        &self.lineage
+    }
 }
 
-impl Service for Blast {
+impl<C: EbioticHttpClient> Service for Blast<C> {
     type ResultType = BlastResult;
     type InputType = String;
 
     /// Run the `Blast` service with a query.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "service_run",
+            skip_all,
+            fields(
+                service = "blast",
+                endpoint = %self.endpoint,
+            )
+        )
+    )]
     async fn run(&self, input: Self::InputType) -> Result<Self::ResultType, EbioticError> {
+        self.run_with_cancel(input, &CancellationToken::new()).await
+    }
+}
+
+impl<C: EbioticHttpClient> JobService for Blast<C> {
+    fn service_name(&self) -> &'static str {
+        "blast"
+    }
+
+    fn poll_policy(&self) -> &PollPolicy {
+        &self.poll_policy
+    }
+
+    fn notify(&self, event: &JobEvent) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(event);
+        }
+    }
+
+    /// Submit a query, returning a handle holding the RID.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "submit",
+            skip_all,
+            fields(
+                service = "blast",
+                endpoint = %self.endpoint,
+                job_id = tracing::field::Empty,
+            )
+        )
+    )]
+    async fn submit(&self, input: Self::InputType) -> EbioticResult<JobHandle> {
         log::info!("Running BLAST ebisearch");
+        self.validate()?;
 
-        let response = self
+        let form = self.put_form(input);
+        let form: Vec<(&str, &str)> = form.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let response = self.client.post_form(&self.endpoint, &form).await;
+        let response = decode_response(self, response)?;
+
+        let (rid, rtoe) = self.fetch_ridrtoe(&response)?;
+
+        log::info!("RID: {}, RTOE: {}", rid, rtoe);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("job_id", rid.as_str());
+        self.notify(&JobEvent::Submitted {
+            job_id: rid.clone(),
+        });
+
+        Ok(JobHandle::new(
+            self.service_name().to_string(),
+            rid,
+            self.endpoint.clone(),
+            rtoe.trim().parse().ok(),
+        ))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "status",
+            skip_all,
+            fields(
+                service = "blast",
+                job_id = %handle.job_id(),
+            )
+        )
+    )]
+    async fn status(&self, handle: &JobHandle) -> EbioticResult<PollStatus> {
+        let search_info = self
             .client
             .post_form(
-                &self.endpoint,
+                handle.endpoint(),
                 &[
-                    ("CMD", "Put"),
-                    ("PROGRAM", &self.program),
-                    ("DATABASE", &self.database),
-                    ("MATRIX", &self.matrix),
-                    ("HITLIST_SIZE", &self.hitlist_size.to_string()),
-                    ("EMAIL", &self.email),
-                    ("TOOL", &self.tool),
-                    ("QUERY", &input),
+                    ("CMD", "Get"),
+                    ("FORMAT_OBJECT", "SearchInfo"),
+                    ("RID", handle.job_id()),
                 ],
             )
-            .await?;
+            .await;
+        let search_info = decode_response(self, search_info)?;
+        Ok((&self).poll_status(&search_info))
+    }
 
-        let (rid, rtoe) = &self.fetch_ridrtoe(&response);
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "fetch_result",
+            skip_all,
+            fields(
+                service = "blast",
+                job_id = %handle.job_id(),
+            )
+        )
+    )]
+    async fn fetch_result(&self, handle: &JobHandle) -> EbioticResult<Self::ResultType> {
+        let result = async {
+            let search_results = self.fetch_json(handle).await?;
+            self.parse_raw_results(&search_results)
+        }
+        .await;
+        report_fetch(self, handle, result)
+    }
 
-        log::info!("RID: {}, RTOE: {}", rid, rtoe);
+    /// Delete the search from the BLAST server.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "cancel",
+            skip_all,
+            fields(
+                service = "blast",
+                job_id = %handle.job_id(),
+            )
+        )
+    )]
+    async fn cancel(&self, handle: &JobHandle) -> EbioticResult<()> {
+        log::info!("Deleting BLAST search with RID: {}", handle.job_id());
 
-        let _search_info = self
+        let response = self
             .client
-            .poll(
-                &self.endpoint,
-                Some(&[
-                    ("CMD", "Get"),
-                    ("FORMAT_OBJECT", "SearchInfo"),
-                    ("RID", &rid),
-                ]),
-                &self,
+            .post_form(
+                handle.endpoint(),
+                &[("CMD", "Delete"), ("RID", handle.job_id())],
             )
-            .await?;
+            .await;
+        decode_response(self, response)?;
+        Ok(())
+    }
+}
+
+impl<C: EbioticHttpClient> Blast<C> {
+    /// Submit several query sequences as a single multi-FASTA search, returning a handle holding the RID
+    /// and the ids of the records.
+    pub async fn submit_records(&self, records: Vec<Record>) -> EbioticResult<JobHandle> {
+        if records.is_empty() {
+            return Err(EbioticError::InvalidBlastParameters(
+                "At least one query sequence is required".to_string(),
+            ));
+        }
+        let mut handle = self.submit(format_records(&records)).await?;
+        handle.set_query_ids(records.iter().map(|r| r.id().to_string()).collect());
+        Ok(handle)
+    }
 
-        log::info!("Fetching results for RID: {}", rid);
+    /// Fetch the results of a finished search, one `BlastResult` per query in the order they were submitted.
+    ///
+    /// If the handle holds the ids of the submitted records, the query id of each result is set to the id of its record,
+    /// as NCBI replaces them with `Query_N` ids.
+    pub async fn fetch_results(&self, handle: &JobHandle) -> EbioticResult<Vec<BlastResult>> {
+        let result = async {
+            let search_results = self.fetch_json(handle).await?;
+            let mut results = self.parse_all_raw_results(&search_results)?;
+
+            let ids = handle.query_ids();
+            if ids.is_empty() {
+                return Ok(results);
+            }
+            if results.len() != ids.len() {
+                return Err(EbioticError::MalformedResponse(format!(
+                    "Expected results for {} queries, got {}",
+                    ids.len(),
+                    results.len()
+                )));
+            }
+            for (result, id) in results.iter_mut().zip(ids) {
+                result.query_id = id.clone();
+            }
+            Ok(results)
+        }
+        .await;
+        report_fetch(self, handle, result)
+    }
+
+    /// Search several query sequences under a single RID, returning one `BlastResult` per record.
+    ///
+    /// The query id of each result is set to the id of its record, as NCBI replaces them with `Query_N` ids.
+    pub async fn run_records(&self, records: Vec<Record>) -> EbioticResult<Vec<BlastResult>> {
+        self.run_records_with_cancel(records, &CancellationToken::new())
+            .await
+    }
+
+    /// Search several query sequences like `run_records`, stopping early if `token` is cancelled.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "service_run",
+            skip_all,
+            fields(
+                service = "blast",
+                endpoint = %self.endpoint,
+                queries = records.len(),
+            )
+        )
+    )]
+    pub async fn run_records_with_cancel(
+        &self,
+        records: Vec<Record>,
+        token: &CancellationToken,
+    ) -> EbioticResult<Vec<BlastResult>> {
+        run_job(
+            self,
+            self.submit_records(records),
+            token,
+            |handle| async move { self.fetch_results(&handle).await },
+        )
+        .await
+    }
+
+    async fn fetch_json(&self, handle: &JobHandle) -> EbioticResult<String> {
+        log::info!("Fetching results for RID: {}", handle.job_id());
+        self.notify(&JobEvent::Fetching {
+            job_id: handle.job_id().clone(),
+            artifact: "JSON2_S".to_string(),
+        });
 
         let search_results = self
             .client
             .post_form(
-                &self.endpoint,
-                &[("CMD", "Get"), ("FORMAT_TYPE", "JSON2_S"), ("RID", &rid)],
+                handle.endpoint(),
+                &[
+                    ("CMD", "Get"),
+                    ("FORMAT_TYPE", "JSON2_S"),
+                    ("RID", handle.job_id()),
+                ],
             )
-            .await?;
-        self.parse_raw_results(&search_results)
+            .await;
+        decode_response(self, search_results)
     }
 }
 
-impl PollableService for &Blast {
+/// Parse the search of every report in a `BlastOutput2` document.
+fn parse_reports(parsed: &Value) -> EbioticResult<Vec<BlastResult>> {
+    let reports = match parsed["BlastOutput2"].as_array() {
+        Some(reports) if !reports.is_empty() => reports,
+        _ => return Err(EbioticError::NoResults),
+    };
+
+    reports
+        .iter()
+        .map(|report| parse_report(&report["report"]))
+        .collect()
+}
+
+/// Parse the search of a JSON2 `report`, keeping the program, version and database it was run with.
+fn parse_report(report: &Value) -> EbioticResult<BlastResult> {
+    let flat = &report["results"]["search"];
+    if flat == &Value::Null {
+        return Err(EbioticError::NoResults);
+    }
+
+    let mut search: BlastResult = serde_json::from_value(flat.clone())?;
+    let text = |value: &Value| value.as_str().map(|s| s.to_string());
+    search.program = text(&report["program"]);
+    search.version = text(&report["version"]);
+    search.database = text(&report["search_target"]["db"]);
+    Ok(search)
+}
+
+/// Format records as a multi-FASTA query.
+fn format_records(records: &[Record]) -> String {
+    let mut query = String::new();
+    for record in records {
+        write!(query, "{}", record).unwrap();
+    }
+    query
+}
+
+impl<C> PollableService for &Blast<C> {
     fn poll_status(&self, response: &str) -> PollStatus {
         for line in response.lines() {
             let trimmed_line = line.trim_start();
             if let Some(line) = trimmed_line.strip_prefix("Status=") {
                 let status_string = line.to_string();
-                if status_string == "READY" {
-                    return PollStatus::Finished;
-                } else if status_string == "WAITING" {
-                    return PollStatus::Running(60);
+                match status_string.as_str() {
+                    "READY" => return PollStatus::Finished,
+                    "WAITING" => return PollStatus::Running,
+                    "FAILED" => {
+                        return PollStatus::Error(EbioticError::BlastError(
+                            "The search failed on the BLAST server.".to_string(),
+                        ))
+                    }
+                    "UNKNOWN" => {
+                        return PollStatus::Error(EbioticError::BlastError(
+                            "The RID is unknown or has expired.".to_string(),
+                        ))
+                    }
+                    _ => {}
                 }
             }
         }
-        PollStatus::Error(EbioticError::ServiceError(
-            "Something went wrong with the job".to_string(),
-        ))
+        PollStatus::Error(self.decode_error(response).unwrap_or_else(|| {
+            EbioticError::MalformedResponse(
+                "No search status found in the BLAST SearchInfo response.".to_string(),
+            )
+        }))
+    }
+}
+
+impl<C> ErrorDecoder for Blast<C> {
+    /// Recognise the error messages embedded in NCBI BLAST HTML pages, e.g.
+    /// `<p class="error">Message ID#24 Error: Failed to read the Blast query</p>`.
+    fn decode_error(&self, body: &str) -> Option<EbioticError> {
+        let start = body.find("Message ID#").or_else(|| {
+            body.find("class=\"error\"")
+                .map(|i| i + "class=\"error\"".len())
+        })?;
+        let message = strip_html(&body[start..]);
+        let message = message.trim_start_matches('>').trim();
+        if message.is_empty() {
+            None
+        } else {
+            Some(EbioticError::BlastError(message.to_string()))
+        }
+    }
+}
+
+/// Take the text content up to the end of the enclosing element, dropping any nested tags.
+fn strip_html(fragment: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    let mut chars = fragment.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                if chars.peek() == Some(&'/') {
+                    break;
+                }
+                in_tag = true;
+            }
+            '>' if in_tag => in_tag = false,
+            '\n' if !in_tag && !text.trim().is_empty() => break,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
     }
+    text
 }
 
-impl Blast {
+impl<C> Blast<C> {
+    /// Build the form submitting a search. Parameters which are not set are left to the server defaults.
+    fn put_form(&self, query: String) -> Vec<(&'static str, String)> {
+        let mut form = vec![("CMD", "Put".to_string())];
+        form.extend(self.program.form_fields());
+        form.push(("DATABASE", self.database.to_string()));
+        if let Some(matrix) = &self.matrix {
+            form.push(("MATRIX", matrix.to_string()));
+        }
+        form.push(("HITLIST_SIZE", self.hitlist_size.to_string()));
+        form.push(("EMAIL", self.email.clone()));
+        form.push(("TOOL", self.tool.clone()));
+        form.extend(self.params.form_fields());
+        form.push(("QUERY", query));
+        form
+    }
+
     fn parse_raw_results(&self, raw_results: &str) -> Result<BlastResult, EbioticError> {
         let parsed: Value = serde_json::from_str(raw_results)?;
-        let flat = &parsed["BlastOutput2"][0]["report"]["results"]["search"];
+        parse_report(&parsed["BlastOutput2"][0]["report"])
+    }
 
-        if flat != &Value::Null {
-            let search: BlastResult = serde_json::from_value(flat.clone())?;
-            Ok(search)
-        } else {
-            Err(EbioticError::ServiceError(
-                "No results were found.".to_string(),
-            ))
-        }
+    /// Parse the results of every query of a multi-query search.
+    fn parse_all_raw_results(&self, raw_results: &str) -> Result<Vec<BlastResult>, EbioticError> {
+        let parsed: Value = serde_json::from_str(raw_results)?;
+        parse_reports(&parsed)
     }
 
-    fn fetch_ridrtoe(&self, response: &str) -> (String, String) {
+    fn fetch_ridrtoe(&self, response: &str) -> Result<(String, String), EbioticError> {
         let mut rid = String::new();
         let mut rtoe = String::new();
         for line in response.lines() {
@@ -326,7 +913,78 @@ impl Blast {
                 rtoe = line.to_string();
             }
         }
-        (rid, rtoe)
+        if rid.is_empty() {
+            return Err(EbioticError::BlastError(
+                "No RID was returned for the submitted search.".to_string(),
+            ));
+        }
+        Ok((rid, rtoe))
+    }
+}
+
+/// Builders for the `BlastResult`s used by the tests of the helper modules.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::BlastResult;
+
+    /// The JSON of an HSP aligned to `query` on the query.
+    pub(crate) fn hsp(evalue: f64, bit_score: f64, identity: u32, query: (u32, u32)) -> String {
+        format!(
+            r#"{{"num": 1, "bit_score": {}, "score": 1, "evalue": {}, "identity": {}, "query_from": {}, "query_to": {},
+                "hit_from": 1, "hit_to": 10, "align_len": 10, "qseq": "", "hseq": "", "midline": ""}}"#,
+            bit_score, evalue, identity, query.0, query.1
+        )
+    }
+
+    /// The `ResultBuilder` struct is used to build a `BlastResult` hit by hit.
+    pub(crate) struct ResultBuilder {
+        query_len: u32,
+        program: Option<String>,
+        database: Option<String>,
+        hits: Vec<String>,
+    }
+
+    impl ResultBuilder {
+        pub(crate) fn new(query_len: u32) -> ResultBuilder {
+            ResultBuilder {
+                query_len,
+                program: None,
+                database: None,
+                hits: Vec::new(),
+            }
+        }
+
+        pub(crate) fn search(mut self, program: &str, database: &str) -> ResultBuilder {
+            self.program = Some(program.to_string());
+            self.database = Some(database.to_string());
+            self
+        }
+
+        /// Add a hit with a single description, numbered after the hits already added.
+        pub(crate) fn hit(mut self, accession: &str, taxid: u32, hsps: &[String]) -> ResultBuilder {
+            self.hits.push(format!(
+                r#"{{"num": {}, "len": 100, "description": [{{"id": "{}", "accession": "{}", "title": "", "taxid": {}, "sciname": ""}}],
+                    "hsps": [{}]}}"#,
+                self.hits.len() + 1,
+                accession,
+                accession,
+                taxid,
+                hsps.join(",")
+            ));
+            self
+        }
+
+        pub(crate) fn build(self) -> BlastResult {
+            let mut result: BlastResult = serde_json::from_str(&format!(
+                r#"{{"query_id": "Q", "query_len": {}, "hits": [{}]}}"#,
+                self.query_len,
+                self.hits.join(",")
+            ))
+            .unwrap();
+            result.program = self.program;
+            result.database = self.database;
+            result
+        }
     }
 }
 
@@ -341,14 +999,116 @@ mod tests {
         blast.parse_raw_results(test_json).unwrap();
     }
 
+    #[test]
+    fn parses_the_full_hsp_and_search_statistics() {
+        let test_json = include_str!("../../tests/example_blast_response.json");
+        let result = Blast::default().parse_raw_results(test_json).unwrap();
+
+        let stat = result.stat().as_ref().unwrap();
+        assert_eq!(stat.db_num(), &628135233);
+        assert_eq!(stat.eff_space(), &9045097358712);
+        assert_eq!(stat.lambda(), &0.267);
+
+        let hit = &result.hits()[0];
+        assert_eq!(hit.description()[0].taxid(), &10160);
+        let hsp = &hit.hsps()[0];
+        assert_eq!((hsp.query_from(), hsp.query_to()), (&5, &198));
+        assert_eq!((hsp.hit_from(), hsp.hit_to()), (&9, &201));
+        assert_eq!(hsp.positive(), &191);
+        assert_eq!(hsp.align_len(), &194);
+        assert_eq!(hsp.gaps(), &1);
+        assert_eq!(hsp.qseq().len(), hsp.hseq().seq().len());
+        assert_eq!(hsp.midline().len(), hsp.qseq().len());
+        assert!(hsp.qseq().starts_with("VQKARK"));
+        assert_eq!(hsp.query_strand(), &None);
+        assert!((hsp.percent_identity() - 96.907).abs() < 0.001);
+    }
+
+    #[test]
+    fn multi_query_results_are_parsed_in_order() {
+        let raw = r#"{"BlastOutput2": [
+            {"report": {"results": {"search": {"query_id": "Query_1", "query_len": 10, "hits": []}}}},
+            {"report": {"results": {"search": {"query_id": "Query_2", "query_len": 20, "hits": []}}}}
+        ]}"#;
+        let results = Blast::default().parse_all_raw_results(raw).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].query_len(), &20);
+        assert!(matches!(
+            Blast::default().parse_all_raw_results(r#"{"BlastOutput2": []}"#),
+            Err(EbioticError::NoResults)
+        ));
+    }
+
+    #[test]
+    fn parses_nucleotide_hsps() {
+        let hsp: Hsp = serde_json::from_str(
+            r#"{"num": 1, "bit_score": 52.8, "score": 28, "evalue": 2e-5, "identity": 28,
+                "query_from": 1, "query_to": 28, "query_strand": "Plus",
+                "hit_from": 340, "hit_to": 313, "hit_strand": "Minus",
+                "align_len": 28, "gaps": 0,
+                "qseq": "ACGTACGTACGTACGTACGTACGTACGT", "hseq": "ACGTACGTACGTACGTACGTACGTACGT",
+                "midline": "||||||||||||||||||||||||||||"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(hsp.hit_strand().as_deref(), Some("Minus"));
+        assert!(hsp.hit_from() > hsp.hit_to());
+        assert_eq!(hsp.positive(), &0);
+        assert_eq!(hsp.hit_frame(), &None);
+    }
+
+    #[test]
+    fn decode_error_recognises_blast_error_pages() {
+        let blast = Blast::default();
+        let page = "<html><body><ul class=\"msg\"><li class=\"error\"><p class=\"error\">Message ID#24 Error: Failed to read the Blast query: Nucleotide FASTA provided for protein sequence</p></li></ul></body></html>";
+
+        match blast.decode_error(page) {
+            Some(EbioticError::BlastError(message)) => assert_eq!(
+                message,
+                "Message ID#24 Error: Failed to read the Blast query: Nucleotide FASTA provided for protein sequence"
+            ),
+            other => panic!("Expected BlastError, got {:?}", other),
+        }
+        assert!(blast.decode_error("RID = ABC123\nRTOE = 20").is_none());
+    }
+
+    #[test]
+    fn fetch_ridrtoe_requires_rid() {
+        let blast = Blast::default();
+        let (rid, rtoe) = blast
+            .fetch_ridrtoe("    RID = ABC123\n    RTOE = 20\n")
+            .unwrap();
+        assert_eq!(rid, "ABC123");
+        assert_eq!(rtoe, "20");
+        assert!(blast.fetch_ridrtoe("<html></html>").is_err());
+    }
+
+    #[test]
+    fn poll_status_recognises_failures() {
+        let blast = Blast::default();
+        assert!(matches!(
+            (&blast).poll_status("Status=READY"),
+            PollStatus::Finished
+        ));
+        assert!(matches!(
+            (&blast).poll_status("Status=FAILED"),
+            PollStatus::Error(EbioticError::BlastError(_))
+        ));
+        assert!(matches!(
+            (&blast).poll_status("<html></html>"),
+            PollStatus::Error(EbioticError::MalformedResponse(_))
+        ));
+    }
+
     #[test]
     fn test_update_functions() {
         let mut blast = Blast::new(
             EbioticClient::default(),
             "endpoint".to_string(),
-            "program".to_string(),
-            "database".to_string(),
-            "matrix".to_string(),
+            BlastProgram::Blastp,
+            BlastDatabase::Custom("database".to_string()),
+            Some(BlastMatrix::Blosum62),
             10,
             "email".to_string(),
             "tool".to_string(),
@@ -356,20 +1116,84 @@ mod tests {
 
         // Update the values
         blast.set_endpoint("new_endpoint".to_string());
-        blast.set_program("new_program".to_string());
-        blast.set_database("new_database".to_string());
-        blast.set_matrix("new_matrix".to_string());
+        blast.set_program(BlastProgram::Blastn);
+        blast.set_database(BlastDatabase::Custom("new_database".to_string()));
+        blast.set_matrix(None);
         blast.set_hitlist_size(20);
         blast.set_email("new_email".to_string());
         blast.set_tool("new_tool".to_string());
 
         // Check that the values have been updated correctly
         assert_eq!(blast.endpoint, "new_endpoint");
-        assert_eq!(blast.program, "new_program");
-        assert_eq!(blast.database, "new_database");
-        assert_eq!(blast.matrix, "new_matrix");
+        assert_eq!(blast.program, BlastProgram::Blastn);
+        assert_eq!(blast.database.to_string(), "new_database");
+        assert_eq!(blast.matrix, None);
         assert_eq!(blast.hitlist_size, 20);
         assert_eq!(blast.email, "new_email");
         assert_eq!(blast.tool, "new_tool");
     }
+
+    #[test]
+    fn put_form_only_sends_set_parameters() {
+        let mut blast = Blast::default();
+        blast.set_program(BlastProgram::Megablast);
+        blast.set_database(BlastDatabase::CoreNt);
+        blast.set_matrix(None);
+        let mut params = BlastParams::new();
+        params.set_expect(Some(0.001));
+        blast.set_params(params);
+
+        let form = blast.put_form("ACGT".to_string());
+
+        assert!(form.contains(&("MEGABLAST", "on".to_string())));
+        assert!(form.contains(&("EXPECT", "0.001".to_string())));
+        assert!(!form.iter().any(|(key, _)| *key == "MATRIX"));
+        assert_eq!(form.last(), Some(&("QUERY", "ACGT".to_string())));
+    }
+
+    #[tokio::test]
+    async fn invalid_searches_are_not_submitted() {
+        let mut blast = Blast::new(
+            EbioticClient::replay(crate::core::Fixtures::new(vec![])),
+            BLAST_ENDPOINT.to_string(),
+            BlastProgram::Blastn,
+            BlastDatabase::Nt,
+            Some(BlastMatrix::Blosum62),
+            10,
+            "".to_string(),
+            "".to_string(),
+        );
+
+        assert!(matches!(
+            blast.submit("ACGT".to_string()).await,
+            Err(EbioticError::InvalidBlastParameters(_))
+        ));
+        blast.set_matrix(None);
+        assert!(matches!(
+            blast.submit("ACGT".to_string()).await,
+            Err(EbioticError::NoMatchingFixture(_))
+        ));
+    }
+
+    #[test]
+    fn databases_are_checked_against_the_program() {
+        let mut blast = Blast::default();
+        assert!(blast.validate().is_ok());
+
+        blast.set_database(BlastDatabase::RefseqRna);
+        assert!(matches!(
+            blast.validate(),
+            Err(EbioticError::ProgramNotAvailable(_, _))
+        ));
+
+        blast.set_program(BlastProgram::Tblastn);
+        assert!(blast.validate().is_ok());
+
+        blast.set_database(BlastDatabase::Custom("refseq_protien".to_string()));
+        assert!(blast.validate().is_ok());
+        assert_eq!(
+            BlastDatabase::Swissprot.available_programs(),
+            vec![BlastProgram::Blastp, BlastProgram::Blastx]
+        );
+    }
 }
@@ -0,0 +1,102 @@
+use super::blastparams::{BlastProgram, BlastSequenceType};
+use super::AvailablePrograms;
+use std::fmt::{Display, Formatter};
+
+/// The `BlastDatabase` enum is used to specify the database searched by the `Blast` service.
+///
+/// `Custom` takes the name of any other database, and is not checked against the program.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BlastDatabase {
+    // Protein
+    Nr,
+    RefseqProtein,
+    Swissprot,
+    Pdb,
+    EnvNr,
+    TsaNr,
+    Landmark,
+    Pataa,
+    // Nucleotide
+    CoreNt,
+    Nt,
+    RefseqRna,
+    RefseqGenomic,
+    RefseqRepresentativeGenomes,
+    Est,
+    TsaNt,
+    Patnt,
+    PdbNt,
+    Wgs,
+    Prokaryotic16SRrna,
+    Custom(String),
+}
+
+impl BlastDatabase {
+    /// The alphabet of the database sequences, or `None` for a `Custom` database.
+    pub fn sequence_type(&self) -> Option<BlastSequenceType> {
+        match self {
+            BlastDatabase::Nr
+            | BlastDatabase::RefseqProtein
+            | BlastDatabase::Swissprot
+            | BlastDatabase::Pdb
+            | BlastDatabase::EnvNr
+            | BlastDatabase::TsaNr
+            | BlastDatabase::Landmark
+            | BlastDatabase::Pataa => Some(BlastSequenceType::Protein),
+            BlastDatabase::Custom(_) => None,
+            _ => Some(BlastSequenceType::Nucleotide),
+        }
+    }
+}
+
+impl AvailablePrograms for BlastDatabase {
+    fn available_programs(&self) -> Vec<BlastProgram> {
+        let programs = vec![
+            BlastProgram::Blastp,
+            BlastProgram::Blastn,
+            BlastProgram::Blastx,
+            BlastProgram::Tblastn,
+            BlastProgram::Tblastx,
+            BlastProgram::Megablast,
+            BlastProgram::DiscontiguousMegablast,
+        ];
+        match self.sequence_type() {
+            Some(sequence_type) => programs
+                .into_iter()
+                .filter(|program| program.database_type() == sequence_type)
+                .collect(),
+            None => programs,
+        }
+    }
+}
+
+impl Display for BlastDatabase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlastDatabase::Nr => write!(f, "nr"),
+            BlastDatabase::RefseqProtein => write!(f, "refseq_protein"),
+            BlastDatabase::Swissprot => write!(f, "swissprot"),
+            BlastDatabase::Pdb => write!(f, "pdb"),
+            BlastDatabase::EnvNr => write!(f, "env_nr"),
+            BlastDatabase::TsaNr => write!(f, "tsa_nr"),
+            BlastDatabase::Landmark => write!(f, "landmark"),
+            BlastDatabase::Pataa => write!(f, "pataa"),
+            BlastDatabase::CoreNt => write!(f, "core_nt"),
+            BlastDatabase::Nt => write!(f, "nt"),
+            BlastDatabase::RefseqRna => write!(f, "refseq_rna"),
+            BlastDatabase::RefseqGenomic => write!(f, "refseq_genomic"),
+            BlastDatabase::RefseqRepresentativeGenomes => {
+                write!(f, "refseq_representative_genomes")
+            }
+            BlastDatabase::Est => write!(f, "est"),
+            BlastDatabase::TsaNt => write!(f, "tsa_nt"),
+            BlastDatabase::Patnt => write!(f, "patnt"),
+            BlastDatabase::PdbNt => write!(f, "pdbnt"),
+            BlastDatabase::Wgs => write!(f, "wgs"),
+            BlastDatabase::Prokaryotic16SRrna => {
+                write!(f, "rRNA_typestrains/prokaryotic_16S_ribosomal_RNA")
+            }
+            BlastDatabase::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use bio::io::fasta::Record;
+
+use super::blastparams::BlastSequenceType;
+use super::BlastResult;
+use crate::core::{EbioticHttpClient, EbioticResult};
+use crate::data::{AccessionIds, DataReturnFormats, Dbfetch, DbfetchDbs, DbfetchStyle};
+
+const REFSEQ_PROTEIN_PREFIXES: [&str; 6] = ["AP_", "NP_", "WP_", "XP_", "YP_", "ZP_"];
+const REFSEQ_NUCLEOTIDE_PREFIXES: [&str; 10] = [
+    "AC_", "NC_", "NG_", "NM_", "NR_", "NT_", "NW_", "NZ_", "XM_", "XR_",
+];
+
+/// The `SubjectSequences` struct is used to specify the sequences of the hits fetched by `fetch_subject_sequences`,
+/// along with the accessions which no Dbfetch database could be chosen for.
+#[derive(Debug, Clone)]
+pub struct SubjectSequences {
+    records: Vec<Record>,
+    skipped: Vec<String>,
+}
+
+impl SubjectSequences {
+    /// The records fetched, grouped by database, with the ids and descriptions given by Dbfetch.
+    pub fn records(&self) -> &Vec<Record> {
+        &self.records
+    }
+
+    /// The accessions which were not fetched, e.g. GenBank protein ids from `nr`.
+    pub fn skipped(&self) -> &Vec<String> {
+        &self.skipped
+    }
+
+    pub fn into_records(self) -> Vec<Record> {
+        self.records
+    }
+}
+
+impl BlastResult {
+    /// Fetch the full-length sequences of the hits from Dbfetch, using `client` for the requests.
+    ///
+    /// RefSeq accessions are fetched from `RefSeqProtein` or `RefSeqNucleotide`, UniProt accessions from `UniProtKB`,
+    /// and other nucleotide accessions from `EnaSequence`. Protein accessions which are in none of these (e.g. GenBank
+    /// protein ids from `nr`) have no protein sequence in Dbfetch, so they are returned as skipped for the caller to
+    /// fetch elsewhere.
+    pub async fn fetch_subject_sequences<C: EbioticHttpClient + Clone>(
+        &self,
+        client: &C,
+    ) -> EbioticResult<SubjectSequences> {
+        let mut groups: Vec<(DbfetchDbs, Vec<String>)> = Vec::new();
+        let mut skipped: Vec<String> = Vec::new();
+        for accession in self.hits.iter().filter_map(|hit| hit.accession()) {
+            let db = match self.dbfetch_db(accession) {
+                Some(db) => db,
+                None => {
+                    if !skipped.contains(accession) {
+                        log::debug!("No Dbfetch database holds {}, skipping it", accession);
+                        skipped.push(accession.clone());
+                    }
+                    continue;
+                }
+            };
+            match groups.iter_mut().find(|(group, _)| group == &db) {
+                Some((_, accessions)) if accessions.contains(accession) => {}
+                Some((_, accessions)) => accessions.push(accession.clone()),
+                None => groups.push((db, vec![accession.clone()])),
+            }
+        }
+
+        let mut records = Vec::new();
+        for (db, accessions) in groups {
+            let dbfetch = Dbfetch::new(
+                client.clone(),
+                db,
+                DataReturnFormats::Fasta,
+                DbfetchStyle::Raw,
+            );
+            for result in dbfetch.run_batched(AccessionIds::new(accessions)).await? {
+                records.extend(result.into_records()?);
+            }
+        }
+        Ok(SubjectSequences { records, skipped })
+    }
+
+    /// The alphabet of the database searched, if the program is known.
+    fn subject_type(&self) -> Option<BlastSequenceType> {
+        match self.program.as_deref()? {
+            "blastp" | "blastx" => Some(BlastSequenceType::Protein),
+            "blastn" | "tblastn" | "tblastx" => Some(BlastSequenceType::Nucleotide),
+            _ => None,
+        }
+    }
+
+    fn dbfetch_db(&self, accession: &str) -> Option<DbfetchDbs> {
+        if REFSEQ_PROTEIN_PREFIXES
+            .iter()
+            .any(|prefix| accession.starts_with(prefix))
+        {
+            return Some(DbfetchDbs::RefSeqProtein);
+        }
+        if REFSEQ_NUCLEOTIDE_PREFIXES
+            .iter()
+            .any(|prefix| accession.starts_with(prefix))
+        {
+            return Some(DbfetchDbs::RefSeqNucleotide);
+        }
+        match self.subject_type()? {
+            BlastSequenceType::Protein
+                if self.database.as_deref() == Some("swissprot")
+                    || is_uniprot_accession(accession) =>
+            {
+                Some(DbfetchDbs::UniProtKB)
+            }
+            BlastSequenceType::Protein => None,
+            BlastSequenceType::Nucleotide => Some(DbfetchDbs::EnaSequence),
+        }
+    }
+}
+
+/// Check an accession against the UniProtKB accession format, e.g. `P62258` or `A0A023GPI8`.
+fn is_uniprot_accession(accession: &str) -> bool {
+    let accession = accession.split('.').next().unwrap_or(accession).as_bytes();
+    let alnum = |c: &u8| c.is_ascii_uppercase() || c.is_ascii_digit();
+    match accession {
+        [first, second, rest @ ..] if first.is_ascii_uppercase() && second.is_ascii_digit() => {
+            if b"OPQ".contains(first) && rest.len() == 4 {
+                rest[..3].iter().all(alnum) && rest[3].is_ascii_digit()
+            } else {
+                (rest.len() == 4 || rest.len() == 8)
+                    && rest.chunks(4).all(|block| {
+                        block[0].is_ascii_uppercase()
+                            && block[1..3].iter().all(alnum)
+                            && block[3].is_ascii_digit()
+                    })
+            }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{EbioticClient, Fixture, Fixtures, HttpRequest, HttpResponse};
+    use crate::tools::blast::testing::ResultBuilder;
+
+    fn result(program: &str, database: &str, accessions: &[&str]) -> BlastResult {
+        accessions
+            .iter()
+            .fold(
+                ResultBuilder::new(10).search(program, database),
+                |builder, accession| builder.hit(accession, 0, &[]),
+            )
+            .build()
+    }
+
+    #[test]
+    fn recognises_uniprot_accessions() {
+        assert!(is_uniprot_accession("P62258"));
+        assert!(is_uniprot_accession("P62258.1"));
+        assert!(is_uniprot_accession("A0A023GPI8"));
+        assert!(!is_uniprot_accession("OBS78370"));
+        assert!(!is_uniprot_accession("XP_023570734"));
+    }
+
+    #[test]
+    fn chooses_the_database_from_the_accession_and_search() {
+        let protein = result("blastp", "nr", &[]);
+        assert_eq!(
+            protein.dbfetch_db("XP_023570734"),
+            Some(DbfetchDbs::RefSeqProtein)
+        );
+        assert_eq!(protein.dbfetch_db("P62258"), Some(DbfetchDbs::UniProtKB));
+        assert_eq!(protein.dbfetch_db("OBS78370"), None);
+
+        let nucleotide = result("blastn", "core_nt", &[]);
+        assert_eq!(
+            nucleotide.dbfetch_db("NM_000207"),
+            Some(DbfetchDbs::RefSeqNucleotide)
+        );
+        assert_eq!(
+            nucleotide.dbfetch_db("M10051"),
+            Some(DbfetchDbs::EnaSequence)
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_hit_sequences_in_batches_per_database() {
+        let client = EbioticClient::replay(Fixtures::new(vec![
+            Fixture::new(
+                HttpRequest::get("https://www.ebi.ac.uk/Tools/dbfetch/?db=refseqp&format=fasta&style=raw&id=XP_023570734,WP_000001"),
+                HttpResponse::new(
+                    200,
+                    ">XP_023570734 14-3-3 protein beta/alpha\nMTMDKSEL\n>WP_000001 hypothetical protein\nMKV\n"
+                        .to_string(),
+                ),
+            ),
+            Fixture::new(
+                HttpRequest::get("https://www.ebi.ac.uk/Tools/dbfetch/?db=uniprotkb&format=fasta&style=raw&id=P62258"),
+                HttpResponse::new(200, ">sp|P62258|1433E_HUMAN 14-3-3 protein epsilon\nMDDREDL\n".to_string()),
+            ),
+        ]));
+        let result = result(
+            "blastp",
+            "nr",
+            &[
+                "XP_023570734",
+                "OBS78370",
+                "P62258",
+                "WP_000001",
+                "XP_023570734",
+            ],
+        );
+
+        let sequences = result.fetch_subject_sequences(&client).await.unwrap();
+        let records = sequences.records();
+
+        assert_eq!(sequences.skipped(), &vec!["OBS78370".to_string()]);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].id(), "XP_023570734");
+        assert_eq!(records[0].desc(), Some("14-3-3 protein beta/alpha"));
+        assert_eq!(records[2].id(), "sp|P62258|1433E_HUMAN");
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, HashSet};
+
+use super::{BlastResult, Hit, Hsp};
+
+impl BlastResult {
+    /// Keep the HSPs matching `predicate`, dropping hits left without any.
+    pub fn filter_hsps<F: Fn(&Hsp) -> bool>(&self, predicate: F) -> BlastResult {
+        let mut result = self.clone();
+        for hit in &mut result.hits {
+            hit.hsps.retain(|hsp| predicate(hsp));
+        }
+        result.hits.retain(|hit| !hit.hsps.is_empty());
+        result
+    }
+
+    /// Keep the hits matching `predicate`.
+    pub fn filter_hits<F: Fn(&Hit) -> bool>(&self, predicate: F) -> BlastResult {
+        let mut result = self.clone();
+        result.hits.retain(|hit| predicate(hit));
+        result
+    }
+
+    /// Keep the HSPs with an e-value of at most `max_evalue`.
+    pub fn filter_by_evalue(&self, max_evalue: f64) -> BlastResult {
+        self.filter_hsps(|hsp| hsp.evalue <= max_evalue)
+    }
+
+    /// Keep the HSPs with a bit score of at least `min_bit_score`.
+    pub fn filter_by_bit_score(&self, min_bit_score: f64) -> BlastResult {
+        self.filter_hsps(|hsp| hsp.bit_score >= min_bit_score)
+    }
+
+    /// Keep the HSPs with a percent identity of at least `min_identity`, e.g. `90.0`.
+    pub fn filter_by_identity(&self, min_identity: f64) -> BlastResult {
+        self.filter_hsps(|hsp| hsp.percent_identity() >= min_identity)
+    }
+
+    /// Keep the hits whose HSPs together cover at least `min_coverage` percent of the query.
+    ///
+    /// Returns `None` if the query length is unknown, e.g. for tabular output without a `qlen` column.
+    pub fn filter_by_query_coverage(&self, min_coverage: f64) -> Option<BlastResult> {
+        if self.query_len == 0 {
+            return None;
+        }
+        Some(self.filter_hits(|hit| hit.query_coverage(self.query_len) >= min_coverage))
+    }
+
+    /// Keep the hits with a description from one of `taxids`.
+    pub fn filter_by_taxid(&self, taxids: &[u32]) -> BlastResult {
+        self.filter_hits(|hit| hit.taxids().iter().any(|taxid| taxids.contains(taxid)))
+    }
+
+    /// Keep only the best HSP of each hit.
+    pub fn best_hsps(&self) -> BlastResult {
+        let mut result = self.clone();
+        for hit in &mut result.hits {
+            hit.hsps = hit.best_hsp().cloned().into_iter().collect();
+        }
+        result
+    }
+
+    /// Keep the first hit for each accession, e.g. when merging results from several searches.
+    pub fn dedup_by_accession(&self) -> BlastResult {
+        let mut seen = HashSet::new();
+        let mut result = self.clone();
+        result
+            .hits
+            .retain(|hit| seen.insert(hit.accession().cloned().unwrap_or_default()));
+        result
+    }
+
+    /// Group the hits by the taxids of their descriptions, matching `filter_by_taxid`. A hit with descriptions
+    /// from several taxa is in the group of each of them, and hits without a taxid are grouped under `0`.
+    pub fn group_by_taxid(&self) -> BTreeMap<u32, Vec<&Hit>> {
+        let mut groups: BTreeMap<u32, Vec<&Hit>> = BTreeMap::new();
+        for hit in &self.hits {
+            let taxids = hit.taxids();
+            if taxids.is_empty() {
+                groups.entry(0).or_default().push(hit);
+            }
+            for taxid in taxids {
+                groups.entry(taxid).or_default().push(hit);
+            }
+        }
+        groups
+    }
+
+    /// The percentage of the query covered by the HSPs of every hit, counting overlapping HSPs once.
+    ///
+    /// Returns `None` if the query length is unknown.
+    pub fn query_coverage(&self) -> Option<f64> {
+        if self.query_len == 0 {
+            return None;
+        }
+        let intervals = merge_intervals(
+            self.hits
+                .iter()
+                .flat_map(|hit| hit.hsps.iter())
+                .map(|hsp| (hsp.query_from, hsp.query_to)),
+        );
+        Some(coverage(&intervals, self.query_len))
+    }
+}
+
+impl Hit {
+    /// The HSP with the lowest e-value, breaking ties by the highest bit score.
+    pub fn best_hsp(&self) -> Option<&Hsp> {
+        self.hsps.iter().min_by(|a, b| {
+            a.evalue
+                .total_cmp(&b.evalue)
+                .then(b.bit_score.total_cmp(&a.bit_score))
+        })
+    }
+
+    /// The accession of the first description of the hit.
+    pub fn accession(&self) -> Option<&String> {
+        self.description.first().map(|d| &d.accession)
+    }
+
+    /// The distinct taxids of the descriptions of the hit.
+    pub fn taxids(&self) -> Vec<u32> {
+        let mut taxids = Vec::new();
+        for taxid in self.description.iter().map(|d| d.taxid) {
+            if taxid != 0 && !taxids.contains(&taxid) {
+                taxids.push(taxid);
+            }
+        }
+        taxids
+    }
+
+    /// The query intervals covered by the HSPs of the hit, with overlapping and adjacent HSPs merged.
+    pub fn query_intervals(&self) -> Vec<(u32, u32)> {
+        merge_intervals(self.hsps.iter().map(|hsp| (hsp.query_from, hsp.query_to)))
+    }
+
+    /// The percentage of a query of `query_len` covered by the HSPs of the hit, counting overlapping HSPs once.
+    pub fn query_coverage(&self, query_len: u32) -> f64 {
+        coverage(&self.query_intervals(), query_len)
+    }
+}
+
+/// Merge 1-based inclusive intervals, in either orientation, into sorted non-overlapping intervals.
+fn merge_intervals<I: Iterator<Item = (u32, u32)>>(intervals: I) -> Vec<(u32, u32)> {
+    let mut intervals: Vec<(u32, u32)> = intervals
+        .map(|(from, to)| (from.min(to), from.max(to)))
+        .collect();
+    intervals.sort();
+
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (from, to) in intervals {
+        match merged.last_mut() {
+            Some(last) if from <= last.1 + 1 => last.1 = last.1.max(to),
+            _ => merged.push((from, to)),
+        }
+    }
+    merged
+}
+
+fn coverage(intervals: &[(u32, u32)], query_len: u32) -> f64 {
+    if query_len == 0 {
+        return 0.0;
+    }
+    let covered: u32 = intervals.iter().map(|(from, to)| to - from + 1).sum();
+    100.0 * covered as f64 / query_len as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::blast::testing::{hsp, ResultBuilder};
+
+    fn result() -> BlastResult {
+        ResultBuilder::new(100)
+            .hit(
+                "A",
+                9606,
+                &[hsp(1e-50, 200.0, 10, (1, 50)), hsp(1e-5, 40.0, 6, (40, 60))],
+            )
+            .hit("B", 10090, &[hsp(1e-3, 30.0, 9, (81, 100))])
+            .hit("A", 9606, &[hsp(1.0, 20.0, 5, (1, 10))])
+            .build()
+    }
+
+    #[test]
+    fn filters_hsps_and_drops_empty_hits() {
+        let result = result();
+
+        let filtered = result.filter_by_evalue(1e-10);
+        assert_eq!(filtered.hits().len(), 1);
+        assert_eq!(filtered.hits()[0].hsps().len(), 1);
+
+        assert_eq!(result.filter_by_bit_score(30.0).hits().len(), 2);
+        assert_eq!(result.filter_by_identity(90.0).hits().len(), 2);
+        assert_eq!(result.filter_by_taxid(&[10090]).hits()[0].num(), &2);
+    }
+
+    #[test]
+    fn ranks_and_deduplicates_hits() {
+        let result = result();
+
+        let best = result.best_hsps();
+        assert_eq!(best.hits()[0].hsps().len(), 1);
+        assert_eq!(best.hits()[0].hsps()[0].bit_score(), &200.0);
+
+        let unique = result.dedup_by_accession();
+        assert_eq!(unique.hits().len(), 2);
+
+        let groups = result.group_by_taxid();
+        assert_eq!(groups[&9606].len(), 2);
+        assert_eq!(groups[&10090].len(), 1);
+    }
+
+    #[test]
+    fn groups_hits_under_every_taxid_they_match() {
+        let mut result = result();
+        let mut human = result.hits[1].description[0].clone();
+        human.taxid = 9606;
+        result.hits[1].description.push(human);
+
+        let groups = result.group_by_taxid();
+
+        assert_eq!(groups[&9606].len(), 3);
+        assert_eq!(groups[&10090].len(), 1);
+        assert_eq!(result.filter_by_taxid(&[9606]).hits().len(), 3);
+    }
+
+    #[test]
+    fn coverage_is_unknown_without_a_query_length() {
+        let mut result = result();
+        result.query_len = 0;
+
+        assert_eq!(result.query_coverage(), None);
+        assert!(result.filter_by_query_coverage(50.0).is_none());
+    }
+
+    #[test]
+    fn computes_coverage_over_merged_intervals() {
+        let result = result();
+        let hit = &result.hits()[0];
+
+        assert_eq!(hit.query_intervals(), vec![(1, 60)]);
+        assert_eq!(hit.query_coverage(100), 60.0);
+        assert_eq!(result.query_coverage(), Some(80.0));
+        assert_eq!(
+            result.filter_by_query_coverage(50.0).unwrap().hits().len(),
+            1
+        );
+        assert_eq!(
+            merge_intervals(vec![(10, 1), (12, 15)].into_iter()),
+            vec![(1, 10), (12, 15)]
+        );
+    }
+}
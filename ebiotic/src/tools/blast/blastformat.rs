@@ -0,0 +1,667 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::str::FromStr;
+
+use super::{BlastResult, Description, Hit, Hsp};
+use crate::core::EbioticResult;
+use crate::errors::EbioticError;
+
+/// The `TabularField` enum is used to specify the columns of BLAST tabular output (`-outfmt 6` and `7`).
+///
+/// The fields are named as in BLAST+, so a column spec such as `"qseqid sseqid pident"` can be parsed
+/// field by field with `str::parse`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TabularField {
+    Qseqid,
+    Qacc,
+    Qaccver,
+    Sseqid,
+    Sacc,
+    Saccver,
+    Stitle,
+    Pident,
+    Length,
+    Mismatch,
+    Gapopen,
+    Gaps,
+    Qstart,
+    Qend,
+    Sstart,
+    Send,
+    Evalue,
+    Bitscore,
+    Score,
+    Nident,
+    Positive,
+    Ppos,
+    Qlen,
+    Slen,
+    Qseq,
+    Sseq,
+    Qframe,
+    Sframe,
+    Sstrand,
+    Staxids,
+    Sscinames,
+}
+
+const ALL_FIELDS: [TabularField; 31] = [
+    TabularField::Qseqid,
+    TabularField::Qacc,
+    TabularField::Qaccver,
+    TabularField::Sseqid,
+    TabularField::Sacc,
+    TabularField::Saccver,
+    TabularField::Stitle,
+    TabularField::Pident,
+    TabularField::Length,
+    TabularField::Mismatch,
+    TabularField::Gapopen,
+    TabularField::Gaps,
+    TabularField::Qstart,
+    TabularField::Qend,
+    TabularField::Sstart,
+    TabularField::Send,
+    TabularField::Evalue,
+    TabularField::Bitscore,
+    TabularField::Score,
+    TabularField::Nident,
+    TabularField::Positive,
+    TabularField::Ppos,
+    TabularField::Qlen,
+    TabularField::Slen,
+    TabularField::Qseq,
+    TabularField::Sseq,
+    TabularField::Qframe,
+    TabularField::Sframe,
+    TabularField::Sstrand,
+    TabularField::Staxids,
+    TabularField::Sscinames,
+];
+
+impl TabularField {
+    /// The twelve standard columns (`std`), with the query and subject written as `qseqid` and `sseqid`.
+    pub fn standard() -> Vec<TabularField> {
+        vec![
+            TabularField::Qseqid,
+            TabularField::Sseqid,
+            TabularField::Pident,
+            TabularField::Length,
+            TabularField::Mismatch,
+            TabularField::Gapopen,
+            TabularField::Qstart,
+            TabularField::Qend,
+            TabularField::Sstart,
+            TabularField::Send,
+            TabularField::Evalue,
+            TabularField::Bitscore,
+        ]
+    }
+
+    /// The name of the column in the `# Fields:` header of `-outfmt 7`.
+    pub fn label(&self) -> &'static str {
+        match self {
+            TabularField::Qseqid => "query id",
+            TabularField::Qacc => "query acc.",
+            TabularField::Qaccver => "query acc.ver",
+            TabularField::Sseqid => "subject id",
+            TabularField::Sacc => "subject acc.",
+            TabularField::Saccver => "subject acc.ver",
+            TabularField::Stitle => "subject title",
+            TabularField::Pident => "% identity",
+            TabularField::Length => "alignment length",
+            TabularField::Mismatch => "mismatches",
+            TabularField::Gapopen => "gap opens",
+            TabularField::Gaps => "gaps",
+            TabularField::Qstart => "q. start",
+            TabularField::Qend => "q. end",
+            TabularField::Sstart => "s. start",
+            TabularField::Send => "s. end",
+            TabularField::Evalue => "evalue",
+            TabularField::Bitscore => "bit score",
+            TabularField::Score => "score",
+            TabularField::Nident => "identical",
+            TabularField::Positive => "positives",
+            TabularField::Ppos => "% positives",
+            TabularField::Qlen => "query length",
+            TabularField::Slen => "subject length",
+            TabularField::Qseq => "query seq",
+            TabularField::Sseq => "subject seq",
+            TabularField::Qframe => "query frame",
+            TabularField::Sframe => "sbjct frame",
+            TabularField::Sstrand => "subject strand",
+            TabularField::Staxids => "subject tax ids",
+            TabularField::Sscinames => "subject sci names",
+        }
+    }
+
+    /// Find the field with an `-outfmt 7` header label, e.g. `"% identity"`.
+    pub fn from_label(label: &str) -> Option<TabularField> {
+        ALL_FIELDS
+            .iter()
+            .find(|field| field.label() == label.trim())
+            .copied()
+    }
+
+    fn value(&self, result: &BlastResult, hit: &Hit, hsp: &Hsp) -> String {
+        let subject = hit.description.first();
+        match self {
+            TabularField::Qseqid | TabularField::Qacc | TabularField::Qaccver => {
+                result.query_id.clone()
+            }
+            TabularField::Sseqid => describe(subject, |d| d.id.clone()),
+            TabularField::Sacc => describe(subject, |d| d.accession.clone()),
+            TabularField::Saccver => describe(subject, accession_version),
+            TabularField::Stitle => describe(subject, |d| d.title.clone()),
+            TabularField::Pident => format!("{:.3}", hsp.percent_identity()),
+            TabularField::Length => hsp.align_len.to_string(),
+            TabularField::Mismatch => hsp.mismatches().to_string(),
+            TabularField::Gapopen => hsp.gap_opens().to_string(),
+            TabularField::Gaps => hsp.gaps.to_string(),
+            TabularField::Qstart => hsp.query_from.to_string(),
+            TabularField::Qend => hsp.query_to.to_string(),
+            TabularField::Sstart => hsp.hit_from.to_string(),
+            TabularField::Send => hsp.hit_to.to_string(),
+            TabularField::Evalue => format_evalue(hsp.evalue),
+            TabularField::Bitscore => format_bit_score(hsp.bit_score),
+            TabularField::Score => hsp.score.to_string(),
+            TabularField::Nident => hsp.identity.to_string(),
+            TabularField::Positive => hsp.positive.to_string(),
+            TabularField::Ppos => {
+                if hsp.align_len == 0 {
+                    "0.00".to_string()
+                } else {
+                    format!("{:.2}", 100.0 * hsp.positive as f64 / hsp.align_len as f64)
+                }
+            }
+            TabularField::Qlen => result.query_len.to_string(),
+            TabularField::Slen => hit.len.to_string(),
+            TabularField::Qseq => hsp.qseq.clone(),
+            TabularField::Sseq => String::from_utf8_lossy(hsp.hseq.seq()).to_string(),
+            TabularField::Qframe => hsp.query_frame.unwrap_or(0).to_string(),
+            TabularField::Sframe => hsp.hit_frame.unwrap_or(0).to_string(),
+            TabularField::Sstrand => hsp
+                .hit_strand
+                .as_ref()
+                .map(|strand| strand.to_lowercase())
+                .unwrap_or_else(|| "N/A".to_string()),
+            TabularField::Staxids => {
+                let mut taxids: Vec<u32> = Vec::new();
+                for taxid in hit.description.iter().map(|d| d.taxid) {
+                    if taxid != 0 && !taxids.contains(&taxid) {
+                        taxids.push(taxid);
+                    }
+                }
+                join_or_na(taxids.iter().map(|taxid| taxid.to_string()).collect())
+            }
+            TabularField::Sscinames => {
+                let mut names: Vec<String> = Vec::new();
+                for name in hit.description.iter().map(|d| &d.sciname) {
+                    if !name.is_empty() && !names.contains(name) {
+                        names.push(name.clone());
+                    }
+                }
+                join_or_na(names)
+            }
+        }
+    }
+}
+
+impl Display for TabularField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TabularField::Qseqid => "qseqid",
+            TabularField::Qacc => "qacc",
+            TabularField::Qaccver => "qaccver",
+            TabularField::Sseqid => "sseqid",
+            TabularField::Sacc => "sacc",
+            TabularField::Saccver => "saccver",
+            TabularField::Stitle => "stitle",
+            TabularField::Pident => "pident",
+            TabularField::Length => "length",
+            TabularField::Mismatch => "mismatch",
+            TabularField::Gapopen => "gapopen",
+            TabularField::Gaps => "gaps",
+            TabularField::Qstart => "qstart",
+            TabularField::Qend => "qend",
+            TabularField::Sstart => "sstart",
+            TabularField::Send => "send",
+            TabularField::Evalue => "evalue",
+            TabularField::Bitscore => "bitscore",
+            TabularField::Score => "score",
+            TabularField::Nident => "nident",
+            TabularField::Positive => "positive",
+            TabularField::Ppos => "ppos",
+            TabularField::Qlen => "qlen",
+            TabularField::Slen => "slen",
+            TabularField::Qseq => "qseq",
+            TabularField::Sseq => "sseq",
+            TabularField::Qframe => "qframe",
+            TabularField::Sframe => "sframe",
+            TabularField::Sstrand => "sstrand",
+            TabularField::Staxids => "staxids",
+            TabularField::Sscinames => "sscinames",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TabularField {
+    type Err = EbioticError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL_FIELDS
+            .iter()
+            .find(|field| field.to_string() == s.trim())
+            .copied()
+            .ok_or_else(|| EbioticError::UnknownTabularField(s.to_string()))
+    }
+}
+
+impl BlastResult {
+    /// Write one line per HSP in BLAST tabular format (`-outfmt 6`), with the given columns.
+    pub fn write_tabular<W: Write>(
+        &self,
+        mut writer: W,
+        fields: &[TabularField],
+    ) -> EbioticResult<()> {
+        for hit in &self.hits {
+            for hsp in &hit.hsps {
+                let row: Vec<String> = fields
+                    .iter()
+                    .map(|field| field.value(self, hit, hsp))
+                    .collect();
+                writeln!(writer, "{}", row.join("\t"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the HSPs in commented BLAST tabular format (`-outfmt 7`), with the given columns.
+    pub fn write_tabular_with_comments<W: Write>(
+        &self,
+        mut writer: W,
+        fields: &[TabularField],
+    ) -> EbioticResult<()> {
+        let version = match (&self.version, &self.program) {
+            (Some(version), _) => version.clone(),
+            (None, Some(program)) => program.to_uppercase(),
+            (None, None) => "BLAST".to_string(),
+        };
+        writeln!(writer, "# {}", version)?;
+        if self.query_title.is_empty() {
+            writeln!(writer, "# Query: {}", self.query_id)?;
+        } else {
+            writeln!(writer, "# Query: {}", self.query_title)?;
+        }
+        if let Some(database) = &self.database {
+            writeln!(writer, "# Database: {}", database)?;
+        }
+
+        let hsps: usize = self.hits.iter().map(|hit| hit.hsps.len()).sum();
+        if hsps > 0 {
+            let labels: Vec<&str> = fields.iter().map(|field| field.label()).collect();
+            writeln!(writer, "# Fields: {}", labels.join(", "))?;
+        }
+        writeln!(writer, "# {} hits found", hsps)?;
+        self.write_tabular(writer, fields)
+    }
+
+    /// Write the result as a BLAST XML2 document (`-outfmt 16`).
+    pub fn write_xml2<W: Write>(&self, writer: W) -> EbioticResult<()> {
+        BlastResult::write_xml2_all(std::slice::from_ref(self), writer)
+    }
+
+    /// Write several results, e.g. those of a multi-query search, as a single BLAST XML2 document.
+    pub fn write_xml2_all<W: Write>(results: &[BlastResult], mut writer: W) -> EbioticResult<()> {
+        writeln!(writer, r#"<?xml version="1.0"?>"#)?;
+        writeln!(
+            writer,
+            r#"<BlastXML2 xmlns="http://www.ncbi.nlm.nih.gov" xmlns:xs="http://www.w3.org/2001/XMLSchema-instance" xs:schemaLocation="http://www.ncbi.nlm.nih.gov http://www.ncbi.nlm.nih.gov/data_specs/schema_alt/NCBI_BlastOutput2.xsd">"#
+        )?;
+        for result in results {
+            let mut xml = XmlWriter::new(&mut writer, 1);
+            xml.open("BlastOutput2")?;
+            xml.open("report")?;
+            xml.open("Report")?;
+            xml.element("program", result.program.as_deref().unwrap_or(""))?;
+            xml.element("version", result.version.as_deref().unwrap_or(""))?;
+            xml.element("reference", "")?;
+            xml.open("search-target")?;
+            xml.open("Target")?;
+            xml.element("db", result.database.as_deref().unwrap_or(""))?;
+            xml.close("Target")?;
+            xml.close("search-target")?;
+            xml.open("params")?;
+            xml.open("Parameters")?;
+            xml.close("Parameters")?;
+            xml.close("params")?;
+            xml.open("results")?;
+            xml.open("Results")?;
+            xml.open("search")?;
+            result.write_search(&mut xml)?;
+            xml.close("search")?;
+            xml.close("Results")?;
+            xml.close("results")?;
+            xml.close("Report")?;
+            xml.close("report")?;
+            xml.close("BlastOutput2")?;
+        }
+        writeln!(writer, "</BlastXML2>")?;
+        Ok(())
+    }
+
+    fn write_search<W: Write>(&self, xml: &mut XmlWriter<W>) -> EbioticResult<()> {
+        xml.open("Search")?;
+        xml.element("query-id", &self.query_id)?;
+        if !self.query_title.is_empty() {
+            xml.element("query-title", &self.query_title)?;
+        }
+        xml.element("query-len", self.query_len)?;
+        if !self.hits.is_empty() {
+            xml.open("hits")?;
+            for hit in &self.hits {
+                write_hit(xml, hit)?;
+            }
+            xml.close("hits")?;
+        }
+        if let Some(stat) = &self.stat {
+            xml.open("stat")?;
+            xml.open("Statistics")?;
+            xml.element("db-num", stat.db_num)?;
+            xml.element("db-len", stat.db_len)?;
+            xml.element("hsp-len", stat.hsp_len)?;
+            xml.element("eff-space", stat.eff_space)?;
+            xml.element("kappa", stat.kappa)?;
+            xml.element("lambda", stat.lambda)?;
+            xml.element("entropy", stat.entropy)?;
+            xml.close("Statistics")?;
+            xml.close("stat")?;
+        }
+        if let Some(message) = &self.message {
+            xml.element("message", message)?;
+        }
+        xml.close("Search")
+    }
+}
+
+fn write_hit<W: Write>(xml: &mut XmlWriter<W>, hit: &Hit) -> EbioticResult<()> {
+    xml.open("Hit")?;
+    xml.element("num", hit.num)?;
+    xml.open("description")?;
+    for description in &hit.description {
+        xml.open("HitDescr")?;
+        xml.element("id", &description.id)?;
+        xml.element("accession", &description.accession)?;
+        xml.element("title", &description.title)?;
+        if description.taxid != 0 {
+            xml.element("taxid", description.taxid)?;
+        }
+        if !description.sciname.is_empty() {
+            xml.element("sciname", &description.sciname)?;
+        }
+        xml.close("HitDescr")?;
+    }
+    xml.close("description")?;
+    xml.element("len", hit.len)?;
+    xml.open("hsps")?;
+    for hsp in &hit.hsps {
+        xml.open("Hsp")?;
+        xml.element("num", hsp.num)?;
+        xml.element("bit-score", hsp.bit_score)?;
+        xml.element("score", hsp.score)?;
+        xml.element("evalue", format_double(hsp.evalue))?;
+        xml.element("identity", hsp.identity)?;
+        xml.element("positive", hsp.positive)?;
+        xml.element("query-from", hsp.query_from)?;
+        xml.element("query-to", hsp.query_to)?;
+        if let Some(strand) = &hsp.query_strand {
+            xml.element("query-strand", strand)?;
+        }
+        if let Some(frame) = hsp.query_frame {
+            xml.element("query-frame", frame)?;
+        }
+        xml.element("hit-from", hsp.hit_from)?;
+        xml.element("hit-to", hsp.hit_to)?;
+        if let Some(strand) = &hsp.hit_strand {
+            xml.element("hit-strand", strand)?;
+        }
+        if let Some(frame) = hsp.hit_frame {
+            xml.element("hit-frame", frame)?;
+        }
+        xml.element("align-len", hsp.align_len)?;
+        xml.element("gaps", hsp.gaps)?;
+        xml.element("qseq", &hsp.qseq)?;
+        xml.element("hseq", String::from_utf8_lossy(hsp.hseq.seq()))?;
+        xml.element("midline", &hsp.midline)?;
+        xml.close("Hsp")?;
+    }
+    xml.close("hsps")?;
+    xml.close("Hit")
+}
+
+/// The `XmlWriter` struct writes indented XML elements.
+struct XmlWriter<W> {
+    writer: W,
+    depth: usize,
+}
+
+impl<W: Write> XmlWriter<W> {
+    fn new(writer: W, depth: usize) -> XmlWriter<W> {
+        XmlWriter { writer, depth }
+    }
+
+    fn open(&mut self, tag: &str) -> EbioticResult<()> {
+        writeln!(self.writer, "{}<{}>", "  ".repeat(self.depth), tag)?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn close(&mut self, tag: &str) -> EbioticResult<()> {
+        self.depth -= 1;
+        writeln!(self.writer, "{}</{}>", "  ".repeat(self.depth), tag)?;
+        Ok(())
+    }
+
+    fn element<T: Display>(&mut self, tag: &str, value: T) -> EbioticResult<()> {
+        writeln!(
+            self.writer,
+            "{}<{}>{}</{}>",
+            "  ".repeat(self.depth),
+            tag,
+            escape_xml(&value.to_string()),
+            tag
+        )?;
+        Ok(())
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn describe<F: Fn(&Description) -> String>(description: Option<&Description>, field: F) -> String {
+    description.map(field).unwrap_or_else(|| "N/A".to_string())
+}
+
+/// Find the versioned accession in a FASTA id such as `ref|XP_023570734.1|`, falling back to the accession.
+fn accession_version(description: &Description) -> String {
+    description
+        .id
+        .split('|')
+        .find(|part| {
+            part.strip_prefix(description.accession.as_str())
+                .is_some_and(|version| version.starts_with('.'))
+        })
+        .unwrap_or(&description.accession)
+        .to_string()
+}
+
+fn join_or_na(values: Vec<String>) -> String {
+    if values.is_empty() {
+        "N/A".to_string()
+    } else {
+        values.join(";")
+    }
+}
+
+/// Format a double as BLAST does in XML, using an exponent for very small or large values.
+fn format_double(value: f64) -> String {
+    if value == 0.0 || (1e-4..1e6).contains(&value.abs()) {
+        value.to_string()
+    } else {
+        format!("{:e}", value)
+    }
+}
+
+/// Format an e-value as BLAST+ does in tabular output, e.g. `0.0`, `1.27e-164` or `0.003`.
+fn format_evalue(evalue: f64) -> String {
+    if evalue < 1.0e-180 {
+        "0.0".to_string()
+    } else if evalue < 1.0e-3 {
+        // C's printf pads the exponent to two digits, which Rust does not.
+        let formatted = format!("{:.2e}", evalue);
+        match formatted.split_once("e-") {
+            Some((mantissa, exponent)) if exponent.len() < 2 => {
+                format!("{}e-0{}", mantissa, exponent)
+            }
+            _ => formatted,
+        }
+    } else if evalue < 0.1 {
+        format!("{:.3}", evalue)
+    } else if evalue < 1.0 {
+        format!("{:.2}", evalue)
+    } else if evalue < 10.0 {
+        format!("{:.1}", evalue)
+    } else {
+        format!("{:.0}", evalue)
+    }
+}
+
+/// Format a bit score as BLAST+ does in tabular output.
+fn format_bit_score(bit_score: f64) -> String {
+    if bit_score > 9999.0 {
+        format!("{:.3e}", bit_score)
+    } else if bit_score > 99.9 {
+        format!("{:.0}", bit_score)
+    } else {
+        format!("{:.1}", bit_score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Blast;
+    use super::*;
+
+    fn example_result() -> BlastResult {
+        let test_json = include_str!("../../../tests/example_blast_response.json");
+        Blast::default().parse_raw_results(test_json).unwrap()
+    }
+
+    #[test]
+    fn writes_standard_tabular_rows() {
+        let result = example_result();
+        let mut out = Vec::new();
+
+        result
+            .write_tabular(&mut out, &TabularField::standard())
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let first: Vec<&str> = out.lines().next().unwrap().split('\t').collect();
+
+        assert_eq!(out.lines().count(), 100);
+        assert_eq!(
+            first,
+            vec![
+                "Query_83953",
+                "ref|XP_023570734.1|",
+                "96.907",
+                "194",
+                "5",
+                "1",
+                "5",
+                "198",
+                "9",
+                "201",
+                "6.12e-135",
+                "389"
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_commented_tabular_headers() {
+        let result = example_result();
+        let fields: Vec<TabularField> = "qacc sacc evalue staxids"
+            .split_whitespace()
+            .map(|field| field.parse().unwrap())
+            .collect();
+        let mut out = Vec::new();
+
+        result
+            .write_tabular_with_comments(&mut out, &fields)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        assert_eq!(lines[0], "# BLASTP 2.14.1+");
+        assert_eq!(lines[1], "# Query: unnamed protein product");
+        assert_eq!(lines[2], "# Database: nr");
+        assert_eq!(
+            lines[3],
+            "# Fields: query acc., subject acc., evalue, subject tax ids"
+        );
+        assert_eq!(lines[4], "# 100 hits found");
+        assert_eq!(
+            lines[5],
+            "Query_83953\tXP_023570734\t6.12e-135\t10160;56216"
+        );
+        assert!("qstrand".parse::<TabularField>().is_err());
+
+        let mut out = Vec::new();
+        result
+            .write_tabular(&mut out, &[TabularField::Saccver])
+            .unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("XP_023570734.1\n"));
+    }
+
+    #[test]
+    fn writes_blast_xml2() {
+        let result = example_result();
+        let mut out = Vec::new();
+
+        result.write_xml2(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.starts_with("<?xml version=\"1.0\"?>\n<BlastXML2"));
+        assert!(out.contains("<program>blastp</program>"));
+        assert!(out.contains("<query-id>Query_83953</query-id>"));
+        assert!(out.contains("<evalue>6.12199e-135</evalue>"));
+        assert!(out.contains("<db-num>628135233</db-num>"));
+        assert_eq!(out.matches("<Hsp>").count(), 100);
+        assert!(out.trim_end().ends_with("</BlastXML2>"));
+    }
+
+    #[test]
+    fn formats_evalues_like_blast() {
+        assert_eq!(format_evalue(0.0), "0.0");
+        assert_eq!(format_evalue(1.4e-5), "1.40e-05");
+        assert_eq!(format_evalue(0.0034), "0.003");
+        assert_eq!(format_evalue(2.5), "2.5");
+        assert_eq!(escape_xml("a < b & \"c\""), "a &lt; b &amp; &quot;c&quot;");
+    }
+}
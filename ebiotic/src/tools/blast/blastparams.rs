@@ -0,0 +1,432 @@
+use std::fmt::{Display, Formatter};
+
+use crate::core::EbioticResult;
+use crate::errors::EbioticError;
+
+/// The `BlastSequenceType` enum is used to specify the alphabet of a BLAST query or database.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BlastSequenceType {
+    Nucleotide,
+    Protein,
+}
+
+/// The `BlastProgram` enum is used to specify the search program of the `Blast` service.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BlastProgram {
+    /// Protein query against a protein database.
+    Blastp,
+    /// Nucleotide query against a nucleotide database.
+    Blastn,
+    /// Translated nucleotide query against a protein database.
+    Blastx,
+    /// Protein query against a translated nucleotide database.
+    Tblastn,
+    /// Translated nucleotide query against a translated nucleotide database.
+    Tblastx,
+    /// `blastn` tuned for highly similar sequences.
+    Megablast,
+    /// `blastn` tuned for more dissimilar sequences, e.g. across species.
+    DiscontiguousMegablast,
+}
+
+/// The `BlastMatrix` enum is used to specify the scoring matrix of protein comparisons.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BlastMatrix {
+    Blosum45,
+    Blosum50,
+    Blosum62,
+    Blosum80,
+    Blosum90,
+    Pam30,
+    Pam70,
+    Pam250,
+}
+
+/// The `BlastParams` struct is used to specify the optional search parameters of the NCBI BLAST URL API.
+///
+/// Parameters left as `None` are not sent, so the server defaults for the program are used.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlastParams {
+    expect: Option<f64>,
+    word_size: Option<u32>,
+    gapcosts: Option<(u32, u32)>,
+    filter: Option<String>,
+    entrez_query: Option<String>,
+    organism: Option<String>,
+    nucl_reward: Option<u32>,
+    nucl_penalty: Option<i32>,
+    composition_based_statistics: Option<u8>,
+    alignments: Option<u32>,
+    descriptions: Option<u32>,
+}
+
+impl BlastProgram {
+    /// The alphabet of the query sequence.
+    pub fn query_type(&self) -> BlastSequenceType {
+        match self {
+            BlastProgram::Blastp | BlastProgram::Tblastn => BlastSequenceType::Protein,
+            _ => BlastSequenceType::Nucleotide,
+        }
+    }
+
+    /// The alphabet of the database sequences.
+    pub fn database_type(&self) -> BlastSequenceType {
+        match self {
+            BlastProgram::Blastp | BlastProgram::Blastx => BlastSequenceType::Protein,
+            _ => BlastSequenceType::Nucleotide,
+        }
+    }
+
+    /// Whether the sequences are compared as nucleotides, i.e. one of the `blastn` variants.
+    pub fn is_nucleotide_comparison(&self) -> bool {
+        matches!(
+            self,
+            BlastProgram::Blastn | BlastProgram::Megablast | BlastProgram::DiscontiguousMegablast
+        )
+    }
+
+    /// The form fields selecting the program. The megablast variants are sent as `blastn` with extra options.
+    pub(crate) fn form_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("PROGRAM", self.to_string())];
+        match self {
+            BlastProgram::Megablast => fields.push(("MEGABLAST", "on".to_string())),
+            BlastProgram::DiscontiguousMegablast => {
+                fields.push(("MEGABLAST", "on".to_string()));
+                fields.push(("TEMPLATE_TYPE", "0".to_string()));
+                fields.push(("TEMPLATE_LENGTH", "18".to_string()));
+            }
+            _ => {}
+        }
+        fields
+    }
+}
+
+impl Display for BlastProgram {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlastProgram::Blastp => write!(f, "blastp"),
+            BlastProgram::Blastn
+            | BlastProgram::Megablast
+            | BlastProgram::DiscontiguousMegablast => write!(f, "blastn"),
+            BlastProgram::Blastx => write!(f, "blastx"),
+            BlastProgram::Tblastn => write!(f, "tblastn"),
+            BlastProgram::Tblastx => write!(f, "tblastx"),
+        }
+    }
+}
+
+impl Display for BlastMatrix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlastMatrix::Blosum45 => write!(f, "BLOSUM45"),
+            BlastMatrix::Blosum50 => write!(f, "BLOSUM50"),
+            BlastMatrix::Blosum62 => write!(f, "BLOSUM62"),
+            BlastMatrix::Blosum80 => write!(f, "BLOSUM80"),
+            BlastMatrix::Blosum90 => write!(f, "BLOSUM90"),
+            BlastMatrix::Pam30 => write!(f, "PAM30"),
+            BlastMatrix::Pam70 => write!(f, "PAM70"),
+            BlastMatrix::Pam250 => write!(f, "PAM250"),
+        }
+    }
+}
+
+impl BlastParams {
+    pub fn new() -> BlastParams {
+        BlastParams::default()
+    }
+
+    /// Set the expect value threshold for reporting hits.
+    pub fn set_expect(&mut self, expect: Option<f64>) {
+        self.expect = expect;
+    }
+
+    pub fn set_word_size(&mut self, word_size: Option<u32>) {
+        self.word_size = word_size;
+    }
+
+    /// Set the gap existence and extension costs.
+    pub fn set_gapcosts(&mut self, gapcosts: Option<(u32, u32)>) {
+        self.gapcosts = gapcosts;
+    }
+
+    /// Set the low complexity filter, e.g. `"L"` to filter, `"F"` to disable it, or `"m L"` to mask lookups only.
+    pub fn set_filter(&mut self, filter: Option<String>) {
+        self.filter = filter;
+    }
+
+    /// Set an Entrez query restricting the database sequences searched, e.g. `"biomol_mrna[PROP]"`.
+    pub fn set_entrez_query(&mut self, entrez_query: Option<String>) {
+        self.entrez_query = entrez_query;
+    }
+
+    /// Restrict the search to an organism, given by name (e.g. `"Homo sapiens"`) or NCBI taxonomy id (e.g. `"9606"`).
+    ///
+    /// The restriction is sent as part of the `ENTREZ_QUERY`, combined with any Entrez query that is set.
+    pub fn set_organism(&mut self, organism: Option<String>) {
+        self.organism = organism;
+    }
+
+    /// Set the reward for a nucleotide match.
+    pub fn set_nucl_reward(&mut self, nucl_reward: Option<u32>) {
+        self.nucl_reward = nucl_reward;
+    }
+
+    /// Set the (negative) penalty for a nucleotide mismatch.
+    pub fn set_nucl_penalty(&mut self, nucl_penalty: Option<i32>) {
+        self.nucl_penalty = nucl_penalty;
+    }
+
+    /// Set the composition-based statistics mode, from `0` (off) to `3`.
+    pub fn set_composition_based_statistics(&mut self, mode: Option<u8>) {
+        self.composition_based_statistics = mode;
+    }
+
+    /// Set the number of alignments to report.
+    pub fn set_alignments(&mut self, alignments: Option<u32>) {
+        self.alignments = alignments;
+    }
+
+    /// Set the number of descriptions to report.
+    pub fn set_descriptions(&mut self, descriptions: Option<u32>) {
+        self.descriptions = descriptions;
+    }
+
+    pub fn expect(&self) -> &Option<f64> {
+        &self.expect
+    }
+
+    pub fn word_size(&self) -> &Option<u32> {
+        &self.word_size
+    }
+
+    pub fn gapcosts(&self) -> &Option<(u32, u32)> {
+        &self.gapcosts
+    }
+
+    pub fn filter(&self) -> &Option<String> {
+        &self.filter
+    }
+
+    pub fn entrez_query(&self) -> &Option<String> {
+        &self.entrez_query
+    }
+
+    pub fn organism(&self) -> &Option<String> {
+        &self.organism
+    }
+
+    pub fn nucl_reward(&self) -> &Option<u32> {
+        &self.nucl_reward
+    }
+
+    pub fn nucl_penalty(&self) -> &Option<i32> {
+        &self.nucl_penalty
+    }
+
+    pub fn composition_based_statistics(&self) -> &Option<u8> {
+        &self.composition_based_statistics
+    }
+
+    pub fn alignments(&self) -> &Option<u32> {
+        &self.alignments
+    }
+
+    pub fn descriptions(&self) -> &Option<u32> {
+        &self.descriptions
+    }
+
+    /// Check the parameters can be used with `program` and `matrix`, so that invalid searches are rejected
+    /// before they are submitted.
+    pub fn validate(
+        &self,
+        program: &BlastProgram,
+        matrix: &Option<BlastMatrix>,
+    ) -> EbioticResult<()> {
+        let nucleotide = program.is_nucleotide_comparison();
+
+        if let Some(matrix) = matrix {
+            if nucleotide {
+                return invalid(format!(
+                    "MATRIX {} cannot be used with {:?}, which compares nucleotides",
+                    matrix, program
+                ));
+            }
+        }
+        if !nucleotide && (self.nucl_reward.is_some() || self.nucl_penalty.is_some()) {
+            return invalid(format!(
+                "NUCL_REWARD and NUCL_PENALTY cannot be used with {:?}, which compares proteins",
+                program
+            ));
+        }
+        if self.nucl_penalty.is_some_and(|penalty| penalty >= 0) {
+            return invalid("NUCL_PENALTY must be negative".to_string());
+        }
+        if self.nucl_reward == Some(0) {
+            return invalid("NUCL_REWARD must be positive".to_string());
+        }
+        if let Some(mode) = self.composition_based_statistics {
+            if !matches!(
+                program,
+                BlastProgram::Blastp | BlastProgram::Blastx | BlastProgram::Tblastn
+            ) {
+                return invalid(format!(
+                    "COMPOSITION_BASED_STATISTICS cannot be used with {:?}",
+                    program
+                ));
+            }
+            if mode > 3 {
+                return invalid(format!(
+                    "COMPOSITION_BASED_STATISTICS must be between 0 and 3, not {}",
+                    mode
+                ));
+            }
+        }
+        if self.gapcosts.is_some() && program == &BlastProgram::Tblastx {
+            return invalid("GAPCOSTS cannot be used with Tblastx, which is ungapped".to_string());
+        }
+        if let Some(word_size) = self.word_size {
+            let valid = match program {
+                BlastProgram::DiscontiguousMegablast => word_size == 11 || word_size == 12,
+                _ if nucleotide => word_size >= 4,
+                _ => (2..=7).contains(&word_size),
+            };
+            if !valid {
+                return invalid(format!(
+                    "WORD_SIZE {} cannot be used with {:?}",
+                    word_size, program
+                ));
+            }
+        }
+        if self.expect.is_some_and(|expect| expect <= 0.0) {
+            return invalid("EXPECT must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// The form fields of the parameters which are set.
+    pub(crate) fn form_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let Some(expect) = self.expect {
+            fields.push(("EXPECT", expect.to_string()));
+        }
+        if let Some(word_size) = self.word_size {
+            fields.push(("WORD_SIZE", word_size.to_string()));
+        }
+        if let Some((existence, extension)) = self.gapcosts {
+            fields.push(("GAPCOSTS", format!("{} {}", existence, extension)));
+        }
+        if let Some(filter) = &self.filter {
+            fields.push(("FILTER", filter.clone()));
+        }
+        if let Some(entrez_query) = self.combined_entrez_query() {
+            fields.push(("ENTREZ_QUERY", entrez_query));
+        }
+        if let Some(nucl_reward) = self.nucl_reward {
+            fields.push(("NUCL_REWARD", nucl_reward.to_string()));
+        }
+        if let Some(nucl_penalty) = self.nucl_penalty {
+            fields.push(("NUCL_PENALTY", nucl_penalty.to_string()));
+        }
+        if let Some(mode) = self.composition_based_statistics {
+            fields.push(("COMPOSITION_BASED_STATISTICS", mode.to_string()));
+        }
+        if let Some(alignments) = self.alignments {
+            fields.push(("ALIGNMENTS", alignments.to_string()));
+        }
+        if let Some(descriptions) = self.descriptions {
+            fields.push(("DESCRIPTIONS", descriptions.to_string()));
+        }
+        fields
+    }
+
+    fn combined_entrez_query(&self) -> Option<String> {
+        let organism = self.organism.as_ref().map(|organism| {
+            if organism.chars().all(|c| c.is_ascii_digit()) {
+                format!("txid{}[ORGN]", organism)
+            } else {
+                format!("\"{}\"[ORGN]", organism)
+            }
+        });
+        match (&self.entrez_query, organism) {
+            (Some(query), Some(organism)) => Some(format!("({}) AND {}", query, organism)),
+            (Some(query), None) => Some(query.clone()),
+            (None, organism) => organism,
+        }
+    }
+}
+
+fn invalid(message: String) -> EbioticResult<()> {
+    Err(EbioticError::InvalidBlastParameters(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn megablast_variants_are_sent_as_blastn() {
+        let fields = BlastProgram::Megablast.form_fields();
+        assert!(fields.contains(&("PROGRAM", "blastn".to_string())));
+        assert!(fields.contains(&("MEGABLAST", "on".to_string())));
+        assert_eq!(BlastProgram::Tblastx.form_fields().len(), 1);
+    }
+
+    #[test]
+    fn rejects_matrix_with_nucleotide_programs() {
+        let params = BlastParams::new();
+        assert!(matches!(
+            params.validate(&BlastProgram::Blastn, &Some(BlastMatrix::Blosum62)),
+            Err(EbioticError::InvalidBlastParameters(_))
+        ));
+        assert!(params
+            .validate(&BlastProgram::Blastx, &Some(BlastMatrix::Blosum62))
+            .is_ok());
+        assert!(params.validate(&BlastProgram::Megablast, &None).is_ok());
+    }
+
+    #[test]
+    fn rejects_parameters_for_the_wrong_program() {
+        let mut params = BlastParams::new();
+        params.set_nucl_reward(Some(1));
+        params.set_nucl_penalty(Some(-2));
+        assert!(params.validate(&BlastProgram::Blastn, &None).is_ok());
+        assert!(params
+            .validate(&BlastProgram::Blastp, &Some(BlastMatrix::Blosum62))
+            .is_err());
+
+        let mut params = BlastParams::new();
+        params.set_gapcosts(Some((11, 1)));
+        assert!(params.validate(&BlastProgram::Tblastx, &None).is_err());
+
+        let mut params = BlastParams::new();
+        params.set_word_size(Some(28));
+        assert!(params.validate(&BlastProgram::Megablast, &None).is_ok());
+        assert!(params
+            .validate(&BlastProgram::DiscontiguousMegablast, &None)
+            .is_err());
+    }
+
+    #[test]
+    fn organism_is_combined_with_the_entrez_query() {
+        let mut params = BlastParams::new();
+        params.set_organism(Some("9606".to_string()));
+        assert_eq!(
+            params.form_fields(),
+            vec![("ENTREZ_QUERY", "txid9606[ORGN]".to_string())]
+        );
+
+        params.set_organism(Some("Homo sapiens".to_string()));
+        params.set_entrez_query(Some("biomol_mrna[PROP]".to_string()));
+        params.set_gapcosts(Some((11, 1)));
+        assert_eq!(
+            params.form_fields(),
+            vec![
+                ("GAPCOSTS", "11 1".to_string()),
+                (
+                    "ENTREZ_QUERY",
+                    "(biomol_mrna[PROP]) AND \"Homo sapiens\"[ORGN]".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,523 @@
+use bio::io::fasta::Record;
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::blastformat::TabularField;
+use super::{parse_reports, BlastResult, Description, Hit, Hsp};
+use crate::core::EbioticResult;
+use crate::errors::EbioticError;
+
+/// XML2 elements holding numbers in the equivalent JSON2 report.
+const XML2_NUMBERS: [&str; 24] = [
+    "num",
+    "bit-score",
+    "score",
+    "evalue",
+    "identity",
+    "positive",
+    "query-from",
+    "query-to",
+    "query-frame",
+    "hit-from",
+    "hit-to",
+    "hit-frame",
+    "align-len",
+    "gaps",
+    "len",
+    "query-len",
+    "taxid",
+    "db-num",
+    "db-len",
+    "hsp-len",
+    "eff-space",
+    "kappa",
+    "lambda",
+    "entropy",
+];
+
+/// XML2 elements holding lists in the equivalent JSON2 report.
+const XML2_LISTS: [&str; 4] = ["hits", "hsps", "description", "query-masking"];
+
+impl BlastResult {
+    /// Parse the BLAST+ single file JSON output (`-outfmt 15`), one `BlastResult` per query.
+    pub fn from_json_reader<R: Read>(reader: R) -> EbioticResult<Vec<BlastResult>> {
+        let parsed: Value = serde_json::from_reader(reader)?;
+        parse_reports(&parsed)
+    }
+
+    /// Parse a BLAST+ JSON file (`-outfmt 15`).
+    pub fn from_json_file<P: AsRef<Path>>(path: P) -> EbioticResult<Vec<BlastResult>> {
+        BlastResult::from_json_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Parse the BLAST XML2 output (`-outfmt 16`), one `BlastResult` per query.
+    pub fn from_xml2_reader<R: Read>(mut reader: R) -> EbioticResult<Vec<BlastResult>> {
+        let mut xml = String::new();
+        reader.read_to_string(&mut xml)?;
+        let document = roxmltree::Document::parse(&xml).map_err(|err| {
+            EbioticError::MalformedResponse(format!("Invalid BLAST XML2: {}", err))
+        })?;
+
+        // Each BlastOutput2 element holds a report with the same structure as JSON2.
+        let reports: Vec<Value> = document
+            .root_element()
+            .children()
+            .filter(|node| node.has_tag_name("BlastOutput2"))
+            .map(|node| xml2_value(&node))
+            .collect();
+        parse_reports(&serde_json::json!({ "BlastOutput2": reports }))
+    }
+
+    /// Parse a BLAST XML2 file (`-outfmt 16`).
+    pub fn from_xml2_file<P: AsRef<Path>>(path: P) -> EbioticResult<Vec<BlastResult>> {
+        BlastResult::from_xml2_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Parse BLAST tabular output (`-outfmt 6` or `7`), one `BlastResult` per query.
+    ///
+    /// The columns are given by `fields`, unless the output has a `# Fields:` header (`-outfmt 7`). Only the values
+    /// present in the columns are filled in, e.g. HSPs have no midline and hits no length unless `slen` is a column.
+    /// Queries without hits are only reported by `-outfmt 7`.
+    pub fn from_tabular_reader<R: Read>(
+        reader: R,
+        fields: &[TabularField],
+    ) -> EbioticResult<Vec<BlastResult>> {
+        let mut parser = TabularParser::new(fields.to_vec());
+        for line in BufReader::new(reader).lines() {
+            parser.line(&line?)?;
+        }
+        Ok(parser.finish())
+    }
+
+    /// Parse a BLAST tabular file (`-outfmt 6` or `7`).
+    pub fn from_tabular_file<P: AsRef<Path>>(
+        path: P,
+        fields: &[TabularField],
+    ) -> EbioticResult<Vec<BlastResult>> {
+        BlastResult::from_tabular_reader(File::open(path)?, fields)
+    }
+}
+
+/// Convert an XML2 element to the JSON2 value it corresponds to.
+///
+/// Wrapper elements named after their type (e.g. `<search><Search>`) are collapsed, and hyphenated names use underscores.
+fn xml2_value(node: &roxmltree::Node) -> Value {
+    let name = node.tag_name().name();
+    let children: Vec<roxmltree::Node> = node.children().filter(|n| n.is_element()).collect();
+
+    if children.is_empty() {
+        let text = node.text().unwrap_or("").trim();
+        if XML2_NUMBERS.contains(&name) {
+            if let Ok(number) = text.parse::<i64>() {
+                return Value::from(number);
+            }
+            if let Ok(number) = text.parse::<f64>() {
+                return Value::from(number);
+            }
+        }
+        return Value::String(text.to_string());
+    }
+    if XML2_LISTS.contains(&name) {
+        return Value::Array(children.iter().map(xml2_value).collect());
+    }
+    if children.len() == 1
+        && children[0]
+            .tag_name()
+            .name()
+            .starts_with(|c: char| c.is_ascii_uppercase())
+    {
+        return xml2_value(&children[0]);
+    }
+
+    let mut object = Map::new();
+    for child in &children {
+        object.insert(child.tag_name().name().replace('-', "_"), xml2_value(child));
+    }
+    Value::Object(object)
+}
+
+/// The `TabularParser` struct is used to group the rows of tabular output into results, hits and HSPs.
+struct TabularParser {
+    fields: Vec<TabularField>,
+    version: Option<String>,
+    database: Option<String>,
+    results: Vec<BlastResult>,
+    current: Option<BlastResult>,
+    // Whether the current result was started by a `# Query:` comment, and has not yet seen a row
+    from_header: bool,
+}
+
+impl TabularParser {
+    fn new(fields: Vec<TabularField>) -> TabularParser {
+        TabularParser {
+            fields,
+            version: None,
+            database: None,
+            results: Vec::new(),
+            current: None,
+            from_header: false,
+        }
+    }
+
+    fn line(&mut self, line: &str) -> EbioticResult<()> {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        match line.strip_prefix('#') {
+            Some(comment) => self.comment(comment.trim()),
+            None => self.row(line),
+        }
+    }
+
+    fn comment(&mut self, comment: &str) -> EbioticResult<()> {
+        if let Some(title) = comment.strip_prefix("Query:") {
+            let title = title.trim();
+            let query_id = title.split_whitespace().next().unwrap_or("").to_string();
+            self.start(query_id, title.to_string());
+            self.from_header = true;
+        } else if let Some(database) = comment.strip_prefix("Database:") {
+            self.database = Some(database.trim().to_string());
+            if let Some(current) = &mut self.current {
+                current.database = self.database.clone();
+            }
+        } else if let Some(labels) = comment.strip_prefix("Fields:") {
+            self.fields = labels
+                .split(',')
+                .map(|label| {
+                    TabularField::from_label(label)
+                        .ok_or_else(|| EbioticError::UnknownTabularField(label.trim().to_string()))
+                })
+                .collect::<EbioticResult<Vec<TabularField>>>()?;
+        } else if comment.contains("BLAST") && !comment.starts_with("BLAST processed") {
+            self.version = Some(comment.to_string());
+        }
+        Ok(())
+    }
+
+    fn row(&mut self, line: &str) -> EbioticResult<()> {
+        let values: Vec<&str> = line.split('\t').collect();
+        if values.len() != self.fields.len() {
+            return Err(EbioticError::MalformedResponse(format!(
+                "Expected {} tabular columns, found {}: {}",
+                self.fields.len(),
+                values.len(),
+                line
+            )));
+        }
+        let fields = self.fields.clone();
+        let row = Row {
+            fields: &fields,
+            values,
+        };
+
+        let query_id = row
+            .first(&[
+                TabularField::Qseqid,
+                TabularField::Qaccver,
+                TabularField::Qacc,
+            ])
+            .unwrap_or("")
+            .to_string();
+        match &mut self.current {
+            Some(current) if self.from_header => current.query_id = query_id,
+            Some(current) if current.query_id == query_id => {}
+            _ => self.start(query_id, String::new()),
+        }
+        self.from_header = false;
+        let current = self.current.as_mut().expect("a result was started above");
+        if current.query_len == 0 {
+            current.query_len = row.parse(TabularField::Qlen)?.unwrap_or(0);
+        }
+
+        let description = row.description()?;
+        let hsp = row.hsp()?;
+        match current.hits.last_mut() {
+            Some(hit) if hit.description.first().map(|d| &d.id) == Some(&description.id) => {
+                let mut hsp = hsp;
+                hsp.num = hit.hsps.len() as u32 + 1;
+                hit.hsps.push(hsp);
+            }
+            _ => {
+                let hit = Hit {
+                    num: current.hits.len() as u32 + 1,
+                    description: vec![description],
+                    len: row.parse(TabularField::Slen)?.unwrap_or(0),
+                    hsps: vec![hsp],
+                };
+                current.hits.push(hit);
+            }
+        }
+        Ok(())
+    }
+
+    fn start(&mut self, query_id: String, query_title: String) {
+        if let Some(result) = self.current.take() {
+            self.results.push(result);
+        }
+        let program = self
+            .version
+            .as_ref()
+            .and_then(|version| version.split_whitespace().next())
+            .map(|program| program.to_lowercase());
+        self.current = Some(BlastResult {
+            query_id,
+            query_title,
+            query_len: 0,
+            hits: Vec::new(),
+            stat: None,
+            message: None,
+            program,
+            version: self.version.clone(),
+            database: self.database.clone(),
+        });
+    }
+
+    fn finish(mut self) -> Vec<BlastResult> {
+        if let Some(result) = self.current.take() {
+            self.results.push(result);
+        }
+        self.results
+    }
+}
+
+/// The `Row` struct is a single line of tabular output, with the values looked up by field.
+struct Row<'a> {
+    fields: &'a [TabularField],
+    values: Vec<&'a str>,
+}
+
+impl Row<'_> {
+    fn get(&self, field: TabularField) -> Option<&str> {
+        self.fields
+            .iter()
+            .position(|f| f == &field)
+            .map(|i| self.values[i].trim())
+            .filter(|value| *value != "N/A")
+    }
+
+    fn first(&self, fields: &[TabularField]) -> Option<&str> {
+        fields.iter().find_map(|field| self.get(*field))
+    }
+
+    fn parse<T: FromStr>(&self, field: TabularField) -> EbioticResult<Option<T>> {
+        match self.get(field) {
+            Some(value) => value.parse().map(Some).map_err(|_| {
+                EbioticError::MalformedResponse(format!("Invalid {} value: {}", field, value))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    fn description(&self) -> EbioticResult<Description> {
+        let id = self
+            .first(&[
+                TabularField::Sseqid,
+                TabularField::Saccver,
+                TabularField::Sacc,
+            ])
+            .unwrap_or("")
+            .to_string();
+        let accession = match (
+            self.get(TabularField::Sacc),
+            self.get(TabularField::Saccver),
+        ) {
+            (Some(accession), _) => accession.to_string(),
+            (None, Some(accession)) => accession
+                .split_once('.')
+                .map_or(accession, |(accession, _)| accession)
+                .to_string(),
+            (None, None) => id.clone(),
+        };
+        let first = |field: TabularField| {
+            self.get(field)
+                .and_then(|values| values.split(';').next())
+                .map(|value| value.to_string())
+        };
+
+        Ok(Description {
+            id,
+            accession,
+            title: self.get(TabularField::Stitle).unwrap_or("").to_string(),
+            taxid: first(TabularField::Staxids)
+                .and_then(|taxid| taxid.parse().ok())
+                .unwrap_or(0),
+            sciname: first(TabularField::Sscinames).unwrap_or_default(),
+            lineage: None,
+        })
+    }
+
+    fn hsp(&self) -> EbioticResult<Hsp> {
+        let align_len: u32 = self.parse(TabularField::Length)?.unwrap_or(0);
+        // Counts which were not written can be recovered from the percentages
+        let from_percent = |percent: Option<f64>| {
+            percent.map_or(0, |percent| {
+                (percent * align_len as f64 / 100.0).round() as u32
+            })
+        };
+        let identity = match self.parse(TabularField::Nident)? {
+            Some(identity) => identity,
+            None => from_percent(self.parse(TabularField::Pident)?),
+        };
+        let positive = match self.parse(TabularField::Positive)? {
+            Some(positive) => positive,
+            None => from_percent(self.parse(TabularField::Ppos)?),
+        };
+        let frame = |field: TabularField| -> EbioticResult<Option<i32>> {
+            Ok(self.parse(field)?.filter(|frame| *frame != 0))
+        };
+        let mismatch_count = self.parse(TabularField::Mismatch)?;
+        // The std columns have no gap count, but it follows from the identities and mismatches
+        let gaps = match (self.parse(TabularField::Gaps)?, mismatch_count) {
+            (Some(gaps), _) => gaps,
+            (None, Some(mismatches)) => align_len.saturating_sub(identity + mismatches),
+            (None, None) => 0,
+        };
+        let hit_strand = self.get(TabularField::Sstrand).map(|strand| match strand {
+            "plus" => "Plus".to_string(),
+            "minus" => "Minus".to_string(),
+            other => other.to_string(),
+        });
+
+        Ok(Hsp {
+            num: 1,
+            bit_score: self.parse(TabularField::Bitscore)?.unwrap_or(0.0),
+            score: self.parse(TabularField::Score)?.unwrap_or(0),
+            evalue: self.parse(TabularField::Evalue)?.unwrap_or(0.0),
+            identity,
+            positive,
+            query_from: self.parse(TabularField::Qstart)?.unwrap_or(0),
+            query_to: self.parse(TabularField::Qend)?.unwrap_or(0),
+            query_strand: None,
+            query_frame: frame(TabularField::Qframe)?,
+            hit_from: self.parse(TabularField::Sstart)?.unwrap_or(0),
+            hit_to: self.parse(TabularField::Send)?.unwrap_or(0),
+            hit_strand,
+            hit_frame: frame(TabularField::Sframe)?,
+            align_len,
+            gaps,
+            qseq: self.get(TabularField::Qseq).unwrap_or("").to_string(),
+            hseq: Record::with_attrs(
+                "consensus_hsp_seq",
+                None,
+                self.get(TabularField::Sseq).unwrap_or("").as_bytes(),
+            ),
+            midline: String::new(),
+            mismatch_count,
+            gap_open_count: self.parse(TabularField::Gapopen)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_JSON: &str = include_str!("../../../tests/example_blast_response.json");
+
+    #[test]
+    fn parses_blast_json() {
+        let results = BlastResult::from_json_reader(EXAMPLE_JSON.as_bytes()).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].hits().len(), 100);
+        assert_eq!(results[0].database().as_deref(), Some("nr"));
+    }
+
+    #[test]
+    fn xml2_round_trips_through_the_writer() {
+        let expected = BlastResult::from_json_reader(EXAMPLE_JSON.as_bytes())
+            .unwrap()
+            .remove(0);
+        let mut xml = Vec::new();
+        expected.write_xml2(&mut xml).unwrap();
+
+        let parsed = BlastResult::from_xml2_reader(xml.as_slice())
+            .unwrap()
+            .remove(0);
+
+        assert_eq!(parsed.query_id(), expected.query_id());
+        assert_eq!(parsed.version(), expected.version());
+        assert_eq!(parsed.hits().len(), expected.hits().len());
+        let (hsp, expected_hsp) = (&parsed.hits()[0].hsps()[0], &expected.hits()[0].hsps()[0]);
+        assert_eq!(hsp.evalue(), expected_hsp.evalue());
+        assert_eq!(hsp.hseq().seq(), expected_hsp.hseq().seq());
+        assert_eq!(hsp.midline(), expected_hsp.midline());
+        assert_eq!(parsed.stat().as_ref().unwrap().db_num(), &628135233);
+    }
+
+    #[test]
+    fn parses_commented_tabular_output() {
+        let tabular = "\
+# BLASTN 2.14.1+
+# Query: seq1 first query
+# Database: nt
+# Fields: query acc.ver, subject acc.ver, % identity, alignment length, mismatches, gap opens, q. start, q. end, s. start, s. end, evalue, bit score
+# 3 hits found
+seq1\tNM_000207.3\t100.000\t120\t0\t0\t1\t120\t40\t159\t1.02e-55\t222
+seq1\tNM_000207.3\t95.000\t40\t2\t0\t130\t169\t300\t261\t2.00e-10\t60.2
+seq1\tXM_001.1\t90.000\t100\t10\t0\t1\t100\t1\t100\t3.00e-30\t150
+# BLASTN 2.14.1+
+# Query: seq2
+# Database: nt
+# 0 hits found
+# BLAST processed 2 queries
+";
+        let results = BlastResult::from_tabular_reader(tabular.as_bytes(), &[]).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].query_id(), "seq1");
+        assert_eq!(results[0].query_title(), "seq1 first query");
+        assert_eq!(results[0].program().as_deref(), Some("blastn"));
+        assert_eq!(results[0].hits().len(), 2);
+        let hit = &results[0].hits()[0];
+        assert_eq!(hit.description()[0].accession(), "NM_000207");
+        assert_eq!(hit.hsps().len(), 2);
+        assert_eq!(hit.hsps()[1].num(), &2);
+        assert_eq!(hit.hsps()[1].identity(), &38);
+        assert_eq!(hit.hsps()[1].hit_from(), &300);
+        assert_eq!(results[1].query_id(), "seq2");
+        assert!(results[1].hits().is_empty());
+    }
+
+    #[test]
+    fn parses_tabular_output_with_given_columns() {
+        let tabular = "q1\ts1\t80.0\t50\t10\t0\t1\t50\t1\t50\t1e-5\t40.1\nq2\ts1\t70.0\t10\t3\t0\t1\t10\t1\t10\t0.5\t20.0\n";
+
+        let results =
+            BlastResult::from_tabular_reader(tabular.as_bytes(), &TabularField::standard())
+                .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].query_id(), "q2");
+        assert_eq!(results[0].hits()[0].hsps()[0].identity(), &40);
+        assert!(matches!(
+            BlastResult::from_tabular_reader(tabular.as_bytes(), &[TabularField::Qseqid]),
+            Err(EbioticError::MalformedResponse(_))
+        ));
+    }
+
+    #[test]
+    fn std_rows_with_gap_opens_round_trip() {
+        let tabular = "q1\ts1\t90.000\t100\t6\t2\t1\t98\t1\t100\t1e-40\t150\n";
+        let fields = TabularField::standard();
+
+        let result = BlastResult::from_tabular_reader(tabular.as_bytes(), &fields)
+            .unwrap()
+            .remove(0);
+        let hsp = &result.hits()[0].hsps()[0];
+        let mut written = Vec::new();
+        result.write_tabular(&mut written, &fields).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let columns: Vec<&str> = written.trim_end().split('\t').collect();
+
+        assert_eq!(hsp.identity(), &90);
+        assert_eq!(hsp.gaps(), &4);
+        assert_eq!(hsp.mismatches(), 6);
+        assert_eq!(hsp.gap_opens(), 2);
+        assert_eq!(&columns[..6], &["q1", "s1", "90.000", "100", "6", "2"]);
+    }
+}
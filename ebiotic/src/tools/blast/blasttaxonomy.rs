@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet};
+
+use super::BlastResult;
+use crate::core::{EbioticHttpClient, EbioticResult};
+use crate::data::{AccessionIds, DataReturnFormats, Dbfetch, DbfetchDbs, DbfetchStyle};
+use crate::errors::EbioticError;
+
+/// The `Taxon` struct is used to specify a single node of the taxonomy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Taxon {
+    taxid: u32,
+    scientific_name: String,
+    rank: Option<String>,
+}
+
+/// The `Lineage` struct is used to specify the lineage of a taxon, from the root of the taxonomy down to the taxon
+/// itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lineage {
+    taxa: Vec<Taxon>,
+}
+
+/// The `TaxonomyCache` struct is used to keep the lineages already resolved from the Dbfetch `Taxonomy` database, so
+/// that annotating several results only fetches each taxid once. Taxids unknown to Dbfetch are remembered as well.
+#[derive(Debug, Clone, Default)]
+pub struct TaxonomyCache {
+    lineages: HashMap<u32, Lineage>,
+    unknown: HashSet<u32>,
+}
+
+impl Taxon {
+    pub fn new(taxid: u32, scientific_name: String, rank: Option<String>) -> Taxon {
+        Taxon {
+            taxid,
+            scientific_name,
+            rank,
+        }
+    }
+
+    pub fn taxid(&self) -> &u32 {
+        &self.taxid
+    }
+
+    pub fn scientific_name(&self) -> &String {
+        &self.scientific_name
+    }
+
+    /// The rank of the taxon, or `None` for the unranked nodes, e.g. `cellular organisms`.
+    pub fn rank(&self) -> &Option<String> {
+        &self.rank
+    }
+}
+
+impl Lineage {
+    pub fn new(taxa: Vec<Taxon>) -> Lineage {
+        Lineage { taxa }
+    }
+
+    /// The taxa of the lineage, from the root down to the taxon itself.
+    pub fn taxa(&self) -> &Vec<Taxon> {
+        &self.taxa
+    }
+
+    /// The taxon the lineage belongs to.
+    pub fn taxon(&self) -> Option<&Taxon> {
+        self.taxa.last()
+    }
+
+    /// The taxon of the lineage with the given rank, e.g. `superkingdom` or `genus`.
+    pub fn at_rank(&self, rank: &str) -> Option<&Taxon> {
+        self.taxa
+            .iter()
+            .find(|taxon| taxon.rank.as_deref() == Some(rank))
+    }
+
+    /// Check whether the lineage passes through `taxid`, e.g. `2` for bacteria.
+    pub fn contains(&self, taxid: u32) -> bool {
+        self.taxa.iter().any(|taxon| taxon.taxid == taxid)
+    }
+
+    /// The deepest taxon shared by all of `lineages`, or `None` if there are none or they share no taxon.
+    pub fn lowest_common_ancestor<'a, I: IntoIterator<Item = &'a Lineage>>(
+        lineages: I,
+    ) -> Option<Taxon> {
+        let mut lineages = lineages.into_iter();
+        let mut common: &[Taxon] = &lineages.next()?.taxa;
+        for lineage in lineages {
+            let shared = common
+                .iter()
+                .zip(&lineage.taxa)
+                .take_while(|(a, b)| a.taxid == b.taxid)
+                .count();
+            common = &common[..shared];
+        }
+        common.last().cloned()
+    }
+}
+
+impl TaxonomyCache {
+    pub fn new() -> TaxonomyCache {
+        TaxonomyCache::default()
+    }
+
+    pub fn get(&self, taxid: u32) -> Option<&Lineage> {
+        self.lineages.get(&taxid)
+    }
+
+    /// Check whether `taxid` was looked up in Dbfetch without a lineage being found.
+    pub fn is_unknown(&self, taxid: u32) -> bool {
+        self.unknown.contains(&taxid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lineages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lineages.is_empty()
+    }
+
+    /// Fetch the lineages of the `taxids` not already cached from the Dbfetch `Taxonomy` database, using `client`
+    /// for the requests. Taxids unknown to Dbfetch are recorded so that they are not requested again.
+    pub async fn resolve<C: EbioticHttpClient + Clone>(
+        &mut self,
+        client: &C,
+        taxids: &[u32],
+    ) -> EbioticResult<()> {
+        let mut missing: Vec<u32> = Vec::new();
+        for taxid in taxids {
+            if *taxid != 0
+                && !self.lineages.contains_key(taxid)
+                && !self.unknown.contains(taxid)
+                && !missing.contains(taxid)
+            {
+                missing.push(*taxid);
+            }
+        }
+
+        let dbfetch = Dbfetch::new(
+            client.clone(),
+            DbfetchDbs::Taxonomy,
+            DataReturnFormats::Xml,
+            DbfetchStyle::Raw,
+        );
+        let ids = missing.iter().map(|taxid| taxid.to_string()).collect();
+        for result in dbfetch.run_batched(AccessionIds::new(ids)).await? {
+            for lineage in parse_taxonomy_xml(&result.data())? {
+                if let Some(taxon) = lineage.taxon() {
+                    self.lineages.insert(taxon.taxid, lineage);
+                }
+            }
+        }
+        for taxid in missing {
+            if !self.lineages.contains_key(&taxid) {
+                self.unknown.insert(taxid);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BlastResult {
+    /// Attach the lineage of its taxid to each description of the hits, resolving the taxids not yet in `cache` from
+    /// the Dbfetch `Taxonomy` database. Descriptions without a taxid, or with one unknown to Dbfetch, are left without
+    /// a lineage.
+    pub async fn annotate_lineages<C: EbioticHttpClient + Clone>(
+        &mut self,
+        client: &C,
+        cache: &mut TaxonomyCache,
+    ) -> EbioticResult<()> {
+        let taxids: Vec<u32> = self.hits.iter().flat_map(|hit| hit.taxids()).collect();
+        cache.resolve(client, &taxids).await?;
+
+        for description in self.hits.iter_mut().flat_map(|hit| &mut hit.description) {
+            description.lineage = cache.get(description.taxid).cloned();
+        }
+        Ok(())
+    }
+
+    /// Keep the hits with a description whose lineage passes through `taxid`, e.g. `2` for bacteria. Only annotated
+    /// descriptions are considered, see `annotate_lineages`.
+    pub fn filter_by_lineage(&self, taxid: u32) -> BlastResult {
+        self.filter_hits(|hit| {
+            hit.description
+                .iter()
+                .filter_map(|d| d.lineage.as_ref())
+                .any(|lineage| lineage.contains(taxid))
+        })
+    }
+
+    /// The lowest common ancestor of the annotated descriptions of every hit, see `annotate_lineages`.
+    pub fn lowest_common_ancestor(&self) -> Option<Taxon> {
+        Lineage::lowest_common_ancestor(
+            self.hits
+                .iter()
+                .flat_map(|hit| hit.description.iter())
+                .filter_map(|d| d.lineage.as_ref()),
+        )
+    }
+}
+
+/// Parse the lineages from the XML returned by the Dbfetch `Taxonomy` database.
+///
+/// Each entry is a `taxon` element with its ancestors listed in a `lineage` element, nearest first. Several entries
+/// may be returned as separate documents, so any XML declarations are dropped and the entries parsed together.
+fn parse_taxonomy_xml(xml: &str) -> EbioticResult<Vec<Lineage>> {
+    let mut body = String::from("<entries>");
+    let mut rest = xml;
+    while let Some(start) = rest.find("<?xml") {
+        body.push_str(&rest[..start]);
+        rest = rest[start..]
+            .split_once("?>")
+            .map_or("", |(_, after)| after);
+    }
+    body.push_str(rest);
+    body.push_str("</entries>");
+
+    let document = roxmltree::Document::parse(&body)
+        .map_err(|err| EbioticError::MalformedResponse(format!("Invalid taxonomy XML: {}", err)))?;
+
+    let mut lineages = Vec::new();
+    for node in document.descendants().filter(|node| {
+        node.has_tag_name("taxon") && !node.parent().is_some_and(|p| p.has_tag_name("lineage"))
+    }) {
+        let mut taxa: Vec<Taxon> = node
+            .children()
+            .filter(|child| child.has_tag_name("lineage"))
+            .flat_map(|lineage| lineage.children().filter(|n| n.has_tag_name("taxon")))
+            .map(|ancestor| taxon(&ancestor))
+            .collect::<EbioticResult<_>>()?;
+        taxa.reverse();
+        taxa.push(taxon(&node)?);
+        lineages.push(Lineage::new(taxa));
+    }
+    Ok(lineages)
+}
+
+fn taxon(node: &roxmltree::Node) -> EbioticResult<Taxon> {
+    let taxid = node
+        .attribute("taxId")
+        .and_then(|taxid| taxid.parse().ok())
+        .ok_or_else(|| EbioticError::MalformedResponse("Taxon without a taxId".to_string()))?;
+    Ok(Taxon::new(
+        taxid,
+        node.attribute("scientificName").unwrap_or("").to_string(),
+        node.attribute("rank")
+            .filter(|rank| !rank.is_empty() && *rank != "no rank")
+            .map(|rank| rank.to_string()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{EbioticClient, Fixture, Fixtures, HttpRequest, HttpResponse};
+    use crate::tools::blast::testing::ResultBuilder;
+
+    const HUMAN: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TAXON_SET>
+<taxon scientificName="Homo sapiens" commonName="human" taxId="9606" parentTaxId="9605" rank="species" hidden="false">
+  <lineage>
+    <taxon scientificName="Homo" taxId="9605" parentTaxId="40674" rank="genus" hidden="false"/>
+    <taxon scientificName="Mammalia" taxId="40674" parentTaxId="2759" rank="class" hidden="false"/>
+    <taxon scientificName="Eukaryota" taxId="2759" parentTaxId="131567" rank="superkingdom" hidden="false"/>
+    <taxon scientificName="cellular organisms" taxId="131567" parentTaxId="1" hidden="true"/>
+  </lineage>
+</taxon>
+</TAXON_SET>
+"#;
+
+    const MOUSE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TAXON_SET>
+<taxon scientificName="Mus musculus" commonName="house mouse" taxId="10090" parentTaxId="10088" rank="species" hidden="false">
+  <lineage>
+    <taxon scientificName="Mus" taxId="10088" parentTaxId="40674" rank="genus" hidden="false"/>
+    <taxon scientificName="Mammalia" taxId="40674" parentTaxId="2759" rank="class" hidden="false"/>
+    <taxon scientificName="Eukaryota" taxId="2759" parentTaxId="131567" rank="superkingdom" hidden="false"/>
+    <taxon scientificName="cellular organisms" taxId="131567" parentTaxId="1" hidden="true"/>
+  </lineage>
+</taxon>
+</TAXON_SET>
+"#;
+
+    fn result(taxids: &[u32]) -> BlastResult {
+        taxids
+            .iter()
+            .enumerate()
+            .fold(ResultBuilder::new(10), |builder, (i, taxid)| {
+                builder.hit(&format!("H{}", i), *taxid, &[])
+            })
+            .build()
+    }
+
+    #[test]
+    fn parses_lineages_from_the_root_down() {
+        let lineages = parse_taxonomy_xml(&format!("{}{}", HUMAN, MOUSE)).unwrap();
+
+        assert_eq!(lineages.len(), 2);
+        let human = &lineages[0];
+        assert_eq!(human.taxa().len(), 5);
+        assert_eq!(human.taxa()[0].scientific_name(), "cellular organisms");
+        assert_eq!(human.taxa()[0].rank(), &None);
+        assert_eq!(human.taxon().unwrap().taxid(), &9606);
+        assert_eq!(human.at_rank("genus").unwrap().taxid(), &9605);
+        assert!(human.contains(2759));
+        assert!(!human.contains(2));
+
+        let ancestor = Lineage::lowest_common_ancestor(&lineages).unwrap();
+        assert_eq!(ancestor.scientific_name(), "Mammalia");
+        assert_eq!(ancestor.rank().as_deref(), Some("class"));
+    }
+
+    #[tokio::test]
+    async fn annotates_hits_with_cached_lineages() {
+        let client = EbioticClient::replay(Fixtures::new(vec![
+            Fixture::new(
+                HttpRequest::get("https://www.ebi.ac.uk/Tools/dbfetch/?db=taxonomy&format=xml&style=raw&id=9606,10090,999999999"),
+                HttpResponse::new(200, format!("{}{}", HUMAN, MOUSE)),
+            ),
+        ]));
+        let mut cache = TaxonomyCache::new();
+
+        let mut result = result(&[9606, 10090, 0, 9606, 999999999]);
+        result.annotate_lineages(&client, &mut cache).await.unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.is_unknown(999999999));
+        let lineage = result.hits()[1].description()[0].lineage().as_ref();
+        assert_eq!(lineage.unwrap().taxon().unwrap().taxid(), &10090);
+        assert!(result.hits()[2].description()[0].lineage().is_none());
+        assert_eq!(result.filter_by_lineage(9605).hits().len(), 2);
+        assert_eq!(result.lowest_common_ancestor().unwrap().taxid(), &40674);
+
+        // Every taxid is cached, including the unknown one, so no further request is made
+        let offline = EbioticClient::replay(Fixtures::new(vec![]));
+        let mut again = self::result(&[10090, 999999999]);
+        again.annotate_lineages(&offline, &mut cache).await.unwrap();
+        assert!(again.hits()[0].description()[0].lineage().is_some());
+    }
+}
@@ -1,24 +1,42 @@
 use bio::io::fasta::Record;
 
-use std::collections::HashMap;
 use std::fmt::Write;
+use std::sync::Arc;
 
 use super::EBI_TOOLS_ENDPOINT;
-use crate::core::{EbioticClient, EbioticHttpClient, PollStatus, PollableService, Service};
+use crate::core::{
+    decode_response, report_fetch, CancellationToken, EbioticClient, EbioticHttpClient,
+    EbioticResult, ErrorDecoder, JobEvent, JobHandle, JobObserver, JobService, PollPolicy,
+    PollStatus, PollableService, Service,
+};
 use crate::errors::EbioticError;
 
+pub mod clustaloparams;
+pub mod multiplealignment;
+pub mod percentidentitymatrix;
+pub mod phylotree;
+
+use clustaloparams::{ClustaloOutputFormat, ClustaloParams};
+use multiplealignment::MultipleAlignment;
+use percentidentitymatrix::PercentIdentityMatrix;
+use phylotree::PhyloTree;
+
 /// The `Clustalo` struct is used to specify the parameters for the `Clustalo` service.
-pub struct Clustalo {
-    pub(crate) client: EbioticClient,
+pub struct Clustalo<C = EbioticClient> {
+    pub(crate) client: C,
     endpoint: String,
     email: String,
+    params: ClustaloParams,
+    poll_policy: PollPolicy,
+    observer: Option<Arc<dyn JobObserver>>,
 }
 
 /// The `ClustaloResult` struct is used to specify the result of the `Clustalo` service.
 #[derive(Debug, Clone)]
 pub struct ClustaloResult {
-    aln_clustal_num: String,
-    pim: HashMap<String, Vec<f64>>,
+    alignment: String,
+    format: ClustaloOutputFormat,
+    pim: PercentIdentityMatrix,
     phylotree: String,
 }
 
@@ -28,16 +46,22 @@ impl Default for Clustalo {
             client: EbioticClient::default(),
             endpoint: format!("{}{}", EBI_TOOLS_ENDPOINT, "clustalo/"),
             email: "".to_string(),
+            params: ClustaloParams::default(),
+            poll_policy: PollPolicy::job_dispatcher(),
+            observer: None,
         }
     }
 }
 
-impl Clustalo {
-    pub fn new(client: EbioticClient, endpoint: String, email: String) -> Clustalo {
+impl<C> Clustalo<C> {
+    pub fn new(client: C, endpoint: String, email: String) -> Clustalo<C> {
         Clustalo {
             client,
             endpoint,
             email,
+            params: ClustaloParams::default(),
+            poll_policy: PollPolicy::job_dispatcher(),
+            observer: None,
         }
     }
 
@@ -56,103 +80,298 @@ impl Clustalo {
     pub fn email(&self) -> &String {
         &self.email
     }
+
+    /// Set the optional alignment parameters, which are checked when the job is submitted.
+    pub fn set_params(&mut self, params: ClustaloParams) {
+        self.params = params;
+    }
+
+    pub fn params(&self) -> &ClustaloParams {
+        &self.params
+    }
+
+    /// Set the policy used to poll the status of submitted jobs.
+    pub fn set_poll_policy(&mut self, poll_policy: PollPolicy) {
+        self.poll_policy = poll_policy;
+    }
+
+    /// Set an observer to receive the lifecycle events of submitted jobs.
+    pub fn set_observer<O: JobObserver + 'static>(&mut self, observer: O) {
+        self.observer = Some(Arc::new(observer));
+    }
+
+    pub fn set_client(&mut self, client: C) {
+        self.client = client;
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
 }
 
 impl ClustaloResult {
+    /// The alignment, in the output format set in the `ClustaloParams`.
+    pub fn alignment(&self) -> &String {
+        &self.alignment
+    }
+
+    pub fn format(&self) -> &ClustaloOutputFormat {
+        &self.format
+    }
+
+    /// Parse the alignment into a `MultipleAlignment`. Only the Clustal, FASTA and Stockholm formats can be parsed.
+    pub fn multiple_alignment(&self) -> EbioticResult<MultipleAlignment> {
+        MultipleAlignment::parse(&self.alignment, self.format)
+    }
+
+    #[deprecated(
+        since = "0.0.27",
+        note = "use `alignment`, which holds the alignment in the requested output format"
+    )]
     pub fn aln_clustal_num(&self) -> &String {
-        &self.aln_clustal_num
+        &self.alignment
     }
 
-    pub fn pim(&self) -> &HashMap<String, Vec<f64>> {
+    pub fn pim(&self) -> &PercentIdentityMatrix {
         &self.pim
     }
 
     pub fn phylotree(&self) -> &String {
         &self.phylotree
     }
+
+    /// Parse the Newick tree into a `PhyloTree`.
+    pub fn parsed_tree(&self) -> EbioticResult<PhyloTree> {
+        PhyloTree::from_newick(&self.phylotree)
+    }
 }
 
-impl Service for Clustalo {
+impl<C: EbioticHttpClient> Service for Clustalo<C> {
     type ResultType = ClustaloResult;
     type InputType = Vec<Record>;
 
     /// Run the `Clustalo` service with the given input.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "service_run",
+            skip_all,
+            fields(
+                service = "clustalo",
+                endpoint = %self.endpoint,
+            )
+        )
+    )]
     async fn run(&self, input: Self::InputType) -> Result<Self::ResultType, EbioticError> {
-        let run_endpoint = format!("{}{}", &self.endpoint, "run/");
-        let sequences = self.pretty_format_records(input);
-
-        log::info!("Running Clustal Omega alignment");
+        self.run_with_cancel(input, &CancellationToken::new()).await
+    }
+}
 
-        let response = self
-            .client
-            .post_form(
-                &run_endpoint,
-                &[
-                    ("email", &self.email.as_str()),
-                    ("sequence", &sequences.as_str()),
-                ],
-            )
-            .await?;
+impl<C: EbioticHttpClient> JobService for Clustalo<C> {
+    fn service_name(&self) -> &'static str {
+        "clustalo"
+    }
 
-        let poll_endpoint = format!("{}{}{}", &self.endpoint, &"status/", &response);
+    fn poll_policy(&self) -> &PollPolicy {
+        &self.poll_policy
+    }
 
-        log::info!("Job ID: {}", &response);
+    fn notify(&self, event: &JobEvent) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(event);
+        }
+    }
 
-        // Polling to wait for the result, however result is not directly returned
-        let _ = self.client.poll(&poll_endpoint, None, &self).await?;
+    /// Submit the sequences for alignment, returning a handle holding the Job Dispatcher job ID.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "submit",
+            skip_all,
+            fields(
+                service = "clustalo",
+                endpoint = %self.endpoint,
+                job_id = tracing::field::Empty,
+            )
+        )
+    )]
+    async fn submit(&self, input: Self::InputType) -> EbioticResult<JobHandle> {
+        self.params.validate()?;
 
-        log::info!("Fetching results for Job: {}", &response);
+        let run_endpoint = format!("{}{}", &self.endpoint, "run/");
+        let sequences = self.pretty_format_records(input);
 
-        // Assuming the polling does not error out, the earlier response number
-        // can be used to fetch the results
-        let acn = self
-            .client
-            .get(&format!(
-                "{}{}{}{}",
-                &self.endpoint, "result/", &response, "/aln-clustal_num"
-            ))
-            .await?;
+        log::info!("Running Clustal Omega alignment");
 
-        let pim = self
-            .client
-            .get(&format!(
-                "{}{}{}{}",
-                &self.endpoint, "result/", &response, "/pim"
-            ))
-            .await?;
+        let form = self.run_form(sequences);
+        let form: Vec<(&str, &str)> = form.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let response = self.client.post_form(&run_endpoint, &form).await;
+        let job_id = decode_response(self, response)?.trim().to_string();
+
+        log::info!("Job ID: {}", &job_id);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("job_id", job_id.as_str());
+        self.notify(&JobEvent::Submitted {
+            job_id: job_id.clone(),
+        });
+
+        let mut handle = JobHandle::new(
+            self.service_name().to_string(),
+            job_id,
+            self.endpoint.clone(),
+            None,
+        );
+        handle.set_output_format(Some(self.params.output_format().to_string()));
+        Ok(handle)
+    }
 
-        let phylotree = self
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "status",
+            skip_all,
+            fields(
+                service = "clustalo",
+                job_id = %handle.job_id(),
+            )
+        )
+    )]
+    async fn status(&self, handle: &JobHandle) -> EbioticResult<PollStatus> {
+        let status = self
             .client
             .get(&format!(
-                "{}{}{}{}",
-                &self.endpoint, "result/", &response, "/phylotree"
+                "{}{}{}",
+                handle.endpoint(),
+                "status/",
+                handle.job_id()
             ))
-            .await?;
+            .await;
+        let status = decode_response(self, status)?;
+        Ok((&self).poll_status(&status))
+    }
 
-        let results = ClustaloResult {
-            aln_clustal_num: acn,
-            pim: self.parse_pim_result(&pim)?,
-            phylotree,
-        };
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "fetch_result",
+            skip_all,
+            fields(
+                service = "clustalo",
+                job_id = %handle.job_id(),
+            )
+        )
+    )]
+    async fn fetch_result(&self, handle: &JobHandle) -> EbioticResult<Self::ResultType> {
+        let result = async {
+            log::info!("Fetching results for Job: {}", handle.job_id());
+
+            let result_endpoint = format!("{}{}{}", handle.endpoint(), "result/", handle.job_id());
+
+            // The format the job was submitted with, which the current parameters may no longer match
+            let format = match handle.output_format() {
+                Some(format) => format.parse()?,
+                None => self.params.output_format(),
+            };
+            let alignment = self
+                .fetch_artifact(handle, &result_endpoint, &format.result_type())
+                .await?;
+            let pim = self.fetch_artifact(handle, &result_endpoint, "pim").await?;
+            let phylotree = self
+                .fetch_artifact(handle, &result_endpoint, "phylotree")
+                .await?;
+
+            Ok(ClustaloResult {
+                alignment,
+                format,
+                pim: PercentIdentityMatrix::from_clustal(&pim)?,
+                phylotree,
+            })
+        }
+        .await;
+        report_fetch(self, handle, result)
+    }
 
-        Ok(results)
+    /// The Job Dispatcher REST API cannot cancel jobs, they expire on the server instead.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "cancel",
+            skip_all,
+            fields(
+                service = "clustalo",
+                job_id = %handle.job_id(),
+            )
+        )
+    )]
+    async fn cancel(&self, handle: &JobHandle) -> EbioticResult<()> {
+        log::warn!(
+            "Unable to cancel job {}, the Job Dispatcher does not support cancellation",
+            handle.job_id()
+        );
+        Err(EbioticError::CancelNotSupported(
+            "The EBI Job Dispatcher".to_string(),
+        ))
     }
 }
 
-impl PollableService for &Clustalo {
+impl<C> PollableService for &Clustalo<C> {
     fn poll_status(&self, response: &str) -> PollStatus {
-        match response {
+        match response.trim() {
             "FINISHED" => PollStatus::Finished,
-            "RUNNING" => PollStatus::Running(3),
-            "QUEUED" => PollStatus::Running(3),
-            _ => PollStatus::Error(EbioticError::ServiceError(
-                "Something went wrong with the job.".to_string(),
+            "RUNNING" | "QUEUED" => PollStatus::Running,
+            "NOT_FOUND" => PollStatus::Error(EbioticError::JobDispatcherError(
+                "The job could not be found, it may have expired.".to_string(),
             )),
+            status => PollStatus::Error(EbioticError::JobFailed(format!(
+                "Clustal Omega job finished with status {}",
+                status
+            ))),
         }
     }
 }
 
-impl Clustalo {
+impl<C> ErrorDecoder for Clustalo<C> {
+    /// Recognise the Job Dispatcher error XML, e.g.
+    /// `<error><description>Invalid parameters: ...</description></error>`.
+    fn decode_error(&self, body: &str) -> Option<EbioticError> {
+        if !body.trim_start().starts_with("<?xml") && !body.trim_start().starts_with("<error") {
+            return None;
+        }
+        let start = body.find("<description>")? + "<description>".len();
+        let end = body[start..].find("</description>")? + start;
+        Some(EbioticError::JobDispatcherError(
+            body[start..end].trim().to_string(),
+        ))
+    }
+}
+
+impl<C: EbioticHttpClient> Clustalo<C> {
+    async fn fetch_artifact(
+        &self,
+        handle: &JobHandle,
+        result_endpoint: &str,
+        artifact: &str,
+    ) -> EbioticResult<String> {
+        self.notify(&JobEvent::Fetching {
+            job_id: handle.job_id().clone(),
+            artifact: artifact.to_string(),
+        });
+        let response = self
+            .client
+            .get(&format!("{}/{}", result_endpoint, artifact))
+            .await;
+        decode_response(self, response)
+    }
+}
+
+impl<C> Clustalo<C> {
+    fn run_form(&self, sequences: String) -> Vec<(&'static str, String)> {
+        let mut form = vec![("email", self.email.clone())];
+        form.extend(self.params.form_fields());
+        form.push(("sequence", sequences));
+        form
+    }
+
     fn pretty_format_records(&self, sequences: Vec<Record>) -> String {
         let mut records = String::new();
         for record in &sequences {
@@ -160,37 +379,6 @@ impl Clustalo {
         }
         records.trim_end_matches('\n').to_string()
     }
-
-    fn parse_pim_result(
-        &self,
-        raw_results: &str,
-    ) -> Result<HashMap<String, Vec<f64>>, EbioticError> {
-        let mut pim = HashMap::new();
-        for line in raw_results.lines() {
-            if line.trim().starts_with('#') || line.trim().is_empty() {
-                continue;
-            }
-            let mut row = Vec::new();
-            let split_line: Vec<&str> = line.split_whitespace().collect();
-            let sequence_name = split_line[1].to_string();
-            for value in &split_line[2..] {
-                row.push(value.parse::<f64>()?);
-            }
-            if row.is_empty() {
-                return Err(EbioticError::ServiceError(format!(
-                    "No valid percentages found for sequence: {}",
-                    sequence_name
-                )));
-            }
-            pim.insert(sequence_name, row);
-        }
-        if pim.is_empty() {
-            return Err(EbioticError::ServiceError(
-                "No valid lines found in Percent Identity Matrix (PIM).".to_string(),
-            ));
-        }
-        Ok(pim)
-    }
 }
 
 #[cfg(test)]
@@ -223,6 +411,22 @@ mod tests {
         assert_eq!(clustalo.email(), &email);
     }
 
+    #[test]
+    fn run_form_only_sends_set_parameters() {
+        let mut clustalo = Clustalo::default();
+        clustalo.set_email("test@example.com".to_string());
+        assert_eq!(clustalo.run_form(">seq1\nACGT".to_string()).len(), 2);
+
+        let mut params = ClustaloParams::new();
+        params.set_outfmt(Some(ClustaloOutputFormat::Stockholm));
+        clustalo.set_params(params);
+
+        let form = clustalo.run_form(">seq1\nACGT".to_string());
+
+        assert_eq!(form[1], ("outfmt", "stockholm".to_string()));
+        assert_eq!(form.last().unwrap().0, "sequence");
+    }
+
     #[test]
     fn pretty_format_records_formats_correctly() {
         let seq1 = Record::with_attrs(
@@ -280,33 +484,39 @@ mod tests {
     }
 
     #[test]
-    fn parse_pim_result_parses_correctly() {
+    fn decode_error_recognises_job_dispatcher_xml() {
         let clustalo = Clustalo::default();
-        let pim_string = "\
-        1: Sequence1   100.00   36.73   40.91   40.91   40.00
-        2: Sequence2    36.73  100.00   44.44   31.71   33.33
-        3: Sequence3    40.91   44.44  100.00   77.78   83.78
-        4: Sequence4    40.91   31.71   77.78  100.00   96.00
-        5: Sequence5    40.00   33.33   83.78   96.00  100.00";
-
-        let pim = clustalo.parse_pim_result(pim_string).unwrap();
-
-        assert_eq!(pim.len(), 5);
-        assert_eq!(pim["Sequence1"], vec![100.00, 36.73, 40.91, 40.91, 40.00]);
-        assert_eq!(pim["Sequence2"], vec![36.73, 100.00, 44.44, 31.71, 33.33]);
-        assert_eq!(pim["Sequence3"], vec![40.91, 44.44, 100.00, 77.78, 83.78]);
-        assert_eq!(pim["Sequence4"], vec![40.91, 31.71, 77.78, 100.00, 96.00]);
-        assert_eq!(pim["Sequence5"], vec![40.00, 33.33, 83.78, 96.00, 100.00]);
+        let xml = "<?xml version='1.0' encoding='UTF-8'?>\n<error>\n <description>Invalid parameters: \nSequence -> Sequence is required</description>\n</error>";
+
+        match clustalo.decode_error(xml) {
+            Some(EbioticError::JobDispatcherError(message)) => {
+                assert_eq!(
+                    message,
+                    "Invalid parameters: \nSequence -> Sequence is required"
+                )
+            }
+            other => panic!("Expected JobDispatcherError, got {:?}", other),
+        }
+        assert!(clustalo
+            .decode_error("clustalo-R20240101-123456-0123-4567-p1m")
+            .is_none());
     }
 
     #[test]
-    fn parse_pim_result_handles_invalid_input() {
+    fn poll_status_recognises_failures() {
         let clustalo = Clustalo::default();
-        let pim_string = "invalid input";
-
-        let pim = clustalo.parse_pim_result(pim_string);
-
-        assert!(pim.is_err());
+        assert!(matches!(
+            (&clustalo).poll_status("FINISHED\n"),
+            PollStatus::Finished
+        ));
+        assert!(matches!(
+            (&clustalo).poll_status("FAILURE"),
+            PollStatus::Error(EbioticError::JobFailed(_))
+        ));
+        assert!(matches!(
+            (&clustalo).poll_status("NOT_FOUND"),
+            PollStatus::Error(EbioticError::JobDispatcherError(_))
+        ));
     }
 
     #[test]
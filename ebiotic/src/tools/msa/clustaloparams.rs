@@ -0,0 +1,379 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::core::EbioticResult;
+use crate::errors::EbioticError;
+
+/// The `ClustaloSequenceType` enum is used to specify the alphabet of the sequences aligned by the `Clustalo` service.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ClustaloSequenceType {
+    Protein,
+    Dna,
+    Rna,
+}
+
+/// The `ClustaloOutputFormat` enum is used to specify the format of the alignment returned by the `Clustalo` service.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ClustaloOutputFormat {
+    Clustal,
+    /// Clustal with the residue numbers at the end of each line, the server default.
+    ClustalNum,
+    Fasta,
+    Msf,
+    Nexus,
+    Phylip,
+    Selex,
+    Stockholm,
+    Vienna,
+}
+
+/// The `ClustaloOrder` enum is used to specify the order of the sequences in the alignment.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ClustaloOrder {
+    /// Order the sequences as they were aligned, the server default.
+    Aligned,
+    /// Keep the order of the input sequences.
+    Input,
+}
+
+/// The `ClustaloParams` struct is used to specify the optional parameters of the EBI Clustal Omega service.
+///
+/// Parameters left as `None` are not sent, so the server defaults are used.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClustaloParams {
+    stype: Option<ClustaloSequenceType>,
+    guidetreeout: Option<bool>,
+    dismatout: Option<bool>,
+    dealign: Option<bool>,
+    mbed: Option<bool>,
+    mbediteration: Option<bool>,
+    iterations: Option<u8>,
+    gtiterations: Option<i8>,
+    hmmiterations: Option<i8>,
+    outfmt: Option<ClustaloOutputFormat>,
+    order: Option<ClustaloOrder>,
+}
+
+impl Display for ClustaloSequenceType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClustaloSequenceType::Protein => write!(f, "protein"),
+            ClustaloSequenceType::Dna => write!(f, "dna"),
+            ClustaloSequenceType::Rna => write!(f, "rna"),
+        }
+    }
+}
+
+impl ClustaloOutputFormat {
+    /// The name of the result type holding the alignment in this format, e.g. `aln-clustal_num`.
+    pub fn result_type(&self) -> String {
+        format!("aln-{}", self)
+    }
+}
+
+impl Display for ClustaloOutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClustaloOutputFormat::Clustal => write!(f, "clustal"),
+            ClustaloOutputFormat::ClustalNum => write!(f, "clustal_num"),
+            ClustaloOutputFormat::Fasta => write!(f, "fa"),
+            ClustaloOutputFormat::Msf => write!(f, "msf"),
+            ClustaloOutputFormat::Nexus => write!(f, "nexus"),
+            ClustaloOutputFormat::Phylip => write!(f, "phylip"),
+            ClustaloOutputFormat::Selex => write!(f, "selex"),
+            ClustaloOutputFormat::Stockholm => write!(f, "stockholm"),
+            ClustaloOutputFormat::Vienna => write!(f, "vienna"),
+        }
+    }
+}
+
+impl FromStr for ClustaloOutputFormat {
+    type Err = EbioticError;
+
+    /// Parse the name the service uses for the format, e.g. `clustal_num` or `fa`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clustal" => Ok(ClustaloOutputFormat::Clustal),
+            "clustal_num" => Ok(ClustaloOutputFormat::ClustalNum),
+            "fa" => Ok(ClustaloOutputFormat::Fasta),
+            "msf" => Ok(ClustaloOutputFormat::Msf),
+            "nexus" => Ok(ClustaloOutputFormat::Nexus),
+            "phylip" => Ok(ClustaloOutputFormat::Phylip),
+            "selex" => Ok(ClustaloOutputFormat::Selex),
+            "stockholm" => Ok(ClustaloOutputFormat::Stockholm),
+            "vienna" => Ok(ClustaloOutputFormat::Vienna),
+            _ => Err(EbioticError::InvalidClustaloParameters(format!(
+                "unknown output format {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl Display for ClustaloOrder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClustaloOrder::Aligned => write!(f, "aligned"),
+            ClustaloOrder::Input => write!(f, "input"),
+        }
+    }
+}
+
+impl ClustaloParams {
+    pub fn new() -> ClustaloParams {
+        ClustaloParams::default()
+    }
+
+    /// Set the alphabet of the sequences.
+    pub fn set_stype(&mut self, stype: Option<ClustaloSequenceType>) {
+        self.stype = stype;
+    }
+
+    /// Set whether the guide tree is returned as a result.
+    pub fn set_guidetreeout(&mut self, guidetreeout: Option<bool>) {
+        self.guidetreeout = guidetreeout;
+    }
+
+    /// Set whether the distance matrix is returned as a result.
+    pub fn set_dismatout(&mut self, dismatout: Option<bool>) {
+        self.dismatout = dismatout;
+    }
+
+    /// Set whether gaps in the input sequences are removed before aligning them.
+    pub fn set_dealign(&mut self, dealign: Option<bool>) {
+        self.dealign = dealign;
+    }
+
+    /// Set whether the guide tree is built with mBed-like clustering.
+    pub fn set_mbed(&mut self, mbed: Option<bool>) {
+        self.mbed = mbed;
+    }
+
+    /// Set whether the guide trees of the iterations are built with mBed-like clustering.
+    pub fn set_mbediteration(&mut self, mbediteration: Option<bool>) {
+        self.mbediteration = mbediteration;
+    }
+
+    /// Set the number of combined guide tree and HMM iterations, from `0` to `5`.
+    pub fn set_iterations(&mut self, iterations: Option<u8>) {
+        self.iterations = iterations;
+    }
+
+    /// Set the maximum number of guide tree iterations, from `0` to `5`, or `-1` for no limit.
+    pub fn set_gtiterations(&mut self, gtiterations: Option<i8>) {
+        self.gtiterations = gtiterations;
+    }
+
+    /// Set the maximum number of HMM iterations, from `0` to `5`, or `-1` for no limit.
+    pub fn set_hmmiterations(&mut self, hmmiterations: Option<i8>) {
+        self.hmmiterations = hmmiterations;
+    }
+
+    /// Set the format of the alignment.
+    pub fn set_outfmt(&mut self, outfmt: Option<ClustaloOutputFormat>) {
+        self.outfmt = outfmt;
+    }
+
+    /// Set the order of the sequences in the alignment.
+    pub fn set_order(&mut self, order: Option<ClustaloOrder>) {
+        self.order = order;
+    }
+
+    pub fn stype(&self) -> &Option<ClustaloSequenceType> {
+        &self.stype
+    }
+
+    pub fn guidetreeout(&self) -> &Option<bool> {
+        &self.guidetreeout
+    }
+
+    pub fn dismatout(&self) -> &Option<bool> {
+        &self.dismatout
+    }
+
+    pub fn dealign(&self) -> &Option<bool> {
+        &self.dealign
+    }
+
+    pub fn mbed(&self) -> &Option<bool> {
+        &self.mbed
+    }
+
+    pub fn mbediteration(&self) -> &Option<bool> {
+        &self.mbediteration
+    }
+
+    pub fn iterations(&self) -> &Option<u8> {
+        &self.iterations
+    }
+
+    pub fn gtiterations(&self) -> &Option<i8> {
+        &self.gtiterations
+    }
+
+    pub fn hmmiterations(&self) -> &Option<i8> {
+        &self.hmmiterations
+    }
+
+    pub fn outfmt(&self) -> &Option<ClustaloOutputFormat> {
+        &self.outfmt
+    }
+
+    pub fn order(&self) -> &Option<ClustaloOrder> {
+        &self.order
+    }
+
+    /// The format the alignment is returned in, i.e. `clustal_num` unless another format is set.
+    pub fn output_format(&self) -> ClustaloOutputFormat {
+        self.outfmt.unwrap_or(ClustaloOutputFormat::ClustalNum)
+    }
+
+    /// Check the parameters are within the ranges accepted by the service, so that invalid jobs are rejected before
+    /// they are submitted.
+    pub fn validate(&self) -> EbioticResult<()> {
+        if let Some(iterations) = self.iterations {
+            if iterations > 5 {
+                return invalid(format!(
+                    "iterations must be between 0 and 5, not {}",
+                    iterations
+                ));
+            }
+        }
+        for (name, value) in [
+            ("gtiterations", self.gtiterations),
+            ("hmmiterations", self.hmmiterations),
+        ] {
+            if let Some(value) = value {
+                if !(-1..=5).contains(&value) {
+                    return invalid(format!("{} must be between -1 and 5, not {}", name, value));
+                }
+            }
+        }
+        if self.mbediteration == Some(true) && self.mbed == Some(false) {
+            return invalid("mbediteration cannot be used when mbed is disabled".to_string());
+        }
+        Ok(())
+    }
+
+    /// The form fields of the parameters which are set.
+    pub(crate) fn form_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let Some(stype) = self.stype {
+            fields.push(("stype", stype.to_string()));
+        }
+        for (name, value) in [
+            ("guidetreeout", self.guidetreeout),
+            ("dismatout", self.dismatout),
+            ("dealign", self.dealign),
+            ("mbed", self.mbed),
+            ("mbediteration", self.mbediteration),
+        ] {
+            if let Some(value) = value {
+                fields.push((name, value.to_string()));
+            }
+        }
+        if let Some(iterations) = self.iterations {
+            fields.push(("iterations", iterations.to_string()));
+        }
+        if let Some(gtiterations) = self.gtiterations {
+            fields.push(("gtiterations", gtiterations.to_string()));
+        }
+        if let Some(hmmiterations) = self.hmmiterations {
+            fields.push(("hmmiterations", hmmiterations.to_string()));
+        }
+        if let Some(outfmt) = self.outfmt {
+            fields.push(("outfmt", outfmt.to_string()));
+        }
+        if let Some(order) = self.order {
+            fields.push(("order", order.to_string()));
+        }
+        fields
+    }
+}
+
+fn invalid(message: String) -> EbioticResult<()> {
+    Err(EbioticError::InvalidClustaloParameters(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_formats_round_trip_through_their_names() {
+        for format in [
+            ClustaloOutputFormat::Clustal,
+            ClustaloOutputFormat::ClustalNum,
+            ClustaloOutputFormat::Fasta,
+            ClustaloOutputFormat::Msf,
+            ClustaloOutputFormat::Nexus,
+            ClustaloOutputFormat::Phylip,
+            ClustaloOutputFormat::Selex,
+            ClustaloOutputFormat::Stockholm,
+            ClustaloOutputFormat::Vienna,
+        ] {
+            assert_eq!(
+                format.to_string().parse::<ClustaloOutputFormat>().unwrap(),
+                format
+            );
+        }
+        assert!("fasta".parse::<ClustaloOutputFormat>().is_err());
+    }
+
+    #[test]
+    fn only_sends_set_parameters() {
+        assert!(ClustaloParams::new().form_fields().is_empty());
+
+        let mut params = ClustaloParams::new();
+        params.set_stype(Some(ClustaloSequenceType::Dna));
+        params.set_dealign(Some(true));
+        params.set_hmmiterations(Some(-1));
+        params.set_outfmt(Some(ClustaloOutputFormat::Fasta));
+        params.set_order(Some(ClustaloOrder::Input));
+
+        assert_eq!(
+            params.form_fields(),
+            vec![
+                ("stype", "dna".to_string()),
+                ("dealign", "true".to_string()),
+                ("hmmiterations", "-1".to_string()),
+                ("outfmt", "fa".to_string()),
+                ("order", "input".to_string()),
+            ]
+        );
+        assert_eq!(params.output_format().result_type(), "aln-fa");
+        assert_eq!(
+            ClustaloParams::new().output_format().result_type(),
+            "aln-clustal_num"
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_iterations() {
+        let mut params = ClustaloParams::new();
+        params.set_iterations(Some(5));
+        params.set_gtiterations(Some(-1));
+        assert!(params.validate().is_ok());
+
+        params.set_iterations(Some(6));
+        assert!(matches!(
+            params.validate(),
+            Err(EbioticError::InvalidClustaloParameters(_))
+        ));
+
+        let mut params = ClustaloParams::new();
+        params.set_hmmiterations(Some(-2));
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_mbed_iterations_without_mbed() {
+        let mut params = ClustaloParams::new();
+        params.set_mbed(Some(false));
+        params.set_mbediteration(Some(true));
+        assert!(params.validate().is_err());
+
+        params.set_mbediteration(Some(false));
+        assert!(params.validate().is_ok());
+    }
+}
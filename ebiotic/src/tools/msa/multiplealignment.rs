@@ -0,0 +1,576 @@
+use bio::io::fasta::Record;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+use super::clustaloparams::ClustaloOutputFormat;
+use crate::core::{parse_fa_from_bufread, EbioticResult};
+use crate::errors::EbioticError;
+
+/// The number of alignment columns written per block in the Clustal format.
+const CLUSTAL_BLOCK_WIDTH: usize = 60;
+
+/// The `MultipleAlignment` struct is used to specify a multiple sequence alignment, as gapped `Record`s of equal length
+/// in alignment order.
+///
+/// The `conservation` line holds a Clustal symbol per column (`*` for identical columns, `:` and `.` for strongly and
+/// weakly similar ones, and a space otherwise), and is only known when the alignment was read from the Clustal format.
+/// Both `-` and `.` are treated as gaps.
+#[derive(Debug, Clone)]
+pub struct MultipleAlignment {
+    records: Vec<Record>,
+    conservation: Option<String>,
+}
+
+impl MultipleAlignment {
+    /// Create an alignment from gapped records, which must all have the same length.
+    pub fn new(records: Vec<Record>) -> EbioticResult<MultipleAlignment> {
+        if let Some(first) = records.first() {
+            if let Some(record) = records.iter().find(|r| r.seq().len() != first.seq().len()) {
+                return invalid(format!(
+                    "{} has {} columns, but {} has {}",
+                    record.id(),
+                    record.seq().len(),
+                    first.id(),
+                    first.seq().len()
+                ));
+            }
+        }
+        Ok(MultipleAlignment {
+            records,
+            conservation: None,
+        })
+    }
+
+    /// Set the Clustal conservation symbols, one ASCII symbol per column.
+    pub fn set_conservation(&mut self, conservation: Option<String>) -> EbioticResult<()> {
+        if let Some(line) = &conservation {
+            if !line.is_ascii() {
+                return invalid(format!(
+                    "The conservation line holds symbols which are not ASCII: {}",
+                    line
+                ));
+            }
+            if line.len() != self.width() {
+                return invalid(format!(
+                    "The conservation line has {} columns, but the alignment has {}",
+                    line.len(),
+                    self.width()
+                ));
+            }
+        }
+        self.conservation = conservation;
+        Ok(())
+    }
+
+    pub fn records(&self) -> &Vec<Record> {
+        &self.records
+    }
+
+    pub fn conservation(&self) -> &Option<String> {
+        &self.conservation
+    }
+
+    /// The number of sequences in the alignment.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// The number of columns in the alignment.
+    pub fn width(&self) -> usize {
+        self.records.first().map_or(0, |record| record.seq().len())
+    }
+
+    /// The record with the given id.
+    pub fn get(&self, id: &str) -> Option<&Record> {
+        self.records.iter().find(|record| record.id() == id)
+    }
+
+    /// The residues of a column, in alignment order.
+    pub fn column(&self, column: usize) -> Option<Vec<u8>> {
+        if column >= self.width() {
+            return None;
+        }
+        Some(self.records.iter().map(|r| r.seq()[column]).collect())
+    }
+
+    /// The fraction of the sequences with a gap in a column.
+    pub fn gap_fraction(&self, column: usize) -> Option<f64> {
+        let residues = self.column(column)?;
+        let gaps = residues.iter().filter(|residue| is_gap(**residue)).count();
+        Some(gaps as f64 / residues.len() as f64)
+    }
+
+    /// Keep the columns in `columns`, e.g. `10..20` for the 11th to the 20th column.
+    pub fn slice(&self, columns: Range<usize>) -> EbioticResult<MultipleAlignment> {
+        if columns.start > columns.end || columns.end > self.width() {
+            return invalid(format!(
+                "Columns {:?} are outside of the {} alignment columns",
+                columns,
+                self.width()
+            ));
+        }
+        Ok(self.select_columns(&columns.collect::<Vec<usize>>()))
+    }
+
+    /// Keep the columns with a gap in at most `max_gap_fraction` of the sequences, e.g. `0.5` to drop the columns
+    /// which are mostly gaps.
+    pub fn filter_by_gap_fraction(&self, max_gap_fraction: f64) -> MultipleAlignment {
+        let columns: Vec<usize> = (0..self.width())
+            .filter(|column| {
+                self.gap_fraction(*column)
+                    .is_some_and(|fraction| fraction <= max_gap_fraction)
+            })
+            .collect();
+        self.select_columns(&columns)
+    }
+
+    /// The most common residue of each column, ignoring gaps and case, with ties going to the residue seen first.
+    /// Columns holding only gaps are given a `-`.
+    pub fn consensus(&self) -> String {
+        (0..self.width())
+            .map(|column| {
+                let mut counts: Vec<(u8, usize)> = Vec::new();
+                for residue in self.records.iter().map(|r| r.seq()[column]) {
+                    if is_gap(residue) {
+                        continue;
+                    }
+                    let residue = residue.to_ascii_uppercase();
+                    match counts.iter_mut().find(|(r, _)| *r == residue) {
+                        Some((_, count)) => *count += 1,
+                        None => counts.push((residue, 1)),
+                    }
+                }
+                let mut best: Option<(u8, usize)> = None;
+                for (residue, count) in counts {
+                    if best.is_none_or(|(_, best_count)| count > best_count) {
+                        best = Some((residue, count));
+                    }
+                }
+                best.map_or('-', |(residue, _)| residue as char)
+            })
+            .collect()
+    }
+
+    /// Parse an alignment in one of the formats returned by the `Clustalo` service.
+    pub fn parse(text: &str, format: ClustaloOutputFormat) -> EbioticResult<MultipleAlignment> {
+        match format {
+            ClustaloOutputFormat::Clustal | ClustaloOutputFormat::ClustalNum => {
+                MultipleAlignment::from_clustal(text)
+            }
+            ClustaloOutputFormat::Fasta => MultipleAlignment::from_fasta(text),
+            ClustaloOutputFormat::Stockholm => MultipleAlignment::from_stockholm(text),
+            format => Err(EbioticError::UnsupportedAlignmentFormat(format.to_string())),
+        }
+    }
+
+    /// Parse an alignment in the Clustal format, with or without residue numbers.
+    pub fn from_clustal(text: &str) -> EbioticResult<MultipleAlignment> {
+        let mut lines = text.lines();
+        match lines.next() {
+            Some(header) if header.starts_with("CLUSTAL") => {}
+            _ => return invalid("The Clustal alignment has no CLUSTAL header".to_string()),
+        }
+
+        let mut rows = Rows::default();
+        let mut conservation = String::new();
+        // The start and width of the sequences in the current block, used to read the conservation line
+        let mut block: Option<(usize, usize)> = None;
+        for line in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with(char::is_whitespace) {
+                if let Some((start, width)) = block {
+                    let symbols = line.get(start..).unwrap_or("");
+                    conservation.extend(symbols.chars().chain(std::iter::repeat(' ')).take(width));
+                }
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let (id, segment) = match (fields.next(), fields.next()) {
+                (Some(id), Some(segment)) => (id, segment),
+                _ => return invalid(format!("Unable to read the Clustal line: {}", line)),
+            };
+            if rows.is_first(id) {
+                // A new block, so pad the conservation of a block without a conservation line
+                pad(&mut conservation, rows.width());
+            }
+            let start = id.len() + line[id.len()..].find(segment).unwrap_or(0);
+            block = Some((start, segment.len()));
+            rows.push(id, None, segment);
+        }
+        pad(&mut conservation, rows.width());
+
+        let mut alignment = rows.into_alignment()?;
+        alignment.set_conservation(Some(conservation))?;
+        Ok(alignment)
+    }
+
+    /// Parse an alignment in the aligned FASTA format.
+    pub fn from_fasta(text: &str) -> EbioticResult<MultipleAlignment> {
+        MultipleAlignment::new(parse_fa_from_bufread(text)?)
+    }
+
+    /// Parse an alignment in the Stockholm format. Descriptions are read from the `#=GS <id> DE` lines, and the other
+    /// annotations are ignored.
+    pub fn from_stockholm(text: &str) -> EbioticResult<MultipleAlignment> {
+        let mut lines = text.lines();
+        match lines.next() {
+            Some(header) if header.starts_with("# STOCKHOLM") => {}
+            _ => return invalid("The Stockholm alignment has no STOCKHOLM header".to_string()),
+        }
+
+        let mut rows = Rows::default();
+        let mut descriptions: HashMap<String, String> = HashMap::new();
+        for line in lines {
+            let line = line.trim_end();
+            if line == "//" {
+                break;
+            }
+            if let Some(annotation) = line.strip_prefix("#=GS ") {
+                // The ids are padded to a common width, so the fields may be separated by several spaces
+                let (id, rest) = next_field(annotation);
+                let (tag, description) = next_field(rest);
+                if !id.is_empty() && tag == "DE" {
+                    descriptions.insert(id.to_string(), description.to_string());
+                }
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(id), Some(segment)) => rows.push(id, None, segment),
+                _ => return invalid(format!("Unable to read the Stockholm line: {}", line)),
+            }
+        }
+        for (id, description) in descriptions {
+            rows.set_description(&id, description);
+        }
+        rows.into_alignment()
+    }
+
+    /// Write the alignment in one of the formats returned by the `Clustalo` service.
+    pub fn to_format(&self, format: ClustaloOutputFormat) -> EbioticResult<String> {
+        match format {
+            ClustaloOutputFormat::Clustal => Ok(self.write_clustal(false)),
+            ClustaloOutputFormat::ClustalNum => Ok(self.write_clustal(true)),
+            ClustaloOutputFormat::Fasta => Ok(self.to_fasta()),
+            ClustaloOutputFormat::Stockholm => Ok(self.to_stockholm()),
+            format => Err(EbioticError::UnsupportedAlignmentFormat(format.to_string())),
+        }
+    }
+
+    /// Write the alignment in the Clustal format. Without a parsed conservation line, only identical columns are
+    /// marked, with a `*`.
+    pub fn to_clustal(&self) -> String {
+        self.write_clustal(false)
+    }
+
+    /// Write the alignment in the aligned FASTA format.
+    pub fn to_fasta(&self) -> String {
+        let mut fasta = String::new();
+        for record in &self.records {
+            write!(fasta, "{}", record).unwrap();
+        }
+        fasta
+    }
+
+    /// Write the alignment in the Stockholm format, with the descriptions as `#=GS <id> DE` lines.
+    pub fn to_stockholm(&self) -> String {
+        let name_width = self.name_width();
+        let mut stockholm = String::from("# STOCKHOLM 1.0\n\n");
+        for record in &self.records {
+            if let Some(description) = record.desc() {
+                writeln!(
+                    stockholm,
+                    "#=GS {:width$} DE {}",
+                    record.id(),
+                    description,
+                    width = name_width
+                )
+                .unwrap();
+            }
+        }
+        if self.records.iter().any(|record| record.desc().is_some()) {
+            stockholm.push('\n');
+        }
+        for record in &self.records {
+            writeln!(
+                stockholm,
+                "{:width$} {}",
+                record.id(),
+                String::from_utf8_lossy(record.seq()),
+                width = name_width
+            )
+            .unwrap();
+        }
+        stockholm.push_str("//\n");
+        stockholm
+    }
+
+    fn write_clustal(&self, numbered: bool) -> String {
+        let name_width = self.name_width() + 6;
+        let conservation = self.conservation.clone().unwrap_or_else(|| {
+            (0..self.width())
+                .map(|column| {
+                    let residues = self.column(column).unwrap_or_default();
+                    let first = residues[0].to_ascii_uppercase();
+                    let identical =
+                        !is_gap(first) && residues.iter().all(|r| r.to_ascii_uppercase() == first);
+                    if identical {
+                        '*'
+                    } else {
+                        ' '
+                    }
+                })
+                .collect()
+        });
+
+        let mut clustal = String::from("CLUSTAL multiple sequence alignment\n\n");
+        let mut residues = vec![0usize; self.records.len()];
+        for start in (0..self.width()).step_by(CLUSTAL_BLOCK_WIDTH) {
+            let end = (start + CLUSTAL_BLOCK_WIDTH).min(self.width());
+            clustal.push('\n');
+            for (record, count) in self.records.iter().zip(residues.iter_mut()) {
+                let segment = &record.seq()[start..end];
+                write!(
+                    clustal,
+                    "{:width$}{}",
+                    record.id(),
+                    String::from_utf8_lossy(segment),
+                    width = name_width
+                )
+                .unwrap();
+                *count += segment.iter().filter(|residue| !is_gap(**residue)).count();
+                if numbered {
+                    write!(clustal, "\t{}", count).unwrap();
+                }
+                clustal.push('\n');
+            }
+            writeln!(
+                clustal,
+                "{:width$}{}",
+                "",
+                &conservation[start..end],
+                width = name_width
+            )
+            .unwrap();
+        }
+        clustal
+    }
+
+    fn name_width(&self) -> usize {
+        self.records.iter().map(|r| r.id().len()).max().unwrap_or(0)
+    }
+
+    fn select_columns(&self, columns: &[usize]) -> MultipleAlignment {
+        let records = self
+            .records
+            .iter()
+            .map(|record| {
+                let seq: Vec<u8> = columns.iter().map(|c| record.seq()[*c]).collect();
+                Record::with_attrs(record.id(), record.desc(), &seq)
+            })
+            .collect();
+        let conservation = self.conservation.as_ref().map(|line| {
+            let symbols = line.as_bytes();
+            columns.iter().map(|c| symbols[*c] as char).collect()
+        });
+        MultipleAlignment {
+            records,
+            conservation,
+        }
+    }
+}
+
+/// The rows of an interleaved alignment, in the order they are first seen.
+#[derive(Default)]
+struct Rows {
+    rows: Vec<(String, Option<String>, String)>,
+}
+
+impl Rows {
+    fn push(&mut self, id: &str, description: Option<String>, segment: &str) {
+        match self.rows.iter_mut().find(|(row, _, _)| row == id) {
+            Some((_, _, seq)) => seq.push_str(segment),
+            None => self
+                .rows
+                .push((id.to_string(), description, segment.to_string())),
+        }
+    }
+
+    fn set_description(&mut self, id: &str, description: String) {
+        if let Some((_, desc, _)) = self.rows.iter_mut().find(|(row, _, _)| row == id) {
+            *desc = Some(description);
+        }
+    }
+
+    fn is_first(&self, id: &str) -> bool {
+        self.rows.first().is_some_and(|(row, _, _)| row == id)
+    }
+
+    /// The number of columns read so far for the first row.
+    fn width(&self) -> usize {
+        self.rows.first().map_or(0, |(_, _, seq)| seq.len())
+    }
+
+    fn into_alignment(self) -> EbioticResult<MultipleAlignment> {
+        MultipleAlignment::new(
+            self.rows
+                .iter()
+                .map(|(id, desc, seq)| Record::with_attrs(id, desc.as_deref(), seq.as_bytes()))
+                .collect(),
+        )
+    }
+}
+
+fn is_gap(residue: u8) -> bool {
+    residue == b'-' || residue == b'.'
+}
+
+fn pad(line: &mut String, width: usize) {
+    while line.len() < width {
+        line.push(' ');
+    }
+}
+
+/// Split the first whitespace separated field from `text`, returning it along with the rest of the text.
+fn next_field(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    (&text[..end], text[end..].trim_start())
+}
+
+fn invalid<T>(message: String) -> EbioticResult<T> {
+    Err(EbioticError::InvalidAlignment(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLUSTAL: &str = "\
+CLUSTAL O(1.2.4) multiple sequence alignment
+
+
+seq1      MKV-LAAG\t7
+seq2      MKVQLSAG\t8
+seq3      MRV-L--G\t5
+          *:* *  *
+
+seq1      WY\t9
+seq2      WF\t10
+seq3      W-\t6
+          *
+";
+
+    fn alignment() -> MultipleAlignment {
+        MultipleAlignment::from_clustal(CLUSTAL).unwrap()
+    }
+
+    #[test]
+    fn parses_interleaved_clustal_blocks() {
+        let alignment = alignment();
+
+        assert_eq!(alignment.len(), 3);
+        assert_eq!(alignment.width(), 10);
+        assert_eq!(alignment.records()[0].id(), "seq1");
+        assert_eq!(alignment.get("seq3").unwrap().seq(), b"MRV-L--GW-");
+        assert_eq!(alignment.conservation().as_deref(), Some("*:* *  ** "));
+    }
+
+    #[test]
+    fn parses_fasta_and_stockholm() {
+        let fasta = MultipleAlignment::from_fasta(">seq1 first\nMK-V\n>seq2\nMKQV\n").unwrap();
+        assert_eq!(fasta.width(), 4);
+        assert_eq!(fasta.records()[0].desc(), Some("first"));
+        assert!(fasta.conservation().is_none());
+
+        let stockholm = MultipleAlignment::from_stockholm(
+            "# STOCKHOLM 1.0\n#=GF ID test\n#=GS seq1 DE first sequence\n\nseq1 MK-V\nseq2 MKQV\n#=GC SS_cons ....\n\nseq1 LL\nseq2 L.\n//\n",
+        )
+        .unwrap();
+        assert_eq!(stockholm.get("seq1").unwrap().seq(), b"MK-VLL");
+        assert_eq!(
+            stockholm.get("seq1").unwrap().desc(),
+            Some("first sequence")
+        );
+        assert_eq!(stockholm.get("seq2").unwrap().seq(), b"MKQVL.");
+
+        assert!(MultipleAlignment::from_fasta(">seq1\nMK\n>seq2\nMKV\n").is_err());
+        assert!(matches!(
+            MultipleAlignment::parse("", ClustaloOutputFormat::Msf),
+            Err(EbioticError::UnsupportedAlignmentFormat(_))
+        ));
+    }
+
+    #[test]
+    fn slices_and_filters_columns() {
+        let alignment = alignment();
+
+        let slice = alignment.slice(1..4).unwrap();
+        assert_eq!(slice.records()[1].seq(), b"KVQ");
+        assert_eq!(slice.conservation().as_deref(), Some(":* "));
+        let mut symbols = alignment.clone();
+        assert!(symbols
+            .set_conservation(Some("*:* *  *é".to_string()))
+            .is_err());
+        assert!(alignment.slice(5..11).is_err());
+
+        assert_eq!(alignment.gap_fraction(3), Some(2.0 / 3.0));
+        let filtered = alignment.filter_by_gap_fraction(0.5);
+        assert_eq!(filtered.width(), 9);
+        assert_eq!(filtered.records()[0].seq(), b"MKVLAAGWY");
+    }
+
+    #[test]
+    fn computes_the_consensus() {
+        assert_eq!(alignment().consensus(), "MKVQLAAGWY");
+
+        let gaps = MultipleAlignment::from_fasta(">a\nA-\n>b\nc-\n>c\nC-\n").unwrap();
+        assert_eq!(gaps.consensus(), "C-");
+    }
+
+    #[test]
+    fn writes_formats_which_parse_back() {
+        let alignment = alignment();
+
+        let clustal = alignment
+            .to_format(ClustaloOutputFormat::ClustalNum)
+            .unwrap();
+        assert!(clustal.contains("seq2      MKVQLSAGWF\t10\n"));
+        let parsed = MultipleAlignment::from_clustal(&clustal).unwrap();
+        assert_eq!(parsed.records()[2].seq(), alignment.records()[2].seq());
+        assert_eq!(parsed.conservation(), alignment.conservation());
+
+        let parsed = MultipleAlignment::from_stockholm(&alignment.to_stockholm()).unwrap();
+        assert_eq!(parsed.records()[1].seq(), b"MKVQLSAGWF");
+
+        let parsed = MultipleAlignment::from_fasta(&alignment.to_fasta()).unwrap();
+        assert_eq!(parsed.width(), 10);
+
+        let described =
+            MultipleAlignment::from_fasta(">a short one\nMK-V\n>longer_id the  long one\nMKQV\n")
+                .unwrap();
+        let stockholm = described.to_stockholm();
+        assert!(stockholm.contains("#=GS a         DE short one\n"));
+        let parsed = MultipleAlignment::from_stockholm(&stockholm).unwrap();
+        assert_eq!(parsed.get("a").unwrap().desc(), Some("short one"));
+        assert_eq!(
+            parsed.get("longer_id").unwrap().desc(),
+            Some("the  long one")
+        );
+
+        let unparsed = MultipleAlignment::new(alignment.records().clone()).unwrap();
+        assert!(unparsed.to_clustal().contains("\n          * * *  ** \n"));
+    }
+}
@@ -0,0 +1,307 @@
+use std::fmt::Write;
+
+use crate::core::EbioticResult;
+use crate::errors::EbioticError;
+
+/// The `PercentIdentityMatrix` struct is used to specify the pairwise percent identities of the aligned sequences, as
+/// returned by the `Clustalo` service.
+///
+/// The rows and columns follow the order of `labels`. Labels may repeat, e.g. when Clustal truncates long sequence
+/// names, in which case the lookups by label use the first sequence with that label and the others are reached by
+/// position through `values`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PercentIdentityMatrix {
+    labels: Vec<String>,
+    values: Vec<Vec<f64>>,
+}
+
+impl PercentIdentityMatrix {
+    /// Create a matrix from the labels and the rows of percentages, which must be square.
+    pub fn new(labels: Vec<String>, values: Vec<Vec<f64>>) -> EbioticResult<PercentIdentityMatrix> {
+        if values.len() != labels.len() {
+            return malformed(format!(
+                "{} rows were given for {} sequences",
+                values.len(),
+                labels.len()
+            ));
+        }
+        for (label, row) in labels.iter().zip(&values) {
+            if row.len() != labels.len() {
+                return malformed(format!(
+                    "{} has {} percentages, but there are {} sequences",
+                    label,
+                    row.len(),
+                    labels.len()
+                ));
+            }
+        }
+        Ok(PercentIdentityMatrix { labels, values })
+    }
+
+    /// Parse the Percent Identity Matrix (PIM) written by Clustal, e.g. `1: seq1  100.00  28.85`.
+    pub fn from_clustal(raw_results: &str) -> EbioticResult<PercentIdentityMatrix> {
+        let mut labels = Vec::new();
+        let mut values = Vec::new();
+        for line in raw_results.lines() {
+            if line.trim().starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let split_line: Vec<&str> = line.split_whitespace().collect();
+            let sequence_name = match split_line.get(1) {
+                Some(name) => name.to_string(),
+                None => return malformed(format!("Unable to read the PIM line: {}", line)),
+            };
+            let mut row = Vec::new();
+            for value in &split_line[2..] {
+                row.push(value.parse::<f64>()?);
+            }
+            if row.is_empty() {
+                return malformed(format!(
+                    "No valid percentages found for sequence: {}",
+                    sequence_name
+                ));
+            }
+            labels.push(sequence_name);
+            values.push(row);
+        }
+        if labels.is_empty() {
+            return malformed("No valid lines found in Percent Identity Matrix (PIM).".to_string());
+        }
+        PercentIdentityMatrix::new(labels, values)
+    }
+
+    pub fn labels(&self) -> &Vec<String> {
+        &self.labels
+    }
+
+    pub fn values(&self) -> &Vec<Vec<f64>> {
+        &self.values
+    }
+
+    /// The number of sequences in the matrix.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// The position of the first sequence with the label in the rows and columns.
+    pub fn index_of(&self, label: &str) -> Option<usize> {
+        self.labels.iter().position(|l| l == label)
+    }
+
+    /// The percent identity of sequences `a` and `b`.
+    pub fn get(&self, a: &str, b: &str) -> Option<f64> {
+        Some(self.values[self.index_of(a)?][self.index_of(b)?])
+    }
+
+    /// The percent identities of a sequence to every sequence, in the order of `labels`.
+    pub fn row(&self, label: &str) -> Option<&Vec<f64>> {
+        Some(&self.values[self.index_of(label)?])
+    }
+
+    /// The `n` other sequences most identical to `label`, most identical first.
+    pub fn nearest_neighbours(&self, label: &str, n: usize) -> Vec<(&String, f64)> {
+        let index = match self.index_of(label) {
+            Some(index) => index,
+            None => return Vec::new(),
+        };
+        let mut neighbours: Vec<(&String, f64)> = self
+            .labels
+            .iter()
+            .zip(&self.values[index])
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .map(|(_, (label, identity))| (label, *identity))
+            .collect();
+        neighbours.sort_by(|a, b| b.1.total_cmp(&a.1));
+        neighbours.truncate(n);
+        neighbours
+    }
+
+    /// The other sequence most identical to `label`.
+    pub fn nearest_neighbour(&self, label: &str) -> Option<(&String, f64)> {
+        self.nearest_neighbours(label, 1).into_iter().next()
+    }
+
+    /// Group the sequences linked by a percent identity of at least `min_identity`, directly or through other
+    /// sequences (single linkage). Clusters are ordered by their first sequence, and keep the order of `labels`.
+    pub fn cluster(&self, min_identity: f64) -> Vec<Vec<&String>> {
+        let mut cluster_of: Vec<Option<usize>> = vec![None; self.len()];
+        let mut clusters: Vec<Vec<usize>> = Vec::new();
+        for start in 0..self.len() {
+            if cluster_of[start].is_some() {
+                continue;
+            }
+            let id = clusters.len();
+            cluster_of[start] = Some(id);
+            let mut members = vec![start];
+            let mut stack = vec![start];
+            while let Some(i) = stack.pop() {
+                for (j, cluster) in cluster_of.iter_mut().enumerate() {
+                    if cluster.is_none() && self.values[i][j] >= min_identity {
+                        *cluster = Some(id);
+                        members.push(j);
+                        stack.push(j);
+                    }
+                }
+            }
+            members.sort();
+            clusters.push(members);
+        }
+        clusters
+            .into_iter()
+            .map(|members| members.into_iter().map(|i| &self.labels[i]).collect())
+            .collect()
+    }
+
+    /// The distances between the sequences, as `1 - identity / 100`, in the order of `labels`.
+    pub fn to_distance_matrix(&self) -> Vec<Vec<f64>> {
+        self.values
+            .iter()
+            .map(|row| row.iter().map(|identity| 1.0 - identity / 100.0).collect())
+            .collect()
+    }
+
+    /// Write the matrix as tab separated values, with a header row of the labels.
+    pub fn to_tsv(&self) -> String {
+        self.write_delimited('\t', |label| label.to_string())
+    }
+
+    /// Write the matrix as comma separated values, with a header row of the labels.
+    pub fn to_csv(&self) -> String {
+        self.write_delimited(',', |label| {
+            if label.contains([',', '"', '\n']) {
+                format!("\"{}\"", label.replace('"', "\"\""))
+            } else {
+                label.to_string()
+            }
+        })
+    }
+
+    fn write_delimited<F: Fn(&str) -> String>(&self, delimiter: char, escape: F) -> String {
+        let mut output = String::new();
+        for label in &self.labels {
+            write!(output, "{}{}", delimiter, escape(label)).unwrap();
+        }
+        output.push('\n');
+        for (label, row) in self.labels.iter().zip(&self.values) {
+            output.push_str(&escape(label));
+            for value in row {
+                write!(output, "{}{:.2}", delimiter, value).unwrap();
+            }
+            output.push('\n');
+        }
+        output
+    }
+}
+
+fn malformed<T>(message: String) -> EbioticResult<T> {
+    Err(EbioticError::MalformedResponse(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIM: &str = "\
+        1: Sequence1   100.00   36.73   40.91   40.91   40.00
+        2: Sequence2    36.73  100.00   44.44   31.71   33.33
+        3: Sequence3    40.91   44.44  100.00   77.78   83.78
+        4: Sequence4    40.91   31.71   77.78  100.00   96.00
+        5: Sequence5    40.00   33.33   83.78   96.00  100.00";
+
+    #[test]
+    fn parse_pim_result_parses_correctly() {
+        let pim = PercentIdentityMatrix::from_clustal(PIM).unwrap();
+
+        assert_eq!(pim.len(), 5);
+        assert_eq!(
+            pim.labels(),
+            &vec![
+                "Sequence1",
+                "Sequence2",
+                "Sequence3",
+                "Sequence4",
+                "Sequence5"
+            ]
+        );
+        assert_eq!(
+            pim.row("Sequence1").unwrap(),
+            &vec![100.00, 36.73, 40.91, 40.91, 40.00]
+        );
+        assert_eq!(
+            pim.row("Sequence3").unwrap(),
+            &vec![40.91, 44.44, 100.00, 77.78, 83.78]
+        );
+        assert_eq!(pim.get("Sequence2", "Sequence4"), Some(31.71));
+        assert_eq!(pim.get("Sequence2", "Sequence6"), None);
+    }
+
+    #[test]
+    fn parse_pim_result_handles_invalid_input() {
+        assert!(PercentIdentityMatrix::from_clustal("invalid input").is_err());
+        assert!(PercentIdentityMatrix::from_clustal("1: seq1 100.00 50.00").is_err());
+    }
+
+    #[test]
+    fn keeps_rows_with_duplicate_labels() {
+        let pim = PercentIdentityMatrix::from_clustal("1: seq1 100.00 50.00\n2: seq1 50.00 100.00")
+            .unwrap();
+
+        assert_eq!(pim.len(), 2);
+        assert_eq!(pim.index_of("seq1"), Some(0));
+        assert_eq!(pim.values()[1], vec![50.00, 100.00]);
+        assert_eq!(
+            pim.nearest_neighbour("seq1"),
+            Some((&"seq1".to_string(), 50.00))
+        );
+        assert_eq!(pim.cluster(40.0), vec![vec!["seq1", "seq1"]]);
+    }
+
+    #[test]
+    fn finds_neighbours_and_clusters() {
+        let pim = PercentIdentityMatrix::from_clustal(PIM).unwrap();
+
+        let (nearest, identity) = pim.nearest_neighbour("Sequence4").unwrap();
+        assert_eq!(nearest, "Sequence5");
+        assert_eq!(identity, 96.00);
+        let neighbours = pim.nearest_neighbours("Sequence1", 2);
+        assert_eq!(neighbours[0].0, "Sequence3");
+        assert_eq!(neighbours.len(), 2);
+
+        assert_eq!(
+            pim.cluster(75.0),
+            vec![
+                vec!["Sequence1"],
+                vec!["Sequence2"],
+                vec!["Sequence3", "Sequence4", "Sequence5"],
+            ]
+        );
+        assert_eq!(pim.cluster(30.0).len(), 1);
+    }
+
+    #[test]
+    fn converts_and_exports() {
+        let pim = PercentIdentityMatrix::new(
+            vec!["a,b".to_string(), "c".to_string()],
+            vec![vec![100.0, 25.0], vec![25.0, 100.0]],
+        )
+        .unwrap();
+
+        assert_eq!(
+            pim.to_distance_matrix(),
+            vec![vec![0.0, 0.75], vec![0.75, 0.0]]
+        );
+        assert_eq!(
+            pim.to_tsv(),
+            "\ta,b\tc\na,b\t100.00\t25.00\nc\t25.00\t100.00\n"
+        );
+        assert_eq!(
+            pim.to_csv(),
+            ",\"a,b\",c\n\"a,b\",100.00,25.00\nc,25.00,100.00\n"
+        );
+    }
+}
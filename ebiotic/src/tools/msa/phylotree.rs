@@ -0,0 +1,613 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use crate::core::EbioticResult;
+use crate::errors::EbioticError;
+
+/// Distances closer than this are treated as equal when rooting a tree.
+const EPSILON: f64 = 1e-12;
+
+/// The `PhyloNode` struct is used to specify a node of a `PhyloTree`, with the length of the branch to its parent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhyloNode {
+    name: Option<String>,
+    branch_length: Option<f64>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// The `PhyloTree` struct is used to specify a phylogenetic tree, such as the guide tree returned by the `Clustalo`
+/// service.
+///
+/// Nodes are identified by their index in `nodes`, which lists every node before its children. Missing branch lengths
+/// count as `0` in distances.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhyloTree {
+    nodes: Vec<PhyloNode>,
+    root: usize,
+}
+
+impl PhyloNode {
+    pub fn name(&self) -> &Option<String> {
+        &self.name
+    }
+
+    pub fn branch_length(&self) -> &Option<f64> {
+        &self.branch_length
+    }
+
+    pub fn parent(&self) -> &Option<usize> {
+        &self.parent
+    }
+
+    pub fn children(&self) -> &Vec<usize> {
+        &self.children
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+impl PhyloTree {
+    /// Parse a tree in the Newick format, e.g. `((A:0.1,B:0.2):0.05,C:0.3);`.
+    pub fn from_newick(newick: &str) -> EbioticResult<PhyloTree> {
+        let mut parser = NewickParser {
+            input: newick.as_bytes(),
+            position: 0,
+            nodes: Vec::new(),
+        };
+        let root = parser.tree()?;
+        parser.skip_whitespace()?;
+        if parser.peek() != Some(b';') {
+            return parser.error("Expected ';' at the end of the tree");
+        }
+        parser.position += 1;
+        parser.skip_whitespace()?;
+        if parser.position < parser.input.len() {
+            return parser.error("Unexpected text after the end of the tree");
+        }
+        Ok(PhyloTree {
+            nodes: parser.nodes,
+            root,
+        })
+    }
+
+    /// Write the tree in the Newick format.
+    pub fn to_newick(&self) -> String {
+        let mut newick = String::new();
+        // Each node with the index of its next child to write
+        let mut stack = vec![(self.root, 0)];
+        while let Some((node, child)) = stack.pop() {
+            let n = &self.nodes[node];
+            if child < n.children.len() {
+                newick.push(if child == 0 { '(' } else { ',' });
+                stack.push((node, child + 1));
+                stack.push((n.children[child], 0));
+                continue;
+            }
+            if !n.children.is_empty() {
+                newick.push(')');
+            }
+            if let Some(name) = &n.name {
+                newick.push_str(&quote_name(name));
+            }
+            if let Some(length) = n.branch_length {
+                newick.push_str(&format!(":{}", length));
+            }
+        }
+        newick.push(';');
+        newick
+    }
+
+    pub fn nodes(&self) -> &Vec<PhyloNode> {
+        &self.nodes
+    }
+
+    pub fn root(&self) -> &usize {
+        &self.root
+    }
+
+    pub fn node(&self, node: usize) -> Option<&PhyloNode> {
+        self.nodes.get(node)
+    }
+
+    /// The leaves of the tree, from left to right.
+    pub fn leaves(&self) -> Vec<usize> {
+        self.descendants(self.root)
+            .into_iter()
+            .filter(|node| self.nodes[*node].is_leaf())
+            .collect()
+    }
+
+    /// The names of the leaves, from left to right. Unnamed leaves are left out.
+    pub fn leaf_names(&self) -> Vec<&String> {
+        self.leaves()
+            .into_iter()
+            .filter_map(|node| self.nodes[node].name.as_ref())
+            .collect()
+    }
+
+    /// The leaf with the given name.
+    pub fn find_leaf(&self, name: &str) -> Option<usize> {
+        self.leaves()
+            .into_iter()
+            .find(|node| self.nodes[*node].name.as_deref() == Some(name))
+    }
+
+    /// The sum of the branch lengths from the root to `node`, or `None` if there is no such node.
+    pub fn depth(&self, node: usize) -> Option<f64> {
+        self.nodes.get(node)?;
+        Some(self.depth_of(node))
+    }
+
+    /// The deepest node which is an ancestor of (or is) every one of `nodes`, or `None` if `nodes` is empty or holds
+    /// an index with no node.
+    pub fn common_ancestor(&self, nodes: &[usize]) -> Option<usize> {
+        if nodes.iter().any(|node| *node >= self.nodes.len()) {
+            return None;
+        }
+        let (first, rest) = nodes.split_first()?;
+        let mut common = self.ancestors(*first);
+        for node in rest {
+            let ancestors: HashSet<usize> = self.ancestors(*node).into_iter().collect();
+            common.retain(|ancestor| ancestors.contains(ancestor));
+        }
+        common.first().copied()
+    }
+
+    /// The patristic distance between two leaves, i.e. the sum of the branch lengths on the path between them.
+    pub fn patristic_distance(&self, a: &str, b: &str) -> Option<f64> {
+        Some(self.distance(self.find_leaf(a)?, self.find_leaf(b)?))
+    }
+
+    /// The patristic distances between every pair of leaves, in the order of `leaves`.
+    pub fn pairwise_distances(&self) -> Vec<Vec<f64>> {
+        let leaves = self.leaves();
+        leaves
+            .iter()
+            .map(|a| leaves.iter().map(|b| self.distance(*a, *b)).collect())
+            .collect()
+    }
+
+    /// Extract the subtree below `node`, which becomes the root of the new tree without its branch length.
+    pub fn subtree(&self, node: usize) -> Option<PhyloTree> {
+        self.nodes.get(node)?;
+        let mut nodes: Vec<PhyloNode> = Vec::new();
+        let mut stack = vec![(node, None)];
+        while let Some((node, parent)) = stack.pop() {
+            let index = nodes.len();
+            nodes.push(PhyloNode {
+                name: self.nodes[node].name.clone(),
+                branch_length: self.nodes[node].branch_length,
+                parent,
+                children: Vec::new(),
+            });
+            if let Some(parent) = parent {
+                nodes[parent].children.push(index);
+            }
+            stack.extend(
+                self.nodes[node]
+                    .children
+                    .iter()
+                    .rev()
+                    .map(|child| (*child, Some(index))),
+            );
+        }
+        nodes[0].branch_length = None;
+        Some(PhyloTree { nodes, root: 0 })
+    }
+
+    /// Root the tree at the midpoint of the longest path between two leaves. A root left with a single child is
+    /// removed, joining its branches.
+    pub fn midpoint_root(&self) -> PhyloTree {
+        let leaves = self.leaves();
+        let mut longest = (self.root, self.root, 0.0);
+        for (i, a) in leaves.iter().enumerate() {
+            for b in &leaves[i + 1..] {
+                let distance = self.distance(*a, *b);
+                if distance > longest.2 {
+                    longest = (*a, *b, distance);
+                }
+            }
+        }
+        let (a, b, distance) = longest;
+        if distance <= EPSILON {
+            return self.clone();
+        }
+
+        // Walk up from the deeper of the two leaves, which holds the midpoint on its side of the common ancestor
+        let mut node = if self.depth_of(a) >= self.depth_of(b) {
+            a
+        } else {
+            b
+        };
+        let mut remaining = distance / 2.0;
+        loop {
+            let length = self.nodes[node].branch_length.unwrap_or(0.0);
+            if remaining <= EPSILON {
+                return self.reroot(node, None);
+            }
+            if remaining < length - EPSILON {
+                return self.reroot(node, Some(remaining));
+            }
+            remaining -= length;
+            node = match self.nodes[node].parent {
+                Some(parent) => parent,
+                None => return self.clone(),
+            };
+        }
+    }
+
+    /// The patristic distance between two nodes.
+    fn distance(&self, a: usize, b: usize) -> f64 {
+        match self.common_ancestor(&[a, b]) {
+            Some(ancestor) => self.depth_of(a) + self.depth_of(b) - 2.0 * self.depth_of(ancestor),
+            None => 0.0,
+        }
+    }
+
+    fn depth_of(&self, node: usize) -> f64 {
+        self.ancestors(node)
+            .iter()
+            .map(|n| self.nodes[*n].branch_length.unwrap_or(0.0))
+            .sum()
+    }
+
+    /// `node` and its ancestors, from `node` up to the root.
+    fn ancestors(&self, node: usize) -> Vec<usize> {
+        let mut ancestors = vec![node];
+        while let Some(parent) = self.nodes[*ancestors.last().unwrap()].parent {
+            ancestors.push(parent);
+        }
+        ancestors
+    }
+
+    /// `node` and its descendants, every node before its children.
+    fn descendants(&self, node: usize) -> Vec<usize> {
+        let mut descendants = Vec::new();
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            descendants.push(node);
+            stack.extend(self.nodes[node].children.iter().rev());
+        }
+        descendants
+    }
+
+    /// Root the tree at `node`, or, with an `offset`, on the branch above `node` at `offset` from it.
+    fn reroot(&self, node: usize, offset: Option<f64>) -> PhyloTree {
+        // The tree as undirected edges, with the branch lengths
+        let mut neighbours: Vec<Vec<(usize, f64)>> = vec![Vec::new(); self.nodes.len()];
+        for (index, n) in self.nodes.iter().enumerate() {
+            if let Some(parent) = n.parent {
+                let length = n.branch_length.unwrap_or(0.0);
+                neighbours[index].push((parent, length));
+                neighbours[parent].push((index, length));
+            }
+        }
+
+        let mut nodes = vec![PhyloNode {
+            name: None,
+            branch_length: None,
+            parent: None,
+            children: Vec::new(),
+        }];
+        match (offset, self.nodes[node].parent) {
+            (Some(offset), Some(parent)) => {
+                let length = self.nodes[node].branch_length.unwrap_or(0.0);
+                self.copy_rerooted(&neighbours, node, parent, 0, offset, &mut nodes);
+                self.copy_rerooted(&neighbours, parent, node, 0, length - offset, &mut nodes);
+            }
+            _ => {
+                nodes[0].name = self.nodes[node].name.clone();
+                for (neighbour, length) in &neighbours[node] {
+                    self.copy_rerooted(&neighbours, *neighbour, node, 0, *length, &mut nodes);
+                }
+            }
+        }
+        PhyloTree { nodes, root: 0 }
+    }
+
+    /// Copy `node`, reached from `from`, and the nodes beyond it below `parent` in the rerooted tree. The old root is
+    /// removed if it is left with a single child.
+    fn copy_rerooted(
+        &self,
+        neighbours: &[Vec<(usize, f64)>],
+        node: usize,
+        from: usize,
+        parent: usize,
+        length: f64,
+        nodes: &mut Vec<PhyloNode>,
+    ) {
+        let mut stack = vec![(node, from, parent, length)];
+        while let Some((node, from, parent, length)) = stack.pop() {
+            let next: Vec<(usize, f64)> = neighbours[node]
+                .iter()
+                .filter(|(neighbour, _)| *neighbour != from)
+                .copied()
+                .collect();
+            if node == self.root && next.len() == 1 && self.nodes[node].name.is_none() {
+                let (child, child_length) = next[0];
+                stack.push((child, node, parent, length + child_length));
+                continue;
+            }
+
+            let index = nodes.len();
+            nodes.push(PhyloNode {
+                name: self.nodes[node].name.clone(),
+                branch_length: Some(length),
+                parent: Some(parent),
+                children: Vec::new(),
+            });
+            nodes[parent].children.push(index);
+            stack.extend(
+                next.into_iter()
+                    .rev()
+                    .map(|(child, child_length)| (child, node, index, child_length)),
+            );
+        }
+    }
+}
+
+impl Display for PhyloTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_newick())
+    }
+}
+
+struct NewickParser<'a> {
+    input: &'a [u8],
+    position: usize,
+    nodes: Vec<PhyloNode>,
+}
+
+impl NewickParser<'_> {
+    /// Parse the nodes of the tree, returning the index of its root. The nesting is followed with the `parent` of
+    /// each node rather than by recursion, so that deep trees cannot overflow the stack.
+    fn tree(&mut self) -> EbioticResult<usize> {
+        let mut current = self.open(None);
+        loop {
+            self.skip_whitespace()?;
+            while self.peek() == Some(b'(') {
+                self.position += 1;
+                current = self.open(Some(current));
+                self.skip_whitespace()?;
+            }
+            // Label the node and climb back up until a sibling follows
+            loop {
+                self.label(current)?;
+                let parent = match self.nodes[current].parent {
+                    Some(parent) => parent,
+                    None => return Ok(current),
+                };
+                self.skip_whitespace()?;
+                match self.peek() {
+                    Some(b',') => {
+                        self.position += 1;
+                        current = self.open(Some(parent));
+                        break;
+                    }
+                    Some(b')') => {
+                        self.position += 1;
+                        current = parent;
+                    }
+                    _ => return self.error("Expected ',' or ')'"),
+                }
+            }
+        }
+    }
+
+    /// Add a node below `parent`, returning its index.
+    fn open(&mut self, parent: Option<usize>) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(PhyloNode {
+            name: None,
+            branch_length: None,
+            parent,
+            children: Vec::new(),
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(index);
+        }
+        index
+    }
+
+    /// Read the name and branch length following a node.
+    fn label(&mut self, index: usize) -> EbioticResult<()> {
+        self.nodes[index].name = self.name()?;
+        self.skip_whitespace()?;
+        if self.peek() == Some(b':') {
+            self.position += 1;
+            self.skip_whitespace()?;
+            let start = self.position;
+            while self
+                .peek()
+                .is_some_and(|c| c.is_ascii_digit() || b"+-.eE".contains(&c))
+            {
+                self.position += 1;
+            }
+            let length = std::str::from_utf8(&self.input[start..self.position])
+                .ok()
+                .and_then(|length| length.parse().ok());
+            match length {
+                Some(length) => self.nodes[index].branch_length = Some(length),
+                None => return self.error("Invalid branch length"),
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> EbioticResult<Option<String>> {
+        self.skip_whitespace()?;
+        if self.peek() == Some(b'\'') {
+            let mut name = Vec::new();
+            self.position += 1;
+            loop {
+                match self.peek() {
+                    // A doubled quote stands for a quote within the name
+                    Some(b'\'') if self.input.get(self.position + 1) == Some(&b'\'') => {
+                        name.push(b'\'');
+                        self.position += 2;
+                    }
+                    Some(b'\'') => {
+                        self.position += 1;
+                        break;
+                    }
+                    Some(c) => {
+                        name.push(c);
+                        self.position += 1;
+                    }
+                    None => return self.error("Unterminated quoted name"),
+                }
+            }
+            return Ok(Some(String::from_utf8_lossy(&name).into_owned()));
+        }
+
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_ascii_whitespace() && !b"(),:;[".contains(&c))
+        {
+            self.position += 1;
+        }
+        let name = String::from_utf8_lossy(&self.input[start..self.position]).into_owned();
+        Ok((!name.is_empty()).then_some(name))
+    }
+
+    /// Skip whitespace and `[...]` comments.
+    fn skip_whitespace(&mut self) -> EbioticResult<()> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_ascii_whitespace() => self.position += 1,
+                Some(b'[') => {
+                    while self.peek().is_some_and(|c| c != b']') {
+                        self.position += 1;
+                    }
+                    if self.peek().is_none() {
+                        return self.error("Unterminated comment");
+                    }
+                    self.position += 1;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).copied()
+    }
+
+    fn error<T>(&self, message: &str) -> EbioticResult<T> {
+        Err(EbioticError::MalformedResponse(format!(
+            "{} at position {} of the Newick tree",
+            message, self.position
+        )))
+    }
+}
+
+fn quote_name(name: &str) -> String {
+    if name
+        .chars()
+        .any(|c| c.is_whitespace() || "(),:;[]'".contains(c))
+    {
+        format!("'{}'", name.replace('\'', "''"))
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> PhyloTree {
+        PhyloTree::from_newick("((A:0.1,B:0.2)AB:0.3,C:0.4,D:1.0);").unwrap()
+    }
+
+    #[test]
+    fn parses_and_writes_newick() {
+        let tree = tree();
+
+        assert_eq!(tree.nodes().len(), 6);
+        assert_eq!(tree.leaf_names(), vec!["A", "B", "C", "D"]);
+        let ab = tree.common_ancestor(&[1, 2]).unwrap();
+        assert_eq!(tree.node(ab).unwrap().name().as_deref(), Some("AB"));
+        assert_eq!(tree.to_newick(), "((A:0.1,B:0.2)AB:0.3,C:0.4,D:1);");
+
+        let clustal =
+            PhyloTree::from_newick("(\nseq1:0.35385,\nseq2:0.33462,\nseq3:0.32538);\n").unwrap();
+        assert_eq!(clustal.leaves().len(), 3);
+
+        let quoted = PhyloTree::from_newick("('Homo sapiens':1,'O''Brien':2)[comment];").unwrap();
+        assert_eq!(quoted.leaf_names(), vec!["Homo sapiens", "O'Brien"]);
+        assert_eq!(quoted.to_newick(), "('Homo sapiens':1,'O''Brien':2);");
+
+        assert!(PhyloTree::from_newick("(A,B").is_err());
+        assert!(PhyloTree::from_newick("(A:x,B);").is_err());
+        for unterminated in ["[", "(A,B)[", "A:["] {
+            assert!(PhyloTree::from_newick(unterminated).is_err());
+        }
+    }
+
+    #[test]
+    fn computes_patristic_distances() {
+        let tree = tree();
+
+        assert!((tree.patristic_distance("A", "B").unwrap() - 0.3).abs() < 1e-9);
+        assert!((tree.patristic_distance("A", "D").unwrap() - 1.4).abs() < 1e-9);
+        assert!(tree.patristic_distance("A", "E").is_none());
+
+        let distances = tree.pairwise_distances();
+        assert_eq!(distances.len(), 4);
+        assert_eq!(distances[2][2], 0.0);
+        assert!((distances[2][1] - 0.9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn extracts_subtrees() {
+        let tree = tree();
+        let ab =
+            tree.common_ancestor(&[tree.find_leaf("A").unwrap(), tree.find_leaf("B").unwrap()]);
+
+        let subtree = tree.subtree(ab.unwrap()).unwrap();
+
+        assert_eq!(subtree.to_newick(), "(A:0.1,B:0.2)AB;");
+        assert_eq!(subtree.node(1).unwrap().parent(), &Some(0));
+        assert!(tree.subtree(6).is_none());
+        assert_eq!(tree.depth(6), None);
+        assert_eq!(tree.common_ancestor(&[1, 6]), None);
+    }
+
+    #[test]
+    fn roots_at_the_midpoint() {
+        let tree = tree();
+
+        // The longest path is B to D (1.5), so the midpoint is 0.75 from D on its branch
+        let rooted = tree.midpoint_root();
+
+        assert_eq!(rooted.node(0).unwrap().children().len(), 2);
+        assert_eq!(rooted.leaf_names().len(), 4);
+        assert!((rooted.depth(rooted.find_leaf("D").unwrap()).unwrap() - 0.75).abs() < 1e-9);
+        assert!((rooted.depth(rooted.find_leaf("B").unwrap()).unwrap() - 0.75).abs() < 1e-9);
+        for (a, b) in [("A", "C"), ("B", "D"), ("A", "B")] {
+            let before = tree.patristic_distance(a, b).unwrap();
+            let after = rooted.patristic_distance(a, b).unwrap();
+            assert!((before - after).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn handles_deeply_nested_trees() {
+        let depth = 100_000;
+        let newick = format!("({}A:1{}:1,B:1);", "(".repeat(depth), ")".repeat(depth));
+
+        let tree = PhyloTree::from_newick(&newick).unwrap();
+        assert_eq!(tree.nodes().len(), depth + 3);
+        assert_eq!(tree.to_newick(), newick);
+        assert_eq!(tree.subtree(1).unwrap().nodes().len(), depth + 1);
+
+        let rooted = tree.midpoint_root();
+        assert!((rooted.patristic_distance("A", "B").unwrap() - 3.0).abs() < 1e-9);
+        assert!((rooted.depth(rooted.find_leaf("A").unwrap()).unwrap() - 1.5).abs() < 1e-9);
+    }
+}
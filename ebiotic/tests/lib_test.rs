@@ -1,7 +1,6 @@
 use ctor::ctor;
 use ebiotic::data::*;
 use ebiotic::tools::*;
-use env_logger;
 
 #[ctor]
 fn init() {
@@ -0,0 +1,703 @@
+use ebiotic::data::*;
+use ebiotic::tools::*;
+
+fn fixture_client(name: &str) -> EbioticClient {
+    let path = format!(
+        "{}/tests/fixtures/{}.json",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    EbioticClient::replay(Fixtures::from_file(path).unwrap())
+}
+
+/// The three sequences aligned in the `clustalo` fixture.
+fn clustalo_records() -> Vec<Record> {
+    vec![
+        Record::with_attrs(
+            "seq1",
+            None,
+            b"AGCTTGAACGTTAGCGGAACGTAAGCGAGATCCGTAGGCTAACTCGTACGTA",
+        ),
+        Record::with_attrs(
+            "seq2",
+            None,
+            b"TACGATGCAAATCGTGCACGGTCCAGTACGATCCGATGCTAAGTCCGATCGA",
+        ),
+        Record::with_attrs(
+            "seq3",
+            None,
+            b"GCTAGTCCGATGCGTACGATCGTACGATGCTAGCTAGCTAGCTAGCTAGCTA",
+        ),
+    ]
+}
+
+#[tokio::test(start_paused = true)]
+async fn blast_run_replays_fixture() {
+    let blast = Blast::new(
+        fixture_client("blast"),
+        BLAST_ENDPOINT.to_string(),
+        BlastProgram::Blastp,
+        BlastDatabase::Nr,
+        Some(BlastMatrix::Blosum62),
+        10,
+        "".to_string(),
+        "".to_string(),
+    );
+    let query = "MAKQVQKARKLAEQAERYDDMAAAMKAVTEQGHELSNEERNLLSVAYKNVVGARRSSWRVISSIEQKTERNEKKQQMGKEYREKIEAELQDICNDVLELLDKYLIPNATQPESKVFYLKMKGDYFRYLSEVASGDNKQTTVSNSQQAYQEAFEISKKEMQPTHPIRLGLALNFSVFYYEILNSPDRACRLAKAAFDDASLAKDAESEKNPEEIAWYQSITQ";
+
+    let result = blast.run(query.to_string()).await.unwrap();
+
+    assert_eq!(result.query_len(), &221);
+    assert_eq!(result.hits().len(), 3);
+    assert_eq!(
+        result.hits()[0].description()[0].accession(),
+        "XP_023570734"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn clustalo_run_replays_fixture() {
+    let clustalo = Clustalo::new(
+        fixture_client("clustalo"),
+        format!("{}{}", EBI_TOOLS_ENDPOINT, "clustalo/"),
+        "test@example.com".to_string(),
+    );
+    let records = clustalo_records();
+
+    let result = clustalo.run(records).await.unwrap();
+
+    assert!(result
+        .alignment()
+        .starts_with("CLUSTAL O(1.2.4) multiple sequence alignment"));
+    assert_eq!(result.pim().len(), 3);
+    assert_eq!(result.pim().get("seq1", "seq3"), Some(30.77));
+    assert!(result.phylotree().contains("seq3:0.32538"));
+
+    let alignment = result.multiple_alignment().unwrap();
+    assert_eq!(alignment.len(), 3);
+    assert_eq!(alignment.width(), 52);
+
+    let tree = result.parsed_tree().unwrap();
+    assert_eq!(tree.leaf_names(), vec!["seq1", "seq2", "seq3"]);
+}
+
+#[tokio::test]
+async fn dbfetch_run_replays_fixture() {
+    let dbfetch = Dbfetch::new(
+        fixture_client("dbfetch"),
+        DbfetchDbs::EnaSequence,
+        DataReturnFormats::Fasta,
+        DbfetchStyle::Raw,
+    );
+    let ids = AccessionIds::new(vec!["M10051".to_string(), "K00650".to_string()]);
+
+    let records = dbfetch.run(ids).await.unwrap().into_records().unwrap();
+
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].id(), "ENA|M10051|M10051.1");
+    assert_eq!(records[1].id(), "ENA|K00650|K00650.1");
+}
+
+#[tokio::test]
+async fn dbfetch_run_decodes_no_entries_error() {
+    let dbfetch = Dbfetch::new(
+        fixture_client("dbfetch"),
+        DbfetchDbs::EnaSequence,
+        DataReturnFormats::Fasta,
+        DbfetchStyle::Raw,
+    );
+    let ids = AccessionIds::new(vec!["NOTANID".to_string()]);
+
+    let result = dbfetch.run(ids).await;
+
+    assert!(matches!(
+        result,
+        Err(EbioticError::DbfetchError { code: 12, .. })
+    ));
+}
+
+#[tokio::test]
+async fn ebi_search_query_replays_fixture() {
+    let ebi_search = EbiSearch::new(
+        fixture_client("ebisearch"),
+        EbiSearchDomains::Uniprot,
+        DataReturnFormats::Json,
+    );
+
+    let result = ebi_search.query("P53".to_string(), None).await.unwrap();
+
+    assert!(result.data().contains("P04637"));
+}
+
+#[tokio::test]
+async fn ebi_search_reports_http_errors() {
+    let ebi_search = EbiSearch::new(
+        fixture_client("ebisearch"),
+        EbiSearchDomains::Uniprot,
+        DataReturnFormats::Json,
+    );
+
+    let result = ebi_search.query("".to_string(), None).await;
+
+    assert!(matches!(
+        result,
+        Err(EbioticError::HttpStatus { status: 400, .. })
+    ));
+}
+
+/// An instrumented client that counts the requests made through it.
+#[derive(Clone)]
+struct CountingClient {
+    inner: EbioticClient,
+    requests: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl EbioticHttpClient for CountingClient {
+    async fn post_form(
+        &self,
+        endpoint: &str,
+        body: &[(&str, &str)],
+    ) -> Result<String, EbioticError> {
+        self.requests
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        self.inner.post_form(endpoint, body).await
+    }
+
+    async fn get(&self, endpoint: &str) -> Result<String, EbioticError> {
+        self.requests
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        self.inner.get(endpoint).await
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn services_accept_custom_clients() {
+    let client = CountingClient {
+        inner: fixture_client("clustalo"),
+        requests: Default::default(),
+    };
+    let clustalo = Clustalo::new(
+        client.clone(),
+        format!("{}{}", EBI_TOOLS_ENDPOINT, "clustalo/"),
+        "test@example.com".to_string(),
+    );
+    let records = clustalo_records();
+
+    clustalo.run(records).await.unwrap();
+
+    // One submission, three status polls and three result fetches.
+    assert_eq!(client.requests.load(std::sync::atomic::Ordering::SeqCst), 7);
+}
+
+#[tokio::test]
+async fn boxed_client_is_shared_between_services() {
+    let path = |name: &str| {
+        format!(
+            "{}/tests/fixtures/{}.json",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        )
+    };
+    let fixtures = Fixtures::from_file(path("dbfetch")).unwrap();
+    let search = std::fs::read_to_string(path("ebisearch")).unwrap();
+    for fixture in serde_json::from_str::<Vec<Fixture>>(&search).unwrap() {
+        fixtures.push(fixture);
+    }
+    let client = BoxedHttpClient::new(EbioticClient::replay(fixtures));
+
+    let dbfetch = Dbfetch::new(
+        client.clone(),
+        DbfetchDbs::EnaSequence,
+        DataReturnFormats::Fasta,
+        DbfetchStyle::Raw,
+    );
+    let ebi_search = EbiSearch::new(client, EbiSearchDomains::Uniprot, DataReturnFormats::Json);
+
+    let ids = AccessionIds::new(vec!["M10051".to_string(), "K00650".to_string()]);
+    assert!(dbfetch.run(ids).await.is_ok());
+    assert!(ebi_search.query("P53".to_string(), None).await.is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn clustalo_job_resumes_from_serialised_handle() {
+    let client = fixture_client("clustalo");
+    let endpoint = format!("{}{}", EBI_TOOLS_ENDPOINT, "clustalo/");
+    let records = clustalo_records();
+
+    let clustalo = Clustalo::new(
+        client.clone(),
+        endpoint.clone(),
+        "test@example.com".to_string(),
+    );
+    let saved = clustalo.submit(records).await.unwrap().to_json().unwrap();
+    drop(clustalo);
+
+    // e.g. after a restart, with the parameters since changed
+    let mut clustalo = Clustalo::new(client, endpoint, "test@example.com".to_string());
+    let mut params = ClustaloParams::new();
+    params.set_outfmt(Some(ClustaloOutputFormat::Fasta));
+    clustalo.set_params(params);
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    clustalo.set_observer(sender);
+    let handle = JobHandle::from_json(&saved).unwrap();
+    assert_eq!(
+        handle.job_id(),
+        "clustalo-R20241017-101530-0412-71628347-p1m"
+    );
+    handle.wait(&clustalo).await.unwrap();
+    let result = handle.fetch_result(&clustalo).await.unwrap();
+
+    assert_eq!(handle.output_format().as_deref(), Some("clustal_num"));
+    assert_eq!(result.format(), &ClustaloOutputFormat::ClustalNum);
+    assert_eq!(result.pim().len(), 3);
+    assert!(matches!(
+        handle.cancel(&clustalo).await,
+        Err(EbioticError::CancelNotSupported(_))
+    ));
+    drop(clustalo);
+    let mut completed = Vec::new();
+    while let Some(event) = receiver.recv().await {
+        if let JobEvent::Completed { job_id } = event {
+            completed.push(job_id);
+        }
+    }
+    assert_eq!(completed, vec![handle.job_id().clone()]);
+}
+
+#[tokio::test]
+async fn blast_job_can_be_cancelled() {
+    let client = EbioticClient::replay(Fixtures::new(vec![Fixture::new(
+        HttpRequest::post(BLAST_ENDPOINT, &[("CMD", "Delete"), ("RID", "9T2SHSH1016")]),
+        HttpResponse::new(200, "".to_string()),
+    )]));
+    let blast = Blast::new(
+        client,
+        BLAST_ENDPOINT.to_string(),
+        BlastProgram::Blastp,
+        BlastDatabase::Nr,
+        Some(BlastMatrix::Blosum62),
+        10,
+        "".to_string(),
+        "".to_string(),
+    );
+    let handle = JobHandle::new(
+        "blast".to_string(),
+        "9T2SHSH1016".to_string(),
+        BLAST_ENDPOINT.to_string(),
+        None,
+    );
+
+    handle.cancel(&blast).await.unwrap();
+
+    let clustalo = Clustalo::default();
+    assert!(matches!(
+        handle.status(&clustalo).await,
+        Err(EbioticError::InvalidJobHandle(_))
+    ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn clustalo_wait_times_out_when_job_is_stuck() {
+    let endpoint = format!("{}{}", EBI_TOOLS_ENDPOINT, "clustalo/");
+    let status = HttpRequest::get(&format!("{}status/clustalo-R1", endpoint));
+    let client = EbioticClient::replay(Fixtures::new(
+        (0..3)
+            .map(|_| {
+                Fixture::new(
+                    status.clone(),
+                    HttpResponse::new(200, "RUNNING".to_string()),
+                )
+            })
+            .collect(),
+    ));
+    let mut clustalo = Clustalo::new(client, endpoint.clone(), "test@example.com".to_string());
+    let mut policy = PollPolicy::job_dispatcher();
+    policy.set_max_polls(Some(3));
+    clustalo.set_poll_policy(policy);
+    let failures = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = failures.clone();
+    clustalo.set_observer(move |event: &JobEvent| {
+        if let JobEvent::Failed { job_id, .. } = event {
+            seen.lock().unwrap().push(job_id.clone());
+        }
+    });
+    let handle = JobHandle::new(
+        "clustalo".to_string(),
+        "clustalo-R1".to_string(),
+        endpoint,
+        None,
+    );
+
+    let result = handle.wait(&clustalo).await;
+
+    assert!(matches!(
+        result,
+        Err(EbioticError::PollTimeout { polls: 3, .. })
+    ));
+    assert_eq!(
+        *failures.lock().unwrap(),
+        vec![Some("clustalo-R1".to_string())]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancelled_blast_search_is_deleted() {
+    let client = EbioticClient::replay(Fixtures::new(vec![
+        Fixture::new(
+            HttpRequest::post(
+                BLAST_ENDPOINT,
+                &[
+                    ("CMD", "Get"),
+                    ("FORMAT_OBJECT", "SearchInfo"),
+                    ("RID", "9T2SHSH1016"),
+                ],
+            ),
+            HttpResponse::new(200, "Status=WAITING".to_string()),
+        ),
+        Fixture::new(
+            HttpRequest::post(BLAST_ENDPOINT, &[("CMD", "Delete"), ("RID", "9T2SHSH1016")]),
+            HttpResponse::new(200, "".to_string()),
+        ),
+    ]));
+    let mut blast = Blast::default();
+    blast.set_client(client);
+    let handle = JobHandle::new(
+        "blast".to_string(),
+        "9T2SHSH1016".to_string(),
+        BLAST_ENDPOINT.to_string(),
+        None,
+    );
+    let token = CancellationToken::new();
+
+    let abandon = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+        abandon.cancel();
+    });
+    let result = handle.wait_with_cancel(&blast, &token).await;
+
+    // The Delete fixture is only replayed if the search was discarded on the server.
+    assert!(matches!(result, Err(EbioticError::Cancelled(rid)) if rid == "9T2SHSH1016"));
+}
+
+/// A client replaying a two query BLAST search, for which NCBI reports the queries as `Query_1` and `Query_2`.
+fn blast_multi_query_client() -> EbioticClient {
+    let report = |query_id: &str, title: &str, len: u32| {
+        format!(
+            r#"{{"report": {{"results": {{"search": {{"query_id": "{}", "query_title": "{}", "query_len": {}, "hits": [], "message": "No hits found"}}}}}}}}"#,
+            query_id, title, len
+        )
+    };
+    EbioticClient::replay(Fixtures::new(vec![
+        Fixture::new(
+            HttpRequest::post(
+                BLAST_ENDPOINT,
+                &[
+                    ("CMD", "Put"),
+                    ("PROGRAM", "blastp"),
+                    ("DATABASE", "nr"),
+                    ("MATRIX", "BLOSUM62"),
+                    ("HITLIST_SIZE", "10"),
+                    ("EMAIL", ""),
+                    ("TOOL", ""),
+                    ("QUERY", ">P1 first protein\nMKVLAA\n>P2\nMSTNPK\n"),
+                ],
+            ),
+            HttpResponse::new(200, "    RID = 9T2SHSH1016\n    RTOE = 20\n".to_string()),
+        ),
+        Fixture::new(
+            HttpRequest::post(
+                BLAST_ENDPOINT,
+                &[
+                    ("CMD", "Get"),
+                    ("FORMAT_OBJECT", "SearchInfo"),
+                    ("RID", "9T2SHSH1016"),
+                ],
+            ),
+            HttpResponse::new(200, "Status=READY".to_string()),
+        ),
+        Fixture::new(
+            HttpRequest::post(
+                BLAST_ENDPOINT,
+                &[
+                    ("CMD", "Get"),
+                    ("FORMAT_TYPE", "JSON2_S"),
+                    ("RID", "9T2SHSH1016"),
+                ],
+            ),
+            HttpResponse::new(
+                200,
+                format!(
+                    r#"{{"BlastOutput2": [{}, {}]}}"#,
+                    report("Query_1", "P1 first protein", 6),
+                    report("Query_2", "P2", 6)
+                ),
+            ),
+        ),
+    ]))
+}
+
+#[tokio::test(start_paused = true)]
+async fn blast_multi_query_returns_one_result_per_record() {
+    let client = blast_multi_query_client();
+    let mut blast = Blast::default();
+    blast.set_client(client);
+    let records = vec![
+        Record::with_attrs("P1", Some("first protein"), b"MKVLAA"),
+        Record::with_attrs("P2", None, b"MSTNPK"),
+    ];
+
+    let results = blast.run_records(records).await.unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].query_id(), "P1");
+    assert_eq!(results[1].query_id(), "P2");
+    assert_eq!(results[1].message().as_deref(), Some("No hits found"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn blast_resumed_multi_query_restores_record_ids() {
+    let mut blast = Blast::default();
+    blast.set_client(blast_multi_query_client());
+    let records = vec![
+        Record::with_attrs("P1", Some("first protein"), b"MKVLAA"),
+        Record::with_attrs("P2", None, b"MSTNPK"),
+    ];
+
+    let json = blast
+        .submit_records(records)
+        .await
+        .unwrap()
+        .to_json()
+        .unwrap();
+    let handle = JobHandle::from_json(&json).unwrap();
+    handle.wait(&blast).await.unwrap();
+    let results = blast.fetch_results(&handle).await.unwrap();
+
+    assert_eq!(
+        handle.query_ids(),
+        &vec!["P1".to_string(), "P2".to_string()]
+    );
+    assert_eq!(results[0].query_id(), "P1");
+    assert_eq!(results[1].query_id(), "P2");
+}
+
+#[tokio::test(start_paused = true)]
+async fn clustalo_params_are_sent_and_select_the_alignment() {
+    let endpoint = format!("{}{}", EBI_TOOLS_ENDPOINT, "clustalo/");
+    let job = "clustalo-R20241017-101530-0412-71628347-p1m";
+    let result =
+        |artifact: &str| HttpRequest::get(&format!("{}result/{}/{}", endpoint, job, artifact));
+    let client = EbioticClient::replay(Fixtures::new(vec![
+        Fixture::new(
+            HttpRequest::post(
+                &format!("{}run/", endpoint),
+                &[
+                    ("email", "test@example.com"),
+                    ("stype", "dna"),
+                    ("iterations", "2"),
+                    ("outfmt", "fa"),
+                    ("order", "input"),
+                    ("sequence", ">seq1\nACGTACGT\n>seq2\nACGAACGT"),
+                ],
+            ),
+            HttpResponse::new(200, job.to_string()),
+        ),
+        Fixture::new(
+            HttpRequest::get(&format!("{}status/{}", endpoint, job)),
+            HttpResponse::new(200, "FINISHED".to_string()),
+        ),
+        Fixture::new(
+            result("aln-fa"),
+            HttpResponse::new(200, ">seq1\nACGTACGT\n>seq2\nACGAACGT\n".to_string()),
+        ),
+        Fixture::new(
+            result("pim"),
+            HttpResponse::new(
+                200,
+                "     1: seq1    100.00   87.50\n     2: seq2     87.50  100.00\n".to_string(),
+            ),
+        ),
+        Fixture::new(
+            result("phylotree"),
+            HttpResponse::new(200, "(\nseq1:0.0625,\nseq2:0.0625);\n".to_string()),
+        ),
+    ]));
+    let mut clustalo = Clustalo::new(client, endpoint.clone(), "test@example.com".to_string());
+    let mut params = ClustaloParams::new();
+    params.set_stype(Some(ClustaloSequenceType::Dna));
+    params.set_iterations(Some(2));
+    params.set_outfmt(Some(ClustaloOutputFormat::Fasta));
+    params.set_order(Some(ClustaloOrder::Input));
+    clustalo.set_params(params);
+    let records = vec![
+        Record::with_attrs("seq1", None, b"ACGTACGT"),
+        Record::with_attrs("seq2", None, b"ACGAACGT"),
+    ];
+
+    let result = clustalo.run(records.clone()).await.unwrap();
+
+    assert_eq!(result.format(), &ClustaloOutputFormat::Fasta);
+    assert!(result.alignment().starts_with(">seq1"));
+    assert_eq!(result.multiple_alignment().unwrap().consensus(), "ACGTACGT");
+
+    let mut params = ClustaloParams::new();
+    params.set_iterations(Some(6));
+    clustalo.set_params(params);
+    assert!(matches!(
+        clustalo.run(records).await,
+        Err(EbioticError::InvalidClustaloParameters(_))
+    ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn clustalo_run_reports_lifecycle_events() {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut clustalo = Clustalo::new(
+        fixture_client("clustalo"),
+        format!("{}{}", EBI_TOOLS_ENDPOINT, "clustalo/"),
+        "test@example.com".to_string(),
+    );
+    clustalo.set_observer(sender);
+    let records = clustalo_records();
+
+    clustalo.run(records).await.unwrap();
+    drop(clustalo);
+
+    let mut events = Vec::new();
+    while let Some(event) = receiver.recv().await {
+        events.push(event);
+    }
+    let job_id = "clustalo-R20241017-101530-0412-71628347-p1m".to_string();
+    assert_eq!(
+        events[0],
+        JobEvent::Submitted {
+            job_id: job_id.clone()
+        }
+    );
+    let polled: Vec<JobState> = events
+        .iter()
+        .filter_map(|event| match event {
+            JobEvent::Polled { status, .. } => Some(*status),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        polled,
+        vec![JobState::Running, JobState::Running, JobState::Finished]
+    );
+    let artifacts: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            JobEvent::Fetching { artifact, .. } => Some(artifact.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(artifacts, vec!["aln-clustal_num", "pim", "phylotree"]);
+    assert_eq!(events.last(), Some(&JobEvent::Completed { job_id }));
+}
+
+#[tokio::test]
+async fn failed_submission_is_reported_to_observer() {
+    let failures = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut blast = Blast::default();
+    blast.set_client(EbioticClient::replay(Fixtures::default()));
+    let seen = failures.clone();
+    blast.set_observer(move |event: &JobEvent| {
+        if let JobEvent::Failed { job_id, .. } = event {
+            seen.lock().unwrap().push(job_id.clone());
+        }
+    });
+
+    assert!(blast.run("MAKQVQK".to_string()).await.is_err());
+    assert_eq!(*failures.lock().unwrap(), vec![None]);
+}
+
+#[tokio::test]
+async fn dbfetch_errors_are_not_served_from_cache() {
+    let dir = std::env::temp_dir().join(format!("ebiotic-offline-evict-{}", std::process::id()));
+    let cache = ResponseCache::new(&dir).unwrap();
+    cache.clear().unwrap();
+    let mut client = fixture_client("dbfetch");
+    client.set_cache(cache.clone());
+    let dbfetch = Dbfetch::new(
+        client,
+        DbfetchDbs::EnaSequence,
+        DataReturnFormats::Fasta,
+        DbfetchStyle::Raw,
+    );
+    let ids = AccessionIds::new(vec!["NOTANID".to_string()]);
+
+    assert!(dbfetch.run(ids.clone()).await.is_err());
+
+    let mut offline = cache.clone();
+    offline.set_mode(CacheMode::CacheOnly);
+    let mut client = EbioticClient::default();
+    client.set_cache(offline);
+    let dbfetch = Dbfetch::new(
+        client,
+        DbfetchDbs::EnaSequence,
+        DataReturnFormats::Fasta,
+        DbfetchStyle::Raw,
+    );
+    assert!(matches!(
+        dbfetch.run(ids).await,
+        Err(EbioticError::CacheMiss(_))
+    ));
+
+    cache.clear().unwrap();
+}
+
+#[tokio::test]
+async fn dbfetch_responses_are_served_from_cache() {
+    let dir = std::env::temp_dir().join(format!("ebiotic-offline-cache-{}", std::process::id()));
+    let cache = ResponseCache::new(&dir).unwrap();
+    cache.clear().unwrap();
+    let mut client = fixture_client("dbfetch");
+    client.set_cache(cache.clone());
+    let dbfetch = Dbfetch::new(
+        client,
+        DbfetchDbs::EnaSequence,
+        DataReturnFormats::Fasta,
+        DbfetchStyle::Raw,
+    );
+    let ids = AccessionIds::new(vec!["M10051".to_string(), "K00650".to_string()]);
+
+    // The fixture is only replayed once, so the second run must come from the cache.
+    let first = dbfetch
+        .run(ids.clone())
+        .await
+        .unwrap()
+        .into_records()
+        .unwrap();
+    let second = dbfetch
+        .run(ids.clone())
+        .await
+        .unwrap()
+        .into_records()
+        .unwrap();
+    assert_eq!(first.len(), second.len());
+    assert_eq!(*cache.stats().hits(), 1);
+    assert_eq!(*cache.stats().misses(), 1);
+
+    // A cache-only client never uses the network.
+    let mut offline = cache.clone();
+    offline.set_mode(CacheMode::CacheOnly);
+    let mut client = EbioticClient::default();
+    client.set_cache(offline);
+    let dbfetch = Dbfetch::new(
+        client,
+        DbfetchDbs::EnaSequence,
+        DataReturnFormats::Fasta,
+        DbfetchStyle::Raw,
+    );
+    assert!(dbfetch.run(ids).await.is_ok());
+    assert!(matches!(
+        dbfetch
+            .run(AccessionIds::new(vec!["X00000".to_string()]))
+            .await,
+        Err(EbioticError::CacheMiss(_))
+    ));
+
+    cache.clear().unwrap();
+}